@@ -1,31 +1,41 @@
-use backprop;
 use std::collections::HashMap;
 use backprop::tensor_backends::{NdArray, TensorBackend};
+use backprop::data::{DataLoader, DataLoaderConfig, TensorDataset};
 use backprop::layers::LinearLayer;
+use backprop::losses::{mse_loss, Reduction};
+use backprop::module::Module;
+use backprop::optim::{Optimizer, Sgd, SgdConfig};
+use backprop::random;
 use backprop::ComputationRecord;
-use backprop::ops::*;
 
 pub fn main() {
+    random::manual_seed(0);
 
-    let mut parameter_store: HashMap<String, NdArray> = HashMap::new();
-
-    for _i in 0..1000 {
-        let rec: ComputationRecord<NdArray> = ComputationRecord::new();
-        let mut linear = LinearLayer::new(&rec, 3, 3, &parameter_store);
-
-
-        let mut data = NdArray::from_slice(&[1., 2., 3.]); // 1x3
-        data.reshape(&[1, 3]);
+    // Learn y = x0 + 2 * x1 - x2 from 32 samples
+    let samples = 32;
+    let inputs = NdArray::rand_uniform(&[samples, 3], -1., 1., &mut random::Generator::new(1));
+    let targets: Vec<f32> = inputs.to_vec().chunks(3).map(|x| x[0] + 2. * x[1] - x[2]).collect();
+    let targets = NdArray::from_shape_vec(&[samples, 1], targets);
+    let config = DataLoaderConfig { shuffle: true, seed: Some(2), ..DataLoaderConfig::new(8) };
+    let mut loader = DataLoader::new(TensorDataset::new(vec![inputs, targets]), config);
 
-        let input = rec.tensor_from_value(data);
-
-        let output = linear.forward(&input);
-        let output = relu(&output);
-        let loss = sum(&output);
-        println!("{:?}", loss.data());
-
-
-        let grad = loss.grad();
-        linear.optimize(grad, &mut parameter_store);
+    let mut parameter_store: HashMap<String, NdArray> = HashMap::new();
+    let mut optimizer = Sgd::new(SgdConfig::new(0.1));
+
+    for epoch in 0..50 {
+        let mut epoch_loss = 0.;
+        for batch in loader.iter() {
+            let rec: ComputationRecord<NdArray> = ComputationRecord::new();
+            let linear = LinearLayer::new(&rec, 3, 1, &parameter_store);
+
+            let input = rec.tensor_from_value(batch[0].clone());
+            let target = rec.tensor_from_value(batch[1].clone());
+            let loss = mse_loss(&linear.forward(&input), &target, None, Reduction::Mean);
+            epoch_loss += loss.data().index(&[0]);
+
+            let grad = loss.grad();
+            optimizer.step(&linear.named_parameters(), &grad, &mut parameter_store);
+        }
+        println!("epoch {} loss {:?}", epoch, epoch_loss / loader.len() as f32);
     }
 }
@@ -1,7 +1,14 @@
 mod tape;
-pub use tape::{GradFn, OpData, OperandGradBlueprint, TrackedTensor, ComputationRecord};
+pub use tape::{GradFn, OpAttribute, OpData, OperandGradBlueprint, TrackedTensor, ComputationRecord};
 pub mod ops;
 pub mod tensor_backends;
+pub mod module;
+pub mod init;
 pub mod layers;
+pub mod data;
+pub mod losses;
+pub mod optim;
+pub mod serialization;
 
 
+pub mod random;
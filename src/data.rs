@@ -0,0 +1,319 @@
+//! Datasets and mini-batch loading.
+//!
+//! A `Dataset` gives access to samples by index, a `DataLoader` walks it in (optionally
+//! shuffled) order and combines the samples of every mini-batch with a collate function. The
+//! default collate stacks the tensors of the samples along a new first axis. Batches can be
+//! prepared by background threads while the previous ones are used for training.
+
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use ndarray_rand::rand::seq::SliceRandom;
+use crate::random::{self, Generator};
+use crate::tensor_backends::TensorBackend;
+use crate::tensor_backends::slicing::{along_axis, SliceElem};
+
+pub trait Dataset {
+    type Item;
+
+    fn len(&self) -> usize;
+
+    /// Sample at `index`, which is less than `len`
+    fn get(&self, index: usize) -> Self::Item;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Samples are the slices along the first axis of a few tensors of the same length, like
+/// inputs and targets. Slices of rank 1 tensors have shape [1].
+#[derive(Debug, Clone)]
+pub struct TensorDataset<T: TensorBackend> {
+    tensors: Vec<T>,
+}
+
+impl <T: TensorBackend> TensorDataset<T> {
+    pub fn new(tensors: Vec<T>) -> Self {
+        assert!(!tensors.is_empty(), "TensorDataset needs at least one tensor");
+        let len = tensors[0].shape()[0];
+        for tensor in &tensors {
+            assert_eq!(tensor.shape()[0], len, "All tensors of a TensorDataset must have the same first dimension");
+        }
+        TensorDataset { tensors }
+    }
+}
+
+impl <T: TensorBackend> Dataset for TensorDataset<T> {
+    type Item = Vec<T>;
+
+    fn len(&self) -> usize {
+        self.tensors[0].shape()[0]
+    }
+
+    fn get(&self, index: usize) -> Vec<T> {
+        self.tensors.iter()
+            .map(|tensor| {
+                if tensor.shape().len() == 1 {
+                    T::from_slice(&[tensor.index(&[index])])
+                } else {
+                    tensor.slice(&along_axis(0, SliceElem::Index(index as isize)))
+                }
+            })
+            .collect()
+    }
+}
+
+/// The default collate: stacks field i of every sample into field i of the batch
+pub fn stack_samples<T: TensorBackend>(samples: Vec<Vec<T>>) -> Vec<T> {
+    let fields = samples.first().map(|sample| sample.len()).unwrap_or(0);
+    (0..fields)
+        .map(|field| {
+            let tensors: Vec<&T> = samples.iter()
+                .map(|sample| {
+                    assert_eq!(sample.len(), fields, "All samples must have the same number of tensors");
+                    &sample[field]
+                })
+                .collect();
+            T::stack(&tensors, 0)
+        })
+        .collect()
+}
+
+/// Configuration of a DataLoader
+#[derive(Debug, Clone, PartialEq)]
+pub struct DataLoaderConfig {
+    pub batch_size: usize,
+    /// Whether every epoch visits the samples in a new random order
+    pub shuffle: bool,
+    /// Seed of the shuffling, which then gives the same sequence of epochs on every run. If
+    /// None the global generator is used, see `random::manual_seed`.
+    pub seed: Option<u64>,
+    /// Whether the last batch is skipped when it has fewer than `batch_size` samples
+    pub drop_last: bool,
+    /// Threads preparing batches in the background, 0 to prepare them on the calling thread
+    pub num_workers: usize,
+    /// Batches each worker can have ready before the previous ones are consumed
+    pub prefetch: usize,
+}
+
+impl DataLoaderConfig {
+    /// In order, keeping the last partial batch, without background threads
+    pub fn new(batch_size: usize) -> Self {
+        DataLoaderConfig {
+            batch_size,
+            shuffle: false,
+            seed: None,
+            drop_last: false,
+            num_workers: 0,
+            prefetch: 2,
+        }
+    }
+}
+
+type Collate<I, B> = dyn Fn(Vec<I>) -> B + Send + Sync;
+
+pub struct DataLoader<D: Dataset, B> {
+    dataset: Arc<D>,
+    config: DataLoaderConfig,
+    collate: Arc<Collate<D::Item, B>>,
+    /// Shuffling generator when the config has a seed
+    generator: Option<Generator>,
+}
+
+impl <T: TensorBackend + Send, D: Dataset<Item = Vec<T>> + Send + Sync + 'static> DataLoader<D, Vec<T>> {
+    /// Batches are the samples stacked with `stack_samples`
+    pub fn new(dataset: D, config: DataLoaderConfig) -> Self {
+        Self::with_collate(dataset, config, stack_samples)
+    }
+}
+
+impl <D: Dataset + Send + Sync + 'static, B: Send + 'static> DataLoader<D, B> {
+    /// Batches are made from the samples by `collate`
+    pub fn with_collate<F: Fn(Vec<D::Item>) -> B + Send + Sync + 'static>(dataset: D, config: DataLoaderConfig, collate: F) -> Self {
+        assert!(config.batch_size > 0, "The batch size must be positive");
+        assert!(config.num_workers == 0 || config.prefetch > 0, "Background workers need a prefetch of at least 1");
+        let generator = config.seed.map(Generator::new);
+        DataLoader {
+            dataset: Arc::new(dataset),
+            config,
+            collate: Arc::new(collate),
+            generator,
+        }
+    }
+
+    pub fn dataset(&self) -> &D {
+        &self.dataset
+    }
+
+    /// Number of batches per epoch
+    pub fn len(&self) -> usize {
+        let samples = self.dataset.len();
+        if self.config.drop_last {
+            samples / self.config.batch_size
+        } else {
+            samples.div_ceil(self.config.batch_size)
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Batches of a new epoch, each call reshuffles if enabled
+    pub fn iter(&mut self) -> Batches<D, B> {
+        let mut order: Vec<usize> = (0..self.dataset.len()).collect();
+        if self.config.shuffle {
+            match &mut self.generator {
+                Some(generator) => order.shuffle(generator),
+                None => random::with_global_generator(|generator| order.shuffle(generator)),
+            }
+        }
+        let batches: Vec<Vec<usize>> = order.chunks(self.config.batch_size)
+            .filter(|batch| !self.config.drop_last || batch.len() == self.config.batch_size)
+            .map(|batch| batch.to_vec())
+            .collect();
+
+        let workers = (0..self.config.num_workers.min(batches.len()))
+            .map(|worker| {
+                // Worker w prepares batches w, w + num_workers, ... so they can be received in order
+                let (sender, receiver) = sync_channel(self.config.prefetch);
+                let dataset = Arc::clone(&self.dataset);
+                let collate = Arc::clone(&self.collate);
+                let assigned: Vec<Vec<usize>> = batches.iter().skip(worker).step_by(self.config.num_workers).cloned().collect();
+                let handle = std::thread::spawn(move || {
+                    for indices in assigned {
+                        let batch = collate(indices.iter().map(|index| dataset.get(*index)).collect());
+                        // The receiver is gone if the epoch was not iterated to the end
+                        if sender.send(batch).is_err() {
+                            return;
+                        }
+                    }
+                });
+                (receiver, handle)
+            })
+            .collect();
+
+        Batches {
+            dataset: Arc::clone(&self.dataset),
+            collate: Arc::clone(&self.collate),
+            batches,
+            next: 0,
+            workers,
+        }
+    }
+}
+
+/// Iterator over the batches of one epoch
+pub struct Batches<D: Dataset, B> {
+    dataset: Arc<D>,
+    collate: Arc<Collate<D::Item, B>>,
+    batches: Vec<Vec<usize>>,
+    next: usize,
+    workers: Vec<(Receiver<B>, JoinHandle<()>)>,
+}
+
+impl <D: Dataset, B> Iterator for Batches<D, B> {
+    type Item = B;
+
+    fn next(&mut self) -> Option<B> {
+        let indices = self.batches.get(self.next)?;
+        let batch = if self.workers.is_empty() {
+            (self.collate)(indices.iter().map(|index| self.dataset.get(*index)).collect())
+        } else {
+            let worker = self.next % self.workers.len();
+            match self.workers[worker].0.recv() {
+                Ok(batch) => batch,
+                Err(_) => panic!("DataLoader worker {} stopped, did the dataset or collate panic?", worker),
+            }
+        };
+        self.next += 1;
+        Some(batch)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.batches.len() - self.next;
+        (remaining, Some(remaining))
+    }
+}
+
+impl <D: Dataset, B> ExactSizeIterator for Batches<D, B> {}
+
+impl <D: Dataset, B> Drop for Batches<D, B> {
+    fn drop(&mut self) {
+        // Dropping the receivers makes the workers stop at their next send
+        for (receiver, handle) in self.workers.drain(..) {
+            drop(receiver);
+            let _ = handle.join();
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod data_tests {
+    use super::*;
+    use crate::tensor_backends::NdArray;
+
+    /// Sample i is ([i, 10 * i], [i])
+    fn dataset(len: usize) -> TensorDataset<NdArray> {
+        let inputs = NdArray::from_shape_vec(&[len, 2], (0..len).flat_map(|i| vec![i as f32, 10. * i as f32]).collect());
+        let targets = NdArray::from_shape_vec(&[len], (0..len).map(|i| i as f32).collect());
+        TensorDataset::new(vec![inputs, targets])
+    }
+
+    fn targets(batch: &[NdArray]) -> Vec<f32> {
+        batch[1].to_vec()
+    }
+
+    #[test]
+    fn batches_in_order() {
+        let mut loader = DataLoader::new(dataset(5), DataLoaderConfig::new(2));
+        assert_eq!(loader.len(), 3);
+        let batches: Vec<Vec<NdArray>> = loader.iter().collect();
+        assert_eq!(batches[0][0], NdArray::from_shape_vec(&[2, 2], vec![0., 0., 1., 10.]));
+        assert_eq!(batches[0][1].shape(), &[2, 1]);
+        assert_eq!(batches.iter().map(|batch| targets(batch)).collect::<Vec<_>>(), vec![vec![0., 1.], vec![2., 3.], vec![4.]]);
+
+        let mut loader = DataLoader::new(dataset(5), DataLoaderConfig { drop_last: true, ..DataLoaderConfig::new(2) });
+        assert_eq!(loader.len(), 2);
+        assert_eq!(loader.iter().count(), 2);
+    }
+
+    #[test]
+    fn seeded_shuffling() {
+        let config = DataLoaderConfig { shuffle: true, seed: Some(3), ..DataLoaderConfig::new(4) };
+        let epochs = |config: DataLoaderConfig| {
+            let mut loader = DataLoader::new(dataset(10), config);
+            (0..2).map(|_epoch| loader.iter().flat_map(|batch| targets(&batch)).collect::<Vec<f32>>()).collect::<Vec<_>>()
+        };
+        let first_run = epochs(config.clone());
+        assert_eq!(first_run, epochs(config.clone()));
+        // Every epoch is a permutation of the samples, different from the previous one
+        assert_ne!(first_run[0], first_run[1]);
+        for epoch in &first_run {
+            let mut sorted = epoch.clone();
+            sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            assert_eq!(sorted, (0..10).map(|i| i as f32).collect::<Vec<f32>>());
+        }
+        assert_ne!(first_run, epochs(DataLoaderConfig { seed: Some(4), ..config }));
+    }
+
+    #[test]
+    fn background_workers_keep_order() {
+        let config = DataLoaderConfig { shuffle: true, seed: Some(1), ..DataLoaderConfig::new(3) };
+        let expected: Vec<Vec<NdArray>> = DataLoader::new(dataset(20), config.clone()).iter().collect();
+        let mut loader = DataLoader::new(dataset(20), DataLoaderConfig { num_workers: 3, prefetch: 1, ..config });
+        assert_eq!(loader.iter().collect::<Vec<_>>(), expected);
+        // Stopping early does not block
+        assert_eq!(loader.iter().take(2).count(), 2);
+    }
+
+    #[test]
+    fn custom_collate() {
+        let mut loader = DataLoader::with_collate(dataset(5), DataLoaderConfig::new(2), |samples: Vec<Vec<NdArray>>| {
+            samples.iter().map(|sample| sample[1].index(&[0])).sum::<f32>()
+        });
+        assert_eq!(loader.iter().collect::<Vec<f32>>(), vec![1., 5., 4.]);
+    }
+}
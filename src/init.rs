@@ -0,0 +1,231 @@
+//! Parameter initialization schemes.
+//!
+//! Fans follow the [in, out] layout of the LinearLayer weights: for a shape [a, b, ...] the fan
+//! in is a * receptive field and the fan out b * receptive field, where the receptive field is
+//! the product of the remaining dimensions. A rank 1 shape [n] has both fans equal to n.
+
+use crate::random::{self, Generator};
+use crate::tensor_backends::TensorBackend;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FanMode {
+    /// Preserves the variance of the activations in the forward pass
+    FanIn,
+    /// Preserves the variance of the gradients in the backward pass
+    FanOut,
+}
+
+/// The activation following the layer, which determines the recommended gain
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Nonlinearity {
+    Linear,
+    Sigmoid,
+    Tanh,
+    Relu,
+    /// With the negative slope
+    LeakyRelu(f32),
+    Selu,
+}
+
+impl Nonlinearity {
+    /// Same values as PyTorch's `calculate_gain`
+    pub fn gain(self) -> f32 {
+        match self {
+            Nonlinearity::Linear | Nonlinearity::Sigmoid => 1.,
+            Nonlinearity::Tanh => 5. / 3.,
+            Nonlinearity::Relu => 2f32.sqrt(),
+            Nonlinearity::LeakyRelu(slope) => (2. / (1. + slope * slope)).sqrt(),
+            Nonlinearity::Selu => 0.75,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Init {
+    Zeros,
+    Constant(f32),
+    /// Uniform in [low, high)
+    Uniform { low: f32, high: f32 },
+    Normal { mean: f32, std: f32 },
+    /// Glorot & Bengio: Uniform(-a, a) with a = gain * sqrt(6 / (fan_in + fan_out))
+    XavierUniform { gain: f32 },
+    /// Glorot & Bengio: Normal(0, std) with std = gain * sqrt(2 / (fan_in + fan_out))
+    XavierNormal { gain: f32 },
+    /// He et al.: Uniform(-a, a) with a = gain * sqrt(3 / fan)
+    KaimingUniform { mode: FanMode, nonlinearity: Nonlinearity },
+    /// He et al.: Normal(0, std) with std = gain / sqrt(fan)
+    KaimingNormal { mode: FanMode, nonlinearity: Nonlinearity },
+    /// Saxe et al.: (semi) orthogonal matrix times gain, the shape is seen as
+    /// [shape[0], product of the rest]
+    Orthogonal { gain: f32 },
+}
+
+/// (fan in, fan out) of a parameter of the given shape, see the module documentation
+pub fn fans(shape: &[usize]) -> (usize, usize) {
+    match shape {
+        [] => (1, 1),
+        [len] => (*len, *len),
+        [fan_in, fan_out, receptive_field @ ..] => {
+            let receptive_field: usize = receptive_field.iter().product();
+            (fan_in * receptive_field, fan_out * receptive_field)
+        }
+    }
+}
+
+impl Init {
+    /// Values for a parameter of the given shape drawn from `rng`
+    pub fn sample<T: TensorBackend>(&self, shape: &[usize], rng: &mut Generator) -> T {
+        let (fan_in, fan_out) = fans(shape);
+        let fan = |mode: FanMode| match mode {
+            FanMode::FanIn => fan_in as f32,
+            FanMode::FanOut => fan_out as f32,
+        };
+        match self {
+            Init::Zeros => T::zeros(shape),
+            Init::Constant(value) => {
+                let mut values = T::zeros(shape);
+                values.fill_with(*value);
+                values
+            }
+            Init::Uniform { low, high } => T::rand_uniform(shape, *low, *high, rng),
+            Init::Normal { mean, std } => T::rand_normal(shape, *mean, *std, rng),
+            Init::XavierUniform { gain } => {
+                let bound = gain * (6. / (fan_in + fan_out) as f32).sqrt();
+                T::rand_uniform(shape, -bound, bound, rng)
+            }
+            Init::XavierNormal { gain } => {
+                let std = gain * (2. / (fan_in + fan_out) as f32).sqrt();
+                T::rand_normal(shape, 0., std, rng)
+            }
+            Init::KaimingUniform { mode, nonlinearity } => {
+                let bound = nonlinearity.gain() * (3. / fan(*mode)).sqrt();
+                T::rand_uniform(shape, -bound, bound, rng)
+            }
+            Init::KaimingNormal { mode, nonlinearity } => {
+                let std = nonlinearity.gain() / fan(*mode).sqrt();
+                T::rand_normal(shape, 0., std, rng)
+            }
+            Init::Orthogonal { gain } => orthogonal(shape, *gain, rng),
+        }
+    }
+
+    /// Like `sample` with the global generator, see `random::manual_seed`
+    pub fn sample_global<T: TensorBackend>(&self, shape: &[usize]) -> T {
+        random::with_global_generator(|rng| self.sample(shape, rng))
+    }
+}
+
+/// Orthonormal rows if there are fewer rows than columns, orthonormal columns otherwise. The
+/// columns of a Gaussian matrix are orthonormalized with modified Gram-Schmidt, which is the Q
+/// of its QR decomposition with a positive R diagonal, so the result is uniformly distributed.
+fn orthogonal<T: TensorBackend>(shape: &[usize], gain: f32, rng: &mut Generator) -> T {
+    assert!(shape.len() >= 2, "Orthogonal initialization needs at least 2 dimensions, got {:?}", shape);
+    let rows = shape[0];
+    let cols: usize = shape[1..].iter().product();
+    let (long, short) = (rows.max(cols), rows.min(cols));
+
+    // `short` columns of length `long`, stored column after column
+    let gaussian = T::rand_normal(&[short * long], 0., 1., rng).to_vec();
+    let mut columns: Vec<Vec<f64>> = gaussian.chunks(long)
+        .map(|column| column.iter().map(|x| *x as f64).collect())
+        .collect();
+    for i in 0..short {
+        for j in 0..i {
+            let projection: f64 = columns[i].iter().zip(&columns[j]).map(|(a, b)| a * b).sum();
+            let (done, current) = columns.split_at_mut(i);
+            for (x, q) in current[0].iter_mut().zip(&done[j]) {
+                *x -= projection * q;
+            }
+        }
+        let norm = columns[i].iter().map(|x| x * x).sum::<f64>().sqrt();
+        for x in columns[i].iter_mut() {
+            *x /= norm;
+        }
+    }
+
+    let values = (0..rows)
+        .flat_map(|row| (0..cols).map(move |col| (row, col)))
+        .map(|(row, col)| {
+            let value = if rows >= cols { columns[col][row] } else { columns[row][col] };
+            value as f32 * gain
+        })
+        .collect();
+    T::from_shape_vec(shape, values)
+}
+
+
+#[cfg(test)]
+mod init_tests {
+    use super::*;
+    use crate::tensor_backends::NdArray;
+
+    fn mean_and_std(values: &[f32]) -> (f32, f32) {
+        let mean = values.iter().sum::<f32>() / values.len() as f32;
+        let var = values.iter().map(|x| (x - mean).powi(2)).sum::<f32>() / values.len() as f32;
+        (mean, var.sqrt())
+    }
+
+    #[test]
+    fn fans_and_gains() {
+        assert_eq!(fans(&[5]), (5, 5));
+        assert_eq!(fans(&[3, 4]), (3, 4));
+        assert_eq!(fans(&[3, 4, 2, 2]), (12, 16));
+        assert_eq!(Nonlinearity::Relu.gain(), 2f32.sqrt());
+        assert_eq!(Nonlinearity::LeakyRelu(1.).gain(), 1.);
+        assert_eq!(Nonlinearity::Tanh.gain(), 5. / 3.);
+    }
+
+    #[test]
+    fn variance_scaling() {
+        let mut rng = Generator::new(0);
+        let xavier: NdArray = Init::XavierUniform { gain: 1. }.sample(&[100, 200], &mut rng);
+        let bound = (6f32 / 300.).sqrt();
+        assert!(xavier.to_vec().iter().all(|x| x.abs() <= bound));
+        let (_mean, std) = mean_and_std(&xavier.to_vec());
+        assert!((std - bound / 3f32.sqrt()).abs() < 0.005, "{}", std);
+
+        let xavier: NdArray = Init::XavierNormal { gain: 2. }.sample(&[100, 200], &mut rng);
+        let (mean, std) = mean_and_std(&xavier.to_vec());
+        assert!(mean.abs() < 0.01 && (std - 2. * (2f32 / 300.).sqrt()).abs() < 0.005, "{} {}", mean, std);
+
+        let kaiming: NdArray = Init::KaimingNormal { mode: FanMode::FanIn, nonlinearity: Nonlinearity::Relu }.sample(&[100, 200], &mut rng);
+        let (_mean, std) = mean_and_std(&kaiming.to_vec());
+        assert!((std - (2f32 / 100.).sqrt()).abs() < 0.005, "{}", std);
+
+        let kaiming: NdArray = Init::KaimingUniform { mode: FanMode::FanOut, nonlinearity: Nonlinearity::Linear }.sample(&[100, 200], &mut rng);
+        let bound = (3f32 / 200.).sqrt();
+        assert!(kaiming.to_vec().iter().all(|x| x.abs() <= bound));
+        assert!(kaiming.to_vec().iter().any(|x| x.abs() > 0.9 * bound));
+
+        let constant: NdArray = Init::Constant(0.5).sample(&[2, 2], &mut rng);
+        assert_eq!(constant.to_vec(), vec![0.5; 4]);
+        assert_eq!(Init::Zeros.sample::<NdArray>(&[3], &mut rng), NdArray::zeros(&[3]));
+    }
+
+    #[test]
+    fn orthogonal_rows_or_columns() {
+        let mut rng = Generator::new(1);
+        for shape in [[3, 5], [5, 3], [4, 4]].iter() {
+            let q: NdArray = Init::Orthogonal { gain: 2. }.sample(shape, &mut rng);
+            // The Gram matrix of the shorter side is gain^2 * I
+            let mut transposed = q.clone();
+            transposed.t();
+            let gram = if shape[0] <= shape[1] { q.matmul(&transposed) } else { transposed.matmul(&q) };
+            let size = shape[0].min(shape[1]);
+            for i in 0..size {
+                for j in 0..size {
+                    let expected = if i == j { 4. } else { 0. };
+                    assert!((gram.index(&[i, j]) - expected).abs() < 1e-4, "{:?} {:?}", shape, gram);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn seeded_samples_repeat() {
+        let init = Init::KaimingUniform { mode: FanMode::FanIn, nonlinearity: Nonlinearity::Relu };
+        let first: NdArray = init.sample(&[3, 3], &mut Generator::new(9));
+        let second: NdArray = init.sample(&[3, 3], &mut Generator::new(9));
+        assert_eq!(first, second);
+    }
+}
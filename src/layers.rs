@@ -1,44 +1,121 @@
 use crate::TrackedTensor;
-use crate::tensor_backends::{NdArray, TensorBackend};
-use crate::tape::{ComputationRecord, Grad};
+use crate::tensor_backends::TensorBackend;
+use crate::tape::ComputationRecord;
 use crate::ops::*;
+use crate::module::Module;
+use crate::init::{FanMode, Init, Nonlinearity};
 use std::collections::HashMap;
 
 
-/// The input is 1xIN  we multiply by the weights INxOUT and get the output 1xOUT
+/// Configuration of a LinearLayer
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinearConfig {
+    pub in_size: usize,
+    pub out_size: usize,
+    /// Whether a bias of shape [out_size] is added to the output
+    pub bias: bool,
+    /// Prefix of the parameter names in the store: the weights are stored as "{name}.weight"
+    /// and the bias as "{name}.bias". Nested models can use dotted names like "encoder.fc1".
+    /// If None a name is generated by the ComputationRecord, see `ComputationRecord::unique_name`.
+    pub name: Option<String>,
+    /// Used when the weights are not in the store
+    pub weight_init: Init,
+    /// Used when the bias is not in the store
+    pub bias_init: Init,
+}
+
+impl LinearConfig {
+    /// Layer with bias and a generated name. The weights are initialized like PyTorch's Linear,
+    /// Uniform(-a, a) with a = 1 / sqrt(in_size), and the bias with zeros.
+    pub fn new(in_size: usize, out_size: usize) -> Self {
+        LinearConfig {
+            in_size,
+            out_size,
+            bias: true,
+            name: None,
+            weight_init: Init::KaimingUniform { mode: FanMode::FanIn, nonlinearity: Nonlinearity::LeakyRelu(5f32.sqrt()) },
+            bias_init: Init::Zeros,
+        }
+    }
+}
+
+/// The input is [..., IN] we multiply by the weights INxOUT and add the bias to get the
+/// output [..., OUT]
 pub struct LinearLayer<'a, T: TensorBackend>{
     // has shape INxOUT
     weights: TrackedTensor<'a, T>,
-    id: String,
+    // has shape OUT
+    bias: Option<TrackedTensor<'a, T>>,
+    name: String,
+    training: bool,
 }
 
 impl <'a, T: TensorBackend> LinearLayer<'a, T>{
+    /// Layer with bias and a generated name, loading its parameters from the store or
+    /// initializing them randomly if they are not there
     pub fn new(record: &'a ComputationRecord<T>, in_size: usize, out_size: usize, params_store: &HashMap<String, T>) -> Self{
-        let id = "a".to_string();
-        let param = match params_store.get(&id){
+        Self::from_config(record, LinearConfig::new(in_size, out_size), params_store)
+    }
+
+    pub fn from_config(record: &'a ComputationRecord<T>, config: LinearConfig, params_store: &HashMap<String, T>) -> Self{
+        let name = config.name.unwrap_or_else(|| record.unique_name("linear"));
+        let weights = Self::load_or_init(params_store, &format!("{}.weight", name), &[config.in_size, config.out_size], &config.weight_init);
+        let bias = if config.bias {
+            Some(Self::load_or_init(params_store, &format!("{}.bias", name), &[config.out_size], &config.bias_init))
+        } else {
+            None
+        };
+        LinearLayer{
+            weights: record.tensor_from_value(weights),
+            bias: bias.map(|bias| record.tensor_from_value(bias)),
+            name,
+            training: true,
+        }
+    }
+
+    fn load_or_init(params_store: &HashMap<String, T>, id: &str, shape: &[usize], init: &Init) -> T {
+        match params_store.get(id){
             None => {
-                T::rand(&[in_size, out_size])
+                init.sample_global(shape)
             },
             Some(val) => {
+                assert_eq!(val.shape(), shape, "Parameter {} in the store has the wrong shape", id);
                 val.clone()
             },
-        };
-        LinearLayer{
-            weights: record.tensor_from_value(param),
-            id
         }
     }
-    /// Input must be of shape [1 x in_size]
-    /// Output is of size [1 x out_size]
-    pub fn forward(&mut self, input: &TrackedTensor<'a, T>) -> TrackedTensor<'a, T>{
-        matmul(input, &self.weights)
+
+    /// Prefix of the names of this layer's parameters in the store
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl <'a, T: TensorBackend> Module<'a, T> for LinearLayer<'a, T> {
+    /// Input must be of shape [..., in_size], e.g. [in_size] or [batch, in_size]
+    /// Output is of shape [..., out_size]
+    fn forward(&self, input: &TrackedTensor<'a, T>) -> TrackedTensor<'a, T>{
+        let output = matmul(input, &self.weights);
+        match &self.bias {
+            None => output,
+            Some(bias) => add(&output, &broadcast_to(bias, output.shape())),
+        }
+    }
+
+    fn local_parameters(&self) -> Vec<(String, &TrackedTensor<'a, T>)> {
+        let mut params = vec![(format!("{}.weight", self.name), &self.weights)];
+        if let Some(bias) = &self.bias {
+            params.push((format!("{}.bias", self.name), bias));
+        }
+        params
+    }
+
+    fn is_training(&self) -> bool {
+        self.training
     }
 
-    pub fn optimize(&mut self, grad: Grad<T>, params_store: &mut HashMap<String, T>){
-        let self_grads = grad.wrt(&self.weights);
-        let updated_weights = self.weights.data().sub(&self_grads.data().mul_scalar(0.01));
-        self.weights = self.weights.tape.tensor_from_value(updated_weights);
-        params_store.insert(self.id.clone(), self.weights.data().clone());
+    fn set_training(&mut self, training: bool) {
+        self.training = training;
     }
 }
 
@@ -48,17 +125,21 @@ mod layer_tests {
     use crate::tape::ComputationRecord;
     use crate::tensor_backends::{NdArray, TensorBackend};
     use crate::ops::*;
-    use crate::layers::LinearLayer;
+    use crate::layers::{LinearConfig, LinearLayer};
+    use crate::module::Module;
+    use crate::optim::{Optimizer, Sgd, SgdConfig};
+    use crate::init::Init;
     use std::collections::HashMap;
 
     #[test]
     fn layer_test() {
 
         let mut parameter_store: HashMap<String, NdArray> = HashMap::new();
+        let mut optimizer = Sgd::new(SgdConfig::new(0.01));
 
         for _i in 0..1000 {
             let rec: ComputationRecord<NdArray> = ComputationRecord::new();
-            let mut linear = LinearLayer::new(&rec, 3, 3, &parameter_store);
+            let linear = LinearLayer::new(&rec, 3, 3, &parameter_store);
 
 
             let mut data = NdArray::from_slice(&[1., 2., 3.]); // 1x3
@@ -73,8 +154,81 @@ mod layer_tests {
 
 
             let grad = loss.grad();
-            linear.optimize(grad, &mut parameter_store);
+            optimizer.step(&linear.named_parameters(), &grad, &mut parameter_store);
+        }
+
+    }
+
+    #[test]
+    fn layers_get_distinct_names() {
+        let mut parameter_store: HashMap<String, NdArray> = HashMap::new();
+        let mut optimizer = Sgd::new(SgdConfig::new(0.01));
+        for _i in 0..2 {
+            let rec: ComputationRecord<NdArray> = ComputationRecord::new();
+            let first = LinearLayer::new(&rec, 3, 4, &parameter_store);
+            let second = LinearLayer::new(&rec, 4, 2, &parameter_store);
+            let named = LinearLayer::from_config(&rec, LinearConfig {
+                bias: false,
+                name: Some("head.fc".to_string()),
+                ..LinearConfig::new(2, 1)
+            }, &parameter_store);
+            assert_eq!((first.name(), second.name(), named.name()), ("linear_0", "linear_1", "head.fc"));
+
+            let input = rec.tensor_from_slice(&[1., 2., 3.]);
+            let hidden = second.forward(&first.forward(&input));
+            let loss = sum(&named.forward(&hidden));
+            let grad = loss.grad();
+            let mut params = first.named_parameters();
+            params.extend(second.named_parameters());
+            params.extend(named.named_parameters());
+            optimizer.step(&params, &grad, &mut parameter_store);
         }
+        let mut names: Vec<&String> = parameter_store.keys().collect();
+        names.sort();
+        assert_eq!(names, vec!["head.fc.weight", "linear_0.bias", "linear_0.weight", "linear_1.bias", "linear_1.weight"]);
+        assert_eq!(parameter_store["linear_1.weight"].shape(), &[4, 2]);
+    }
+
+    #[test]
+    fn batched_input_with_bias() {
+        let rec: ComputationRecord<NdArray> = ComputationRecord::new();
+        let mut parameter_store: HashMap<String, NdArray> = HashMap::new();
+        let mut weights = NdArray::from_slice(&[1., 0., 0., 1., 1., 1.]);
+        weights.reshape(&[3, 2]);
+        parameter_store.insert("fc.weight".to_string(), weights);
+        parameter_store.insert("fc.bias".to_string(), NdArray::from_slice(&[0.5, -0.5]));
+        let config = LinearConfig { name: Some("fc".to_string()), ..LinearConfig::new(3, 2) };
+        let linear = LinearLayer::from_config(&rec, config, &parameter_store);
+
+        // [2, 2, 3] input, a batch of sequences
+        let mut data = NdArray::from_slice(&[1., 2., 3., 0., 0., 0., 1., 0., 0., 0., 1., 0.]);
+        data.reshape(&[2, 2, 3]);
+        let output = linear.forward(&rec.tensor_from_value(data));
+        let mut expected = NdArray::from_slice(&[4.5, 4.5, 0.5, -0.5, 1.5, -0.5, 0.5, 0.5]);
+        expected.reshape(&[2, 2, 2]);
+        assert_eq!(output.data(), &expected);
+
+        // Every one of the 4 rows adds the bias
+        let grad = sum(&output).grad();
+        assert_eq!(grad.wrt(linear.bias.as_ref().unwrap()).data(), &NdArray::from_slice(&[4., 4.]));
+    }
 
+    #[test]
+    fn configured_initialization() {
+        let rec: ComputationRecord<NdArray> = ComputationRecord::new();
+        let config = LinearConfig {
+            weight_init: Init::Constant(0.25),
+            bias_init: Init::Constant(-1.),
+            ..LinearConfig::new(2, 3)
+        };
+        let linear = LinearLayer::from_config(&rec, config, &HashMap::new());
+        let output = linear.forward(&rec.tensor_from_slice(&[1., 2.]));
+        assert_eq!(output.data(), &NdArray::from_slice(&[-0.25, -0.25, -0.25]));
+
+        // The default keeps the weights in [-1 / sqrt(in_size), 1 / sqrt(in_size)]
+        let linear = LinearLayer::new(&rec, 16, 8, &HashMap::new());
+        let weights = linear.named_parameters()[0].1.data().to_vec();
+        assert!(weights.iter().all(|w| w.abs() <= 0.25 + 1e-6));
+        assert!(weights.iter().any(|w| *w < 0.));
     }
 }
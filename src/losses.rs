@@ -0,0 +1,364 @@
+use crate::{GradFn, OpData, TrackedTensor};
+use crate::tensor_backends::TensorBackend;
+use crate::ops::softmax::log_softmax_data;
+use crate::ops::unary::unary_op;
+use crate::ops::*;
+
+/// How the per element (or per sample) losses are combined
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reduction {
+    /// Average, giving shape [1]. Weighted losses over classes are divided by the sum of the
+    /// weights of the targets instead of the number of samples, the loss is 0 if that sum is 0.
+    Mean,
+    /// Sum, giving shape [1]
+    Sum,
+    /// No reduction, the loss keeps one value per element (or per sample)
+    None,
+}
+
+fn reduce<'t, T: TensorBackend>(losses: TrackedTensor<'t, T>, reduction: Reduction) -> TrackedTensor<'t, T> {
+    match reduction {
+        Reduction::Mean => mean(&losses),
+        Reduction::Sum => sum(&losses),
+        Reduction::None => losses,
+    }
+}
+
+/// Multiplies the element wise losses by weights broadcastable to their shape
+fn apply_weights<'t, T: TensorBackend>(losses: TrackedTensor<'t, T>, weights: Option<&T>) -> TrackedTensor<'t, T> {
+    match weights {
+        None => losses,
+        Some(weights) => {
+            let weights = losses.tape.tensor_from_value(weights.broadcast_to(losses.shape()));
+            mul(&losses, &weights)
+        }
+    }
+}
+
+/// Weight of the target class of every sample, 1 for all of them without class weights
+fn target_weights<T: TensorBackend>(targets: &[usize], classes: usize, weights: Option<&T>) -> Vec<f32> {
+    targets.iter().map(|target| {
+        assert!(*target < classes, "Target class {} out of range for {} classes", target, classes);
+        weights.map_or(1., |weights| weights.index(&[*target]))
+    }).collect()
+}
+
+/// Records a loss over [batch, classes] inputs with one target class per sample.
+/// `sample_losses` are the weighted per sample losses and `input_grad` gives the gradient of
+/// the loss of sample i with respect to the input element [i, class], before weighting.
+fn class_loss<'t, T, F>(input: &TrackedTensor<'t, T>, sample_losses: Vec<f32>, sample_weights: Vec<f32>, reduction: Reduction, op_name: &str, input_grad: F) -> TrackedTensor<'t, T>
+    where T: TensorBackend, F: Fn(usize, usize) -> f32 + 'static {
+    let shape = input.shape().to_vec();
+    // All the targets having weight 0 gives a loss of 0 instead of dividing by 0
+    let weights_sum: f32 = sample_weights.iter().sum();
+    let weights_sum = if weights_sum == 0. { 1. } else { weights_sum };
+    let grad_fn: GradFn<T> = GradFn(Box::new(
+        move |child_grad: T, self_grad: &mut T| {
+            for (sample, weight) in sample_weights.iter().enumerate() {
+                let coefficient = match reduction {
+                    Reduction::Mean => child_grad.index(&[0]) / weights_sum,
+                    Reduction::Sum => child_grad.index(&[0]),
+                    Reduction::None => child_grad.index(&[sample]),
+                };
+                for class in 0..shape[1] {
+                    *self_grad._index_mut(&[sample, class]) += coefficient * weight * input_grad(sample, class);
+                }
+            }
+        },
+    ));
+    let grad_blueprint = input.self_gradient_blueprint(grad_fn);
+
+    let op_data =
+        OpData::from_blueprints(vec![grad_blueprint], op_name.to_string());
+
+    let op_result = match reduction {
+        Reduction::Mean => T::from_slice(&[sample_losses.iter().sum::<f32>() / weights_sum]),
+        Reduction::Sum => T::from_slice(&[sample_losses.iter().sum()]),
+        Reduction::None => T::from_slice(&sample_losses),
+    };
+    input.tape.tensor_from_op_result_and_data(op_result, op_data)
+}
+
+/// Cross entropy between `logits` of shape [batch, classes] and the target class index of each
+/// sample, optionally weighting each class. Fuses log_softmax and nll_loss: the gradient is
+/// computed directly as softmax - one_hot(target), which is more stable than chaining them.
+pub fn cross_entropy<'t, T: TensorBackend>(logits: &TrackedTensor<'t, T>, targets: &[usize], weights: Option<&T>, reduction: Reduction) -> TrackedTensor<'t, T> {
+    let shape = logits.shape();
+    assert_eq!(shape.len(), 2, "Logits must have shape [batch, classes], got {:?}", shape);
+    assert_eq!(shape[0], targets.len(), "Got {} targets for a batch of {}", targets.len(), shape[0]);
+    let sample_weights = target_weights(targets, shape[1], weights);
+    let log_probs = log_softmax_data(logits.data(), 1);
+    let sample_losses = targets.iter().zip(&sample_weights).enumerate()
+        .map(|(sample, (target, weight))| -weight * log_probs.index(&[sample, *target]))
+        .collect();
+
+    let probs = log_probs.exp();
+    let targets = targets.to_vec();
+    class_loss(logits, sample_losses, sample_weights, reduction, "CrossEntropy", move |sample, class| {
+        let one_hot = if targets[sample] == class { 1. } else { 0. };
+        probs.index(&[sample, class]) - one_hot
+    })
+}
+
+/// Negative log likelihood of the target class of each sample given `log_probs` of shape
+/// [batch, classes], usually the output of log_softmax
+pub fn nll_loss<'t, T: TensorBackend>(log_probs: &TrackedTensor<'t, T>, targets: &[usize], weights: Option<&T>, reduction: Reduction) -> TrackedTensor<'t, T> {
+    let shape = log_probs.shape();
+    assert_eq!(shape.len(), 2, "Log probabilities must have shape [batch, classes], got {:?}", shape);
+    assert_eq!(shape[0], targets.len(), "Got {} targets for a batch of {}", targets.len(), shape[0]);
+    let sample_weights = target_weights(targets, shape[1], weights);
+    let sample_losses = targets.iter().zip(&sample_weights).enumerate()
+        .map(|(sample, (target, weight))| -weight * log_probs.data().index(&[sample, *target]))
+        .collect();
+
+    let targets = targets.to_vec();
+    class_loss(log_probs, sample_losses, sample_weights, reduction, "NllLoss", move |sample, class| {
+        if targets[sample] == class { -1. } else { 0. }
+    })
+}
+
+/// Mean squared error: (input - target)^2
+pub fn mse_loss<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>, target: &TrackedTensor<'t, T>, weights: Option<&T>, reduction: Reduction) -> TrackedTensor<'t, T> {
+    let losses = square(&sub(input, target));
+    reduce(apply_weights(losses, weights), reduction)
+}
+
+/// Mean absolute error: |input - target|
+pub fn l1_loss<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>, target: &TrackedTensor<'t, T>, weights: Option<&T>, reduction: Reduction) -> TrackedTensor<'t, T> {
+    let losses = abs(&sub(input, target));
+    reduce(apply_weights(losses, weights), reduction)
+}
+
+/// 0.5 * d^2 / beta if |d| < beta and |d| - 0.5 * beta otherwise, with d = input - target
+pub fn smooth_l1_loss<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>, target: &TrackedTensor<'t, T>, beta: f32, weights: Option<&T>, reduction: Reduction) -> TrackedTensor<'t, T> {
+    assert!(beta > 0., "beta must be positive, got {}", beta);
+    let difference = sub(input, target);
+    let mut op_result = difference.data().clone();
+    op_result.map_inplace(|d| *d = if d.abs() < beta { 0.5 * *d * *d / beta } else { d.abs() - 0.5 * beta });
+    let losses = unary_op(&difference, op_result, "SmoothL1", move |difference, _output| {
+        let mut derivative = difference.clone();
+        derivative.map_inplace(|d| *d = if d.abs() < beta { *d / beta } else { d.signum() });
+        derivative
+    });
+    reduce(apply_weights(losses, weights), reduction)
+}
+
+/// 0.5 * d^2 if |d| < delta and delta * (|d| - 0.5 * delta) otherwise, with d = input - target.
+/// The same as delta * smooth_l1_loss with beta = delta.
+pub fn huber_loss<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>, target: &TrackedTensor<'t, T>, delta: f32, weights: Option<&T>, reduction: Reduction) -> TrackedTensor<'t, T> {
+    let losses = smooth_l1_loss(input, target, delta, weights, Reduction::None);
+    reduce(mul_scalar(&losses, delta), reduction)
+}
+
+/// Binary cross entropy between probabilities in [0, 1] and targets:
+/// -(target * log(p) + (1 - target) * log(1 - p)). The logs are clamped to be >= -100 so
+/// probabilities of exactly 0 or 1 give a finite loss, and the gradient
+/// (p - target) / max(p * (1 - p), 1e-12) stays finite for them as well.
+//noinspection DuplicatedCode
+pub fn binary_cross_entropy<'t, T: TensorBackend>(probs: &TrackedTensor<'t, T>, target: &TrackedTensor<'t, T>, weights: Option<&T>, reduction: Reduction) -> TrackedTensor<'t, T> {
+    let mut log_p = probs.data().clone();
+    log_p.map_inplace(|p| *p = p.ln().max(-100.));
+    let mut log_one_minus_p = probs.data().clone();
+    log_one_minus_p.map_inplace(|p| *p = (1. - *p).ln().max(-100.));
+
+    let probs_data = probs.data().clone();
+    let target_data = target.data().clone();
+    let probs_grad_fn: GradFn<T> = GradFn(Box::new(
+        move |child_grad: T, self_grad: &mut T| {
+            let derivative = probs_data.zip_map(&target_data, |p, y| (p - y) / (p * (1. - p)).max(1e-12));
+            *self_grad = self_grad.add(&child_grad.mul(&derivative));
+        },
+    ));
+    let log_odds = log_p.sub(&log_one_minus_p);
+    let target_grad_fn: GradFn<T> = GradFn(Box::new(
+        move |child_grad: T, self_grad: &mut T| {
+            *self_grad = self_grad.add(&child_grad.mul(&log_odds.neg()));
+        },
+    ));
+
+    let probs_blueprint = probs.self_gradient_blueprint(probs_grad_fn);
+    let target_blueprint = target.self_gradient_blueprint(target_grad_fn);
+
+    let op_data =
+        OpData::from_blueprints(vec![probs_blueprint, target_blueprint], "BinaryCrossEntropy".to_string());
+
+    let positive = target.data().mul(&log_p);
+    let negative = target.data().neg().add_scalar(1.).mul(&log_one_minus_p);
+    let op_result = positive.add(&negative).neg();
+
+    let losses = probs.tape.tensor_from_op_result_and_data(op_result, op_data);
+    reduce(apply_weights(losses, weights), reduction)
+}
+
+/// Binary cross entropy on logits, fusing the sigmoid. Computed as
+/// max(x, 0) - x * target + log(1 + exp(-|x|)), which does not overflow, and with gradient
+/// sigmoid(x) - target. `target` receives the gradient -x.
+//noinspection DuplicatedCode
+pub fn bce_with_logits<'t, T: TensorBackend>(logits: &TrackedTensor<'t, T>, target: &TrackedTensor<'t, T>, weights: Option<&T>, reduction: Reduction) -> TrackedTensor<'t, T> {
+    let logits_data = logits.data().clone();
+    let target_data = target.data().clone();
+    let logits_grad_fn: GradFn<T> = GradFn(Box::new(
+        move |child_grad: T, self_grad: &mut T| {
+            let grad = child_grad.mul(&logits_data.sigmoid().sub(&target_data));
+            *self_grad = self_grad.add(&grad);
+        },
+    ));
+    let logits_data = logits.data().clone();
+    let target_grad_fn: GradFn<T> = GradFn(Box::new(
+        move |child_grad: T, self_grad: &mut T| {
+            let grad = child_grad.mul(&logits_data.neg());
+            *self_grad = self_grad.add(&grad);
+        },
+    ));
+
+    let logits_blueprint = logits.self_gradient_blueprint(logits_grad_fn);
+    let target_blueprint = target.self_gradient_blueprint(target_grad_fn);
+
+    let op_data =
+        OpData::from_blueprints(vec![logits_blueprint, target_blueprint], "BceWithLogits".to_string());
+
+    let mut positive_part = logits.data().clone();
+    positive_part.map_inplace(|x| *x = x.max(0.));
+    let mut log_term = logits.data().abs().neg().exp();
+    log_term.map_inplace(|x| *x = x.ln_1p());
+    let op_result = positive_part.sub(&logits.data().mul(target.data())).add(&log_term);
+
+    let losses = logits.tape.tensor_from_op_result_and_data(op_result, op_data);
+    reduce(apply_weights(losses, weights), reduction)
+}
+
+
+#[cfg(test)]
+mod losses_tests {
+    use super::*;
+    use crate::ops::testing::{assert_close, validate_grad};
+    use crate::tensor_backends::NdArray;
+    use crate::tape::ComputationRecord;
+
+    fn logits(t: &ComputationRecord<NdArray>) -> TrackedTensor<'_, NdArray> {
+        let mut data = NdArray::from_slice(&[1., 2., 3., 1., 0., -1.]);
+        data.reshape(&[2, 3]);
+        t.tensor_from_value(data)
+    }
+
+    #[test]
+    fn cross_entropy_test() {
+        let t: ComputationRecord<NdArray> = ComputationRecord::new();
+        let weights = NdArray::from_slice(&[1., 2., 3.]);
+        // Both samples have loss log(1 + e^-1 + e^-2)
+        let loss = cross_entropy(&logits(&t), &[2, 0], None, Reduction::Mean);
+        assert_close(&loss.data().to_vec(), &[0.407_606]);
+        let loss = cross_entropy(&logits(&t), &[2, 0], Some(&weights), Reduction::Sum);
+        assert_close(&loss.data().to_vec(), &[4. * 0.407_606]);
+        let loss = cross_entropy(&logits(&t), &[2, 0], Some(&weights), Reduction::None);
+        assert_eq!(loss.shape(), &[2]);
+        assert_close(&loss.data().to_vec(), &[3. * 0.407_606, 0.407_606]);
+
+        validate_grad(logits(&t), &|x| cross_entropy(x, &[2, 0], None, Reduction::Mean));
+        validate_grad(logits(&t), &|x| cross_entropy(x, &[1, 0], Some(&weights), Reduction::Mean));
+        validate_grad(logits(&t), &|x| cross_entropy(x, &[0, 2], Some(&weights), Reduction::Sum));
+        validate_grad(logits(&t), &|x| {
+            let losses = cross_entropy(x, &[1, 2], None, Reduction::None);
+            sum(&mul(&losses, &x.tape.tensor_from_slice(&[2., -1.])))
+        });
+    }
+
+    #[test]
+    fn cross_entropy_large_logits() {
+        let t: ComputationRecord<NdArray> = ComputationRecord::new();
+        let mut data = NdArray::from_slice(&[1000., 0., -1000., 500., 501., 502.]);
+        data.reshape(&[2, 3]);
+        let input = t.tensor_from_value(data);
+        let loss = cross_entropy(&input, &[0, 2], None, Reduction::Sum);
+        assert_close(&loss.data().to_vec(), &[0.407_606]);
+        let grad = loss.grad().wrt(&input);
+        assert_close(&[grad.data().index(&[0, 0])], &[0.]);
+        assert_close(&[grad.data().index(&[1, 2])], &[0.665_240_9 - 1.]);
+    }
+
+    #[test]
+    fn nll_loss_test() {
+        let t: ComputationRecord<NdArray> = ComputationRecord::new();
+        let weights = NdArray::from_slice(&[0.5, 2., 1.]);
+        validate_grad(logits(&t), &|x| nll_loss(x, &[2, 0], Some(&weights), Reduction::Mean));
+        validate_grad(logits(&t), &|x| nll_loss(&log_softmax(x, 1), &[1, 0], None, Reduction::Sum));
+
+        // log_softmax followed by nll_loss is cross entropy
+        let input = logits(&t);
+        let composed = nll_loss(&log_softmax(&input, 1), &[1, 2], Some(&weights), Reduction::Mean);
+        let fused = cross_entropy(&input, &[1, 2], Some(&weights), Reduction::Mean);
+        assert_close(&composed.data().to_vec(), &fused.data().to_vec());
+        let composed_grad = composed.grad().wrt(&input);
+        let fused_grad = fused.grad().wrt(&input);
+        assert_close(&composed_grad.data().to_vec(), &fused_grad.data().to_vec());
+    }
+
+    #[test]
+    fn regression_losses_test() {
+        let t: ComputationRecord<NdArray> = ComputationRecord::new();
+        let input = t.tensor_from_slice(&[0.5, -1., 3.]);
+        let target = t.tensor_from_slice(&[0., 1., 1.2]);
+        // Differences 0.5, -2, 1.8
+        assert_close(&mse_loss(&input, &target, None, Reduction::Mean).data().to_vec(), &[(0.25 + 4. + 3.24) / 3.]);
+        assert_close(&l1_loss(&input, &target, None, Reduction::Sum).data().to_vec(), &[4.3]);
+        let smooth = smooth_l1_loss(&input, &target, 1., None, Reduction::None);
+        let huber = huber_loss(&input, &target, 2., None, Reduction::None);
+        assert_close(&smooth.data().to_vec(), &[0.125, 1.5, 1.3]);
+        assert_close(&huber.data().to_vec(), &[0.125, 2., 1.62]);
+
+        let weights = NdArray::from_slice(&[1., 0.5, 2.]);
+        let values = [0.3, -1., 2.5];
+        let target = || t.tensor_from_slice(&[0., 1., 1.2]);
+        validate_grad(t.tensor_from_slice(&values), &|x| mse_loss(x, &x.tape.tensor_from_slice(&[0., 1., 1.2]), Some(&weights), Reduction::Mean));
+        validate_grad(t.tensor_from_slice(&values), &|x| l1_loss(x, &x.tape.tensor_from_slice(&[0., 1., 1.2]), None, Reduction::Sum));
+        validate_grad(t.tensor_from_slice(&values), &|x| smooth_l1_loss(x, &x.tape.tensor_from_slice(&[0., 1., 1.2]), 1., None, Reduction::Mean));
+        validate_grad(t.tensor_from_slice(&values), &|x| huber_loss(x, &x.tape.tensor_from_slice(&[0., 1., 1.2]), 0.5, Some(&weights), Reduction::Sum));
+        // The target receives a gradient as well
+        validate_grad(target(), &|y| mse_loss(&y.tape.tensor_from_slice(&values), y, None, Reduction::Sum));
+    }
+
+    #[test]
+    fn binary_cross_entropy_test() {
+        let t: ComputationRecord<NdArray> = ComputationRecord::new();
+        let weights = NdArray::from_slice(&[1., 2., 0.5]);
+        let logits = [-1., 0.5, 2.];
+        let target = [0., 1., 0.3];
+
+        validate_grad(t.tensor_from_slice(&[0.2, 0.6, 0.9]), &|p| binary_cross_entropy(p, &p.tape.tensor_from_slice(&target), Some(&weights), Reduction::Mean));
+        validate_grad(t.tensor_from_slice(&logits), &|x| bce_with_logits(x, &x.tape.tensor_from_slice(&target), Some(&weights), Reduction::Sum));
+        validate_grad(t.tensor_from_slice(&target), &|y| bce_with_logits(&y.tape.tensor_from_slice(&logits), y, None, Reduction::Mean));
+
+        // Fusing the sigmoid gives the same values
+        let x = t.tensor_from_slice(&logits);
+        let y = t.tensor_from_slice(&target);
+        let fused = bce_with_logits(&x, &y, None, Reduction::None);
+        let composed = binary_cross_entropy(&sigmoid(&x), &y, None, Reduction::None);
+        assert_close(&fused.data().to_vec(), &composed.data().to_vec());
+
+        // Saturated inputs stay finite
+        let x = t.tensor_from_slice(&[-200., 200.]);
+        let y = t.tensor_from_slice(&[1., 0.]);
+        assert_eq!(bce_with_logits(&x, &y, None, Reduction::None).data(), &NdArray::from_slice(&[200., 200.]));
+        let probs = sigmoid(&x);
+        let loss = binary_cross_entropy(&probs, &y, None, Reduction::None);
+        assert_eq!(loss.data(), &NdArray::from_slice(&[100., 100.]));
+        let grad = sum(&loss).grad();
+        let probs_grad = grad.wrt(&probs);
+        assert!(probs_grad.data().index(&[0]) < 0. && probs_grad.data().index(&[1]) > 0.);
+        for i in 0..2 {
+            assert!(probs_grad.data().index(&[i]).is_finite());
+            assert!(grad.wrt(&x).data().index(&[i]).is_finite());
+            assert!(grad.wrt(&y).data().index(&[i]).is_finite());
+        }
+    }
+
+    #[test]
+    fn zero_total_class_weight() {
+        let t: ComputationRecord<NdArray> = ComputationRecord::new();
+        let weights = NdArray::from_slice(&[0., 1., 1.]);
+        let input = logits(&t);
+        let loss = cross_entropy(&input, &[0, 0], Some(&weights), Reduction::Mean);
+        assert_eq!(loss.data(), &NdArray::from_slice(&[0.]));
+        assert_eq!(loss.grad().wrt(&input).data(), &NdArray::zeros(&[2, 3]));
+    }
+}
@@ -0,0 +1,270 @@
+use crate::TrackedTensor;
+use crate::tensor_backends::TensorBackend;
+use std::collections::HashMap;
+
+
+/// A building block of a model: something with a forward pass and (possibly) parameters.
+///
+/// Parameters are TrackedTensors of the ComputationRecord the module was built on and they are
+/// named by their key in the parameter store, like "encoder.fc1.weight". Modules made of other
+/// modules, like a user struct with a few layers, return them in `children` so parameters can be
+/// collected recursively.
+pub trait Module<'t, T: TensorBackend> {
+    fn forward(&self, input: &TrackedTensor<'t, T>) -> TrackedTensor<'t, T>;
+
+    /// Parameters owned directly by this module, not by its children
+    fn local_parameters(&self) -> Vec<(String, &TrackedTensor<'t, T>)> {
+        vec![]
+    }
+
+    fn children(&self) -> Vec<&dyn Module<'t, T>> {
+        vec![]
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut dyn Module<'t, T>> {
+        vec![]
+    }
+
+    fn is_training(&self) -> bool;
+
+    /// Sets the mode of this module only, use `train` or `eval` to also set it for the children
+    fn set_training(&mut self, training: bool);
+
+    fn train(&mut self) {
+        self.set_training(true);
+        for child in self.children_mut() {
+            child.train();
+        }
+    }
+
+    fn eval(&mut self) {
+        self.set_training(false);
+        for child in self.children_mut() {
+            child.eval();
+        }
+    }
+
+    /// Parameters of this module followed by the ones of its children, depth first
+    fn named_parameters(&self) -> Vec<(String, &TrackedTensor<'t, T>)> {
+        let mut params = self.local_parameters();
+        for child in self.children() {
+            params.extend(child.named_parameters());
+        }
+        params
+    }
+
+    fn parameters(&self) -> Vec<&TrackedTensor<'t, T>> {
+        self.named_parameters().into_iter().map(|(_name, param)| param).collect()
+    }
+
+    /// Total number of scalar values in the parameters
+    fn num_parameters(&self) -> usize {
+        self.parameters().iter().map(|param| param.shape().iter().product::<usize>()).sum()
+    }
+
+    /// Copy of the parameter values keyed by name, in the same format as the parameter store
+    fn state_dict(&self) -> HashMap<String, T> {
+        self.named_parameters().into_iter()
+            .map(|(name, param)| (name, param.data().clone()))
+            .collect()
+    }
+}
+
+/// Applies its modules one after the other
+pub struct Sequential<'t, T: TensorBackend> {
+    modules: Vec<Box<dyn Module<'t, T> + 't>>,
+    training: bool,
+}
+
+impl <'t, T: TensorBackend> Default for Sequential<'t, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl <'t, T: TensorBackend> Sequential<'t, T> {
+    pub fn new() -> Self {
+        Sequential {
+            modules: vec![],
+            training: true,
+        }
+    }
+
+    /// Appends a module, for chaining: `Sequential::new().with(fc1).with_fn(relu).with(fc2)`
+    pub fn with<M: Module<'t, T> + 't>(mut self, module: M) -> Self {
+        self.push(module);
+        self
+    }
+
+    /// Appends a parameterless function like an activation
+    pub fn with_fn<F: Fn(&TrackedTensor<'t, T>) -> TrackedTensor<'t, T> + 't>(self, function: F) -> Self {
+        self.with(Lambda::new(function))
+    }
+
+    pub fn push<M: Module<'t, T> + 't>(&mut self, module: M) {
+        self.modules.push(Box::new(module));
+    }
+
+    pub fn len(&self) -> usize {
+        self.modules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.modules.is_empty()
+    }
+}
+
+impl <'t, T: TensorBackend> Module<'t, T> for Sequential<'t, T> {
+    fn forward(&self, input: &TrackedTensor<'t, T>) -> TrackedTensor<'t, T> {
+        let mut modules = self.modules.iter();
+        let first = match modules.next() {
+            None => panic!("Called forward on an empty Sequential"),
+            Some(first) => first.forward(input),
+        };
+        modules.fold(first, |output, module| module.forward(&output))
+    }
+
+    fn children(&self) -> Vec<&dyn Module<'t, T>> {
+        self.modules.iter().map(|module| module.as_ref() as &dyn Module<'t, T>).collect()
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut dyn Module<'t, T>> {
+        self.modules.iter_mut().map(|module| module.as_mut() as &mut dyn Module<'t, T>).collect()
+    }
+
+    fn is_training(&self) -> bool {
+        self.training
+    }
+
+    fn set_training(&mut self, training: bool) {
+        self.training = training;
+    }
+}
+
+/// Module without parameters wrapping a function, like `relu` or `|x| leaky_relu(x, 0.1)`
+pub struct Lambda<'t, T: TensorBackend> {
+    #[allow(clippy::type_complexity)]
+    function: Box<dyn Fn(&TrackedTensor<'t, T>) -> TrackedTensor<'t, T> + 't>,
+    training: bool,
+}
+
+impl <'t, T: TensorBackend> Lambda<'t, T> {
+    pub fn new<F: Fn(&TrackedTensor<'t, T>) -> TrackedTensor<'t, T> + 't>(function: F) -> Self {
+        Lambda {
+            function: Box::new(function),
+            training: true,
+        }
+    }
+}
+
+impl <'t, T: TensorBackend> Module<'t, T> for Lambda<'t, T> {
+    fn forward(&self, input: &TrackedTensor<'t, T>) -> TrackedTensor<'t, T> {
+        (self.function)(input)
+    }
+
+    fn is_training(&self) -> bool {
+        self.training
+    }
+
+    fn set_training(&mut self, training: bool) {
+        self.training = training;
+    }
+}
+
+
+#[cfg(test)]
+mod module_tests {
+    use super::*;
+    use crate::tape::ComputationRecord;
+    use crate::tensor_backends::NdArray;
+    use crate::layers::{LinearConfig, LinearLayer};
+    use crate::ops::*;
+
+    /// A user defined model made of other modules
+    struct Mlp<'t> {
+        hidden: Sequential<'t, NdArray>,
+        head: LinearLayer<'t, NdArray>,
+        training: bool,
+    }
+
+    impl <'t> Mlp<'t> {
+        fn new(rec: &'t ComputationRecord<NdArray>, store: &HashMap<String, NdArray>) -> Self {
+            let named = |name: &str, in_size, out_size| LinearConfig {
+                name: Some(name.to_string()),
+                ..LinearConfig::new(in_size, out_size)
+            };
+            Mlp {
+                hidden: Sequential::new()
+                    .with(LinearLayer::from_config(rec, named("mlp.hidden.0", 3, 4), store))
+                    .with_fn(relu)
+                    .with(LinearLayer::from_config(rec, named("mlp.hidden.2", 4, 4), store))
+                    .with_fn(|x| leaky_relu(x, 0.1)),
+                head: LinearLayer::from_config(rec, LinearConfig { bias: false, ..named("mlp.head", 4, 2) }, store),
+                training: true,
+            }
+        }
+    }
+
+    impl <'t> Module<'t, NdArray> for Mlp<'t> {
+        fn forward(&self, input: &TrackedTensor<'t, NdArray>) -> TrackedTensor<'t, NdArray> {
+            self.head.forward(&self.hidden.forward(input))
+        }
+
+        fn children(&self) -> Vec<&dyn Module<'t, NdArray>> {
+            vec![&self.hidden, &self.head]
+        }
+
+        fn children_mut(&mut self) -> Vec<&mut dyn Module<'t, NdArray>> {
+            vec![&mut self.hidden, &mut self.head]
+        }
+
+        fn is_training(&self) -> bool {
+            self.training
+        }
+
+        fn set_training(&mut self, training: bool) {
+            self.training = training;
+        }
+    }
+
+    #[test]
+    fn nested_parameters() {
+        let rec: ComputationRecord<NdArray> = ComputationRecord::new();
+        let model = Mlp::new(&rec, &HashMap::new());
+        let names: Vec<String> = model.named_parameters().into_iter().map(|(name, _param)| name).collect();
+        assert_eq!(names, vec!["mlp.hidden.0.weight", "mlp.hidden.0.bias", "mlp.hidden.2.weight", "mlp.hidden.2.bias", "mlp.head.weight"]);
+        assert_eq!(model.num_parameters(), 3 * 4 + 4 + 4 * 4 + 4 + 4 * 2);
+
+        // A model built from its own state dict computes the same output
+        let state = model.state_dict();
+        let loaded = Mlp::new(&rec, &state);
+        let mut data = NdArray::from_slice(&[1., 2., 3., -1., 0., 1.]);
+        data.reshape(&[2, 3]);
+        let input = rec.tensor_from_value(data);
+        assert_eq!(model.forward(&input).data(), loaded.forward(&input).data());
+        assert_eq!(model.forward(&input).shape(), &[2, 2]);
+    }
+
+    #[test]
+    fn train_eval_propagates() {
+        let rec: ComputationRecord<NdArray> = ComputationRecord::new();
+        let mut model = Mlp::new(&rec, &HashMap::new());
+        model.eval();
+        assert!(!model.is_training());
+        assert!(model.hidden.children().iter().all(|child| !child.is_training()));
+        assert!(!model.head.is_training());
+        model.train();
+        assert!(model.hidden.children().iter().all(|child| child.is_training()));
+    }
+
+    #[test]
+    fn gradients_reach_all_parameters() {
+        let rec: ComputationRecord<NdArray> = ComputationRecord::new();
+        let model = Mlp::new(&rec, &HashMap::new());
+        let loss = sum(&model.forward(&rec.tensor_from_slice(&[0.1, 0.2, 0.3])));
+        let grad = loss.grad();
+        for (name, param) in model.named_parameters() {
+            assert_eq!(grad.wrt(param).shape(), param.shape(), "{}", name);
+        }
+    }
+}
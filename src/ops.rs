@@ -1,17 +1,46 @@
 mod mul;
 pub use mul::mul;
 mod index;
+pub use index::index;
 mod add;
 pub use add::add;
+mod sub;
+pub use sub::sub;
+mod div;
+pub use div::div;
+mod scalar;
+pub use scalar::{add_scalar, mul_scalar, div_scalar, rsub};
 mod sum;
 pub use sum::sum;
-mod testing;
+#[cfg(test)]
+pub(crate) mod testing;
 
 mod matmul;
 pub use matmul::matmul;
 
 mod relu;
-pub use relu::relu;
+pub use relu::{relu, leaky_relu};
 
-mod logsoftmax;
-pub use logsoftmax::logsoftmax;
+mod activations;
+pub use activations::{elu, selu, gelu, gelu_tanh, silu, hardtanh, mish};
+
+pub(crate) mod softmax;
+pub use softmax::{softmax, log_softmax};
+
+mod reduce;
+pub use reduce::{sum_axis, mean_axis, mean, max_axis, min_axis, prod_axis};
+
+mod slice;
+pub use slice::slice;
+
+mod cat;
+pub use cat::{cat, stack};
+
+mod split;
+pub use split::{split, chunk};
+
+mod reshape;
+pub use reshape::{reshape, flatten, squeeze, unsqueeze, permute, transpose, broadcast_to};
+
+pub(crate) mod unary;
+pub use unary::{exp, log, sqrt, pow, abs, neg, sin, cos, tanh, sigmoid, softplus, clamp, reciprocal, square};
@@ -0,0 +1,147 @@
+use crate::TrackedTensor;
+use crate::tensor_backends::TensorBackend;
+use crate::ops::unary::{clamp, unary_op};
+
+const SELU_ALPHA: f32 = 1.673_263_2;
+const SELU_SCALE: f32 = 1.050_701;
+/// sqrt(2 / pi), used by the tanh approximation of GELU
+const GELU_TANH_SCALE: f32 = 0.797_884_6;
+const GELU_TANH_CUBIC: f32 = 0.044_715;
+
+/// Applies `f` to every element of a copy of `data`
+fn map<T: TensorBackend, F: Fn(f32) -> f32>(data: &T, f: F) -> T {
+    let mut result = data.clone();
+    result.map_inplace(|x| *x = f(*x));
+    result
+}
+
+/// Error function, Abramowitz and Stegun formula 7.1.26 (max error 1.5e-7)
+fn erf(x: f32) -> f32 {
+    let t = 1. / (1. + 0.327_591_1 * x.abs());
+    let polynomial = t * (0.254_829_6 + t * (-0.284_496_74 + t * (1.421_413_8 + t * (-1.453_152_1 + t * 1.061_405_4))));
+    let erf_abs = 1. - polynomial * (-x * x).exp();
+    if x >= 0. { erf_abs } else { -erf_abs }
+}
+
+/// x for x > 0 and alpha * (exp(x) - 1) otherwise
+pub fn elu<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>, alpha: f32) -> TrackedTensor<'t, T> {
+    let op_result = map(input.data(), |x| if x > 0. { x } else { alpha * x.exp_m1() });
+    unary_op(input, op_result, "Elu", move |input, _output| {
+        map(input, |x| if x > 0. { 1. } else { alpha * x.exp() })
+    })
+}
+
+/// Scaled ELU with the constants from "Self-Normalizing Neural Networks"
+pub fn selu<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>) -> TrackedTensor<'t, T> {
+    let op_result = map(input.data(), |x| SELU_SCALE * if x > 0. { x } else { SELU_ALPHA * x.exp_m1() });
+    unary_op(input, op_result, "Selu", |input, _output| {
+        map(input, |x| SELU_SCALE * if x > 0. { 1. } else { SELU_ALPHA * x.exp() })
+    })
+}
+
+/// x * Φ(x) where Φ is the standard normal CDF
+pub fn gelu<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>) -> TrackedTensor<'t, T> {
+    let cdf = |x: f32| 0.5 * (1. + erf(x * std::f32::consts::FRAC_1_SQRT_2));
+    let op_result = map(input.data(), |x| x * cdf(x));
+    unary_op(input, op_result, "Gelu", move |input, _output| {
+        map(input, |x| {
+            let pdf = (-0.5 * x * x).exp() * std::f32::consts::FRAC_2_SQRT_PI * std::f32::consts::FRAC_1_SQRT_2 * 0.5;
+            cdf(x) + x * pdf
+        })
+    })
+}
+
+/// GELU using the approximation 0.5 * x * (1 + tanh(sqrt(2 / pi) * (x + 0.044715 * x^3)))
+pub fn gelu_tanh<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>) -> TrackedTensor<'t, T> {
+    let inner = |x: f32| GELU_TANH_SCALE * (x + GELU_TANH_CUBIC * x * x * x);
+    let op_result = map(input.data(), |x| 0.5 * x * (1. + inner(x).tanh()));
+    unary_op(input, op_result, "GeluTanh", move |input, _output| {
+        map(input, |x| {
+            let tanh = inner(x).tanh();
+            let inner_derivative = GELU_TANH_SCALE * (1. + 3. * GELU_TANH_CUBIC * x * x);
+            0.5 * (1. + tanh) + 0.5 * x * (1. - tanh * tanh) * inner_derivative
+        })
+    })
+}
+
+/// x * sigmoid(x), also known as swish
+pub fn silu<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>) -> TrackedTensor<'t, T> {
+    let op_result = input.data().mul(&input.data().sigmoid());
+    unary_op(input, op_result, "Silu", |input, _output| {
+        // sigmoid(x) * (1 + x * (1 - sigmoid(x)))
+        let sigmoid = input.sigmoid();
+        sigmoid.add(&input.mul(&sigmoid).mul(&sigmoid.neg().add_scalar(1.)))
+    })
+}
+
+/// Limits the values to [min, max], the same as `clamp`
+pub fn hardtanh<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>, min: f32, max: f32) -> TrackedTensor<'t, T> {
+    clamp(input, min, max)
+}
+
+/// x * tanh(softplus(x))
+pub fn mish<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>) -> TrackedTensor<'t, T> {
+    let op_result = input.data().mul(&input.data().softplus().tanh());
+    unary_op(input, op_result, "Mish", |input, _output| {
+        // tanh(softplus(x)) + x * (1 - tanh(softplus(x))^2) * sigmoid(x)
+        let tanh = input.softplus().tanh();
+        tanh.add(&input.mul(&tanh.square().neg().add_scalar(1.)).mul(&input.sigmoid()))
+    })
+}
+
+
+#[cfg(test)]
+mod activations_tests {
+    use super::*;
+    use crate::ops::testing::{assert_close, check};
+    use crate::tensor_backends::NdArray;
+    use crate::tape::ComputationRecord;
+    use crate::ops::sum::sum;
+
+    const VALUES: [f32; 5] = [-2., -0.5, 0.1, 1., 2.5];
+
+    #[test]
+    fn elu_test() {
+        check(&VALUES, &|x| elu(x, 1.5));
+        let t: ComputationRecord<NdArray> = ComputationRecord::new();
+        assert_close(&elu(&t.tensor_from_slice(&[-1., 2.]), 1.).data().to_vec(), &[-0.632_120_6, 2.]);
+    }
+
+    #[test]
+    fn selu_test() {
+        check(&VALUES, &|x| selu(x));
+        let t: ComputationRecord<NdArray> = ComputationRecord::new();
+        assert_close(&selu(&t.tensor_from_slice(&[-1., 2.])).data().to_vec(), &[-1.111_330_8, 2.101_402]);
+    }
+
+    #[test]
+    fn gelu_test() {
+        check(&VALUES, &|x| gelu(x));
+        check(&VALUES, &|x| gelu_tanh(x));
+        let t: ComputationRecord<NdArray> = ComputationRecord::new();
+        let input = t.tensor_from_slice(&[-1., 0.5, 2.]);
+        assert_close(&gelu(&input).data().to_vec(), &[-0.158_655_3, 0.345_731_4, 1.954_5]);
+        assert_close(&gelu_tanh(&input).data().to_vec(), &[-0.158_808, 0.345_714, 1.954_597_7]);
+    }
+
+    #[test]
+    fn silu_test() {
+        check(&VALUES, &|x| silu(x));
+    }
+
+    #[test]
+    fn hardtanh_test() {
+        check(&VALUES, &|x| hardtanh(x, -1., 2.));
+        // The bounds themselves pass the gradient through, like clamp
+        let t: ComputationRecord<NdArray> = ComputationRecord::new();
+        let input = t.tensor_from_slice(&[-1., 2., 3.]);
+        assert_eq!(sum(&hardtanh(&input, -1., 2.)).grad().wrt(&input).data(), &NdArray::from_slice(&[1., 1., 0.]));
+    }
+
+    #[test]
+    fn mish_test() {
+        check(&VALUES, &|x| mish(x));
+        let t: ComputationRecord<NdArray> = ComputationRecord::new();
+        assert_close(&mish(&t.tensor_from_slice(&[-1., 1.])).data().to_vec(), &[-0.303_401_4, 0.865_098_4]);
+    }
+}
@@ -23,7 +23,7 @@ pub fn add<'t, T: TensorBackend>(left: &TrackedTensor<'t, T>, other: &TrackedTen
     let op_data =
         OpData::from_blueprints(vec![left_blueprint, right_blueprint], "Add".to_string());
 
-    let op_result = left.data().add(&other.data());
+    let op_result = left.data().add(other.data());
     left.tape.tensor_from_op_result_and_data(op_result, op_data)
 }
 
@@ -39,8 +39,8 @@ mod add_tests {
     fn add_twice<'t>(input: &TrackedTensor<'t, NdArray>) -> TrackedTensor<'t, NdArray> {
         // [2.] + in + in
         let input_1 = input.tape.tensor_from_slice(&[2.]);
-        let x = add(&input, &input_1);
-        let y = add(&x, &input);
+        let x = add(input, &input_1);
+        let y = add(&x, input);
         y
     }
 
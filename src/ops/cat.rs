@@ -0,0 +1,92 @@
+use crate::{GradFn, OpData, TrackedTensor};
+use crate::tensor_backends::TensorBackend;
+use crate::tensor_backends::slicing::{self, SliceElem};
+
+/// Joins Tensors along an existing axis, all other dimensions must match
+//noinspection DuplicatedCode
+pub fn cat<'t, T: TensorBackend>(tensors: &[&TrackedTensor<'t, T>], axis: usize) -> TrackedTensor<'t, T> {
+    assert!(!tensors.is_empty(), "Need at least one Tensor to concatenate");
+    let mut blueprints = vec![];
+    let mut start = 0;
+    for tensor in tensors {
+        // Each operand gets back the part of the gradient where it was placed
+        let end = start + tensor.shape()[axis];
+        let info = slicing::along_axis(axis, SliceElem::range(start as isize, end as isize));
+        let grad_fn: GradFn<T> = GradFn(Box::new(
+            move |child_grad: T, self_grad: &mut T| {
+                *self_grad = self_grad.add(&child_grad.slice(&info));
+            },
+        ));
+        blueprints.push(tensor.self_gradient_blueprint(grad_fn));
+        start = end;
+    }
+
+    let op_data =
+        OpData::from_blueprints(blueprints, "Cat".to_string());
+
+    let datas: Vec<&T> = tensors.iter().map(|tensor| tensor.data()).collect();
+    let op_result = T::cat(&datas, axis);
+    tensors[0].tape.tensor_from_op_result_and_data(op_result, op_data)
+}
+
+/// Joins Tensors of the same shape along a new axis inserted at `axis`
+//noinspection DuplicatedCode
+pub fn stack<'t, T: TensorBackend>(tensors: &[&TrackedTensor<'t, T>], axis: usize) -> TrackedTensor<'t, T> {
+    assert!(!tensors.is_empty(), "Need at least one Tensor to stack");
+    let mut blueprints = vec![];
+    for (position, tensor) in tensors.iter().enumerate() {
+        let info = slicing::along_axis(axis, SliceElem::Index(position as isize));
+        let grad_fn: GradFn<T> = GradFn(Box::new(
+            move |child_grad: T, self_grad: &mut T| {
+                *self_grad = self_grad.add(&child_grad.slice(&info));
+            },
+        ));
+        blueprints.push(tensor.self_gradient_blueprint(grad_fn));
+    }
+
+    let op_data =
+        OpData::from_blueprints(blueprints, "Stack".to_string());
+
+    let datas: Vec<&T> = tensors.iter().map(|tensor| tensor.data()).collect();
+    let op_result = T::stack(&datas, axis);
+    tensors[0].tape.tensor_from_op_result_and_data(op_result, op_data)
+}
+
+
+#[cfg(test)]
+mod cat_tests {
+    use super::*;
+    use crate::ops::testing::{validate_grad, weighted_sum};
+    use crate::ops::*;
+    use crate::tensor_backends::NdArray;
+    use crate::tape::ComputationRecord;
+
+    fn cat_comp<'t>(input: &TrackedTensor<'t, NdArray>) -> TrackedTensor<'t, NdArray> {
+        // [x, x * x, x] along the columns
+        let squared = mul(input, input);
+        let joined = cat(&[input, &squared, input], 1);
+        weighted_sum(&joined)
+    }
+
+    fn stack_comp<'t>(input: &TrackedTensor<'t, NdArray>) -> TrackedTensor<'t, NdArray> {
+        let squared = mul(input, input);
+        let joined = stack(&[&squared, input], 1);
+        weighted_sum(&joined)
+    }
+
+    #[test]
+    fn cat_test() {
+        let t: ComputationRecord<NdArray> = ComputationRecord::new();
+        let mut data = NdArray::from_slice(&[1., 2., 3., 4.]);
+        data.reshape(&[2, 2]);
+        validate_grad(t.tensor_from_value(data), &cat_comp);
+    }
+
+    #[test]
+    fn stack_test() {
+        let t: ComputationRecord<NdArray> = ComputationRecord::new();
+        let mut data = NdArray::from_slice(&[1., 2., 3., 4.]);
+        data.reshape(&[2, 2]);
+        validate_grad(t.tensor_from_value(data), &stack_comp);
+    }
+}
@@ -0,0 +1,72 @@
+use crate::{GradFn, OpData, TrackedTensor};
+use crate::tensor_backends::TensorBackend;
+
+/// Element wise division. Where the divisor is zero the result follows IEEE (inf or NaN) but
+/// the gradient of both operands is taken as 0 there, so a single bad element does not turn
+/// every gradient flowing through the graph into NaN.
+//noinspection DuplicatedCode
+pub fn div<'t, T: TensorBackend>(left: &TrackedTensor<'t, T>, other: &TrackedTensor<'t, T>) -> TrackedTensor<'t, T> {
+    let right_val = other.data().clone();
+    let grad_fn_left: GradFn<T> = GradFn(Box::new(
+        move |child_grad: T, self_grad: &mut T| {
+            // d(a/b)/da = 1/b
+            let grad = child_grad.zip_map(&right_val, |g, b| if b == 0. { 0. } else { g / b });
+            *self_grad = self_grad.add(&grad);
+        },
+    ));
+
+    let left_val = left.data().clone();
+    let right_val = other.data().clone();
+    let grad_fn_right: GradFn<T> = GradFn(Box::new(
+        move |child_grad: T, self_grad: &mut T| {
+            // d(a/b)/db = -a/b^2
+            let derivative = left_val.zip_map(&right_val, |a, b| if b == 0. { 0. } else { -a / (b * b) });
+            *self_grad = self_grad.add(&child_grad.mul(&derivative));
+        },
+    ));
+
+    let left_blueprint = left.self_gradient_blueprint(grad_fn_left);
+    let right_blueprint = other.self_gradient_blueprint(grad_fn_right);
+
+    let op_data =
+        OpData::from_blueprints(vec![left_blueprint, right_blueprint], "Div".to_string());
+
+    let op_result = left.data().div(other.data());
+
+    left.tape.tensor_from_op_result_and_data(op_result, op_data)
+}
+
+
+#[cfg(test)]
+mod div_tests {
+    use super::*;
+    use crate::ops::testing::validate_grad;
+    use crate::ops::*;
+    use crate::tensor_backends::NdArray;
+    use crate::tape::ComputationRecord;
+
+    fn div_comp<'t>(input: &TrackedTensor<'t, NdArray>) -> TrackedTensor<'t, NdArray> {
+        // sum([2, 3] / in + in / [4, 5])
+        let numerator = input.tape.tensor_from_slice(&[2., 3.]);
+        let denominator = input.tape.tensor_from_slice(&[4., 5.]);
+        sum(&add(&div(&numerator, input), &div(input, &denominator)))
+    }
+
+    #[test]
+    fn div_test() {
+        let t: ComputationRecord<NdArray> = ComputationRecord::new();
+        let input_0 = t.tensor_from_slice(&[2., -3.]);
+        validate_grad(input_0, &div_comp);
+    }
+
+    #[test]
+    fn div_by_zero_has_finite_gradient() {
+        let t: ComputationRecord<NdArray> = ComputationRecord::new();
+        let numerator = t.tensor_from_slice(&[1., 2.]);
+        let denominator = t.tensor_from_slice(&[0., 4.]);
+        let output = sum(&div(&numerator, &denominator));
+        let grad = output.grad();
+        assert_eq!(grad.wrt(&numerator).data(), &NdArray::from_slice(&[0., 0.25]));
+        assert_eq!(grad.wrt(&denominator).data(), &NdArray::from_slice(&[0., -0.125]));
+    }
+}
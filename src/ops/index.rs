@@ -1,21 +1,46 @@
-// use crate::{GradFn, OpData, Tensor};
-// use crate::tensor_backends::TensorBackend;
-//
-// pub fn index<'t, T: TensorBackend>(tensor: &Tensor<'t, T>, index: &[usize]) -> Tensor<'t, T> {
-//     let grad_fn: GradFn<T> = GradFn(Box::new(
-//         move |child_grad: T, self_grad: &mut T| {
-//             self_grad.index(index) += child_grad[[0]];
-//         },
-//     ));
-//
-//     let operand_blueprint = tensor.self_gradient_blueprint(grad_fn);
-//
-//     let blueprints = vec![operand_blueprint];
-//
-//     let op_data = OpData::from_blueprints(blueprints, "Index".to_string());
-//     Tensor {
-//         tape: tensor.tape,
-//         value: tensor.value.index(index),
-//         parent_op_index: tensor.tape.push_op(op_data),
-//     }
-// }
\ No newline at end of file
+use crate::{GradFn, OpData, TrackedTensor};
+use crate::tensor_backends::TensorBackend;
+
+/// Picks a single element, the result has shape [1]
+pub fn index<'t, T: TensorBackend>(tensor: &TrackedTensor<'t, T>, index: &[usize]) -> TrackedTensor<'t, T> {
+    let closure_index = index.to_vec();
+    let grad_fn: GradFn<T> = GradFn(Box::new(
+        move |child_grad: T, self_grad: &mut T| {
+            *self_grad._index_mut(&closure_index) += child_grad.index(&[0]);
+        },
+    ));
+
+    let operand_blueprint = tensor.self_gradient_blueprint(grad_fn);
+
+    let blueprints = vec![operand_blueprint];
+
+    let op_data = OpData::from_blueprints(blueprints, "Index".to_string());
+
+    let op_result = T::from_slice(&[tensor.data().index(index)]);
+    tensor.tape.tensor_from_op_result_and_data(op_result, op_data)
+}
+
+
+#[cfg(test)]
+mod index_tests {
+    use super::*;
+    use crate::ops::testing::validate_grad;
+    use crate::ops::*;
+    use crate::tensor_backends::NdArray;
+    use crate::tape::ComputationRecord;
+
+    fn index_comp<'t>(input: &TrackedTensor<'t, NdArray>) -> TrackedTensor<'t, NdArray> {
+        let first = index(input, &[0, 1]);
+        let second = index(input, &[1, 0]);
+        mul(&first, &second)
+    }
+
+    #[test]
+    fn index_test() {
+        let t: ComputationRecord<NdArray> = ComputationRecord::new();
+        let mut data = NdArray::from_slice(&[1., 2., 3., 4.]);
+        data.reshape(&[2, 2]);
+        let input_0 = t.tensor_from_value(data);
+        validate_grad(input_0, &index_comp);
+    }
+}
@@ -1,28 +1,68 @@
 use crate::{GradFn, OpData, TrackedTensor};
 use crate::tensor_backends::TensorBackend;
+use crate::ops::reduce::sum_to_shape;
 
+/// Shape a matmul operand is promoted to: rank 1 left operands become [1, n] and rank 1 right
+/// operands [n, 1]
+fn promoted_shape(shape: &[usize], is_left: bool) -> Vec<usize> {
+    match (shape.len(), is_left) {
+        (1, true) => vec![1, shape[0]],
+        (1, false) => vec![shape[0], 1],
+        _ => shape.to_vec(),
+    }
+}
+
+/// Swaps the two matrix axes of a (possibly batched) matrix
+fn transpose_matrices<T: TensorBackend>(data: &T) -> T {
+    let rank = data.shape().len();
+    data.transpose(rank - 2, rank - 1)
+}
+
+/// Matrix product following NumPy's matmul semantics, see `TensorBackend::matmul`
 //noinspection DuplicatedCode
 pub fn matmul<'t, T: TensorBackend>(left: &TrackedTensor<'t, T>, right: &TrackedTensor<'t, T>) -> TrackedTensor<'t, T> {
+    // The gradients are computed on the promoted operands, where the output is a (batch of)
+    // matrix, and then brought back to the operand shape summing over broadcast batch axes
+    let mut left_promoted = left.data().clone();
+    left_promoted.reshape(&promoted_shape(left.shape(), true));
+    let mut right_promoted = right.data().clone();
+    right_promoted.reshape(&promoted_shape(right.shape(), false));
+    let mut op_result = left_promoted.matmul(&right_promoted);
+    let output_promoted_shape = op_result.shape().to_vec();
+    // Remove the axes added by the promotion, like the backend matmul of the unpromoted operands
+    let rank = output_promoted_shape.len();
+    let output_shape: Vec<usize> = output_promoted_shape.iter().enumerate()
+        .filter(|(axis, _dim)| !((left.shape().len() == 1 && *axis == rank - 2) || (right.shape().len() == 1 && *axis == rank - 1)))
+        .map(|(_axis, dim)| *dim)
+        .collect();
+    // The product of two vectors is a scalar, which has shape [1]
+    op_result.reshape(if output_shape.is_empty() { &[1] } else { &output_shape });
 
-    let right_data = right.data().clone();
+    let left_shape = left.shape().to_vec();
+    let left_promoted_shape = left_promoted.shape().to_vec();
+    let right_data = right_promoted.clone();
+    let grad_output_shape = output_promoted_shape.clone();
     let left_grad_fn_add: GradFn<T> = GradFn(Box::new(
         move |child_grad: T, self_grad: &mut T| {
-            // println!("Child grad: {:?}", child_grad);
-            // println!("Right: {:?}", right_data);
-            let mut right_data_clone = right_data.clone();
-            right_data_clone.t();
-            // println!("Right T : {:?}", right_data_clone);
-            let new_self_grad = child_grad.matmul2d(&right_data_clone);
+            let mut child_grad = child_grad;
+            child_grad.reshape(&grad_output_shape);
+            let new_self_grad = child_grad.matmul(&transpose_matrices(&right_data));
+            let mut new_self_grad = sum_to_shape(&new_self_grad, &left_promoted_shape);
+            new_self_grad.reshape(&left_shape);
             *self_grad = self_grad.add(&new_self_grad);
         },
     ));
 
-    let left_data = left.data().clone();
+    let right_shape = right.shape().to_vec();
+    let right_promoted_shape = right_promoted.shape().to_vec();
+    let left_data = left_promoted;
     let right_grad_fn_add: GradFn<T> = GradFn(Box::new(
         move |child_grad: T, self_grad: &mut T| {
-            let mut left_data_clone = left_data.clone();
-            left_data_clone.t();
-            let new_self_grad = left_data_clone.matmul2d(&child_grad);
+            let mut child_grad = child_grad;
+            child_grad.reshape(&output_promoted_shape);
+            let new_self_grad = transpose_matrices(&left_data).matmul(&child_grad);
+            let mut new_self_grad = sum_to_shape(&new_self_grad, &right_promoted_shape);
+            new_self_grad.reshape(&right_shape);
             *self_grad = self_grad.add(&new_self_grad);
         },
     ));
@@ -34,7 +74,6 @@ pub fn matmul<'t, T: TensorBackend>(left: &TrackedTensor<'t, T>, right: &Tracked
     let op_data =
         OpData::from_blueprints(vec![left_blueprint, right_blueprint], "Matmul".to_string());
 
-    let op_result = left.data().matmul2d(&right.data());
     left.tape.tensor_from_op_result_and_data(op_result, op_data)
 }
 
@@ -46,14 +85,15 @@ mod matmul_tests {
     use crate::tensor_backends::NdArray;
     use crate::tape::ComputationRecord;
     use crate::ops::sum::sum;
+    use crate::ops::{add, mul, reshape};
 
 
     fn matmul_compute<'t>(input: &TrackedTensor<'t, NdArray>) -> TrackedTensor<'t, NdArray> {
         let mut data = NdArray::from_slice(&[5., 6., 7., 8.]);
         data.reshape(&[2, 2]);
         let input_1 = input.tape.tensor_from_value(data);
-        let x = matmul(&input, &input_1);
-        let y = matmul(&x, &input);
+        let x = matmul(input, &input_1);
+        let y = matmul(&x, input);
         let z = sum(&y);
         z
     }
@@ -66,4 +106,53 @@ mod matmul_tests {
         let input_0 = t.tensor_from_value(data);
         validate_grad(input_0, &matmul_compute);
     }
+
+    fn constant<'t>(input: &TrackedTensor<'t, NdArray>, shape: &[usize]) -> TrackedTensor<'t, NdArray> {
+        let len = shape.iter().product::<usize>();
+        let mut data = NdArray::from_slice(&(0..len).map(|x| 0.5 - x as f32 * 0.1).collect::<Vec<f32>>());
+        data.reshape(shape);
+        input.tape.tensor_from_value(data)
+    }
+
+    fn batched_compute<'t>(input: &TrackedTensor<'t, NdArray>) -> TrackedTensor<'t, NdArray> {
+        // [B, T, D] x [D, H] with B = 2, T = 3, D = 2, H = 4, the right side is broadcast
+        let projection = constant(input, &[2, 4]);
+        let projected = matmul(input, &projection);
+        // [2, 3, 4] x [4] and [3] x [2, 3, 2] exercise the rank 1 promotions
+        let per_step = matmul(&projected, &constant(input, &[4]));
+        let mixed = matmul(&constant(input, &[3]), input);
+        add(&sum(&per_step), &sum(&mixed))
+    }
+
+    fn batched_right_compute<'t>(input: &TrackedTensor<'t, NdArray>) -> TrackedTensor<'t, NdArray> {
+        // [3, 2] x [2, 2, 2], the left side is broadcast over the batch of the right one
+        let left = constant(input, &[3, 2]);
+        let product = matmul(&left, input);
+        let weights = constant(input, &[2, 3, 2]);
+        sum(&mul(&product, &weights))
+    }
+
+    #[test]
+    fn batched_matmul_test() {
+        let t: ComputationRecord<NdArray> = ComputationRecord::new();
+        let mut data = NdArray::from_slice(&[0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8, 0.9, 1.0, 1.1, 1.2]);
+        data.reshape(&[2, 3, 2]);
+        validate_grad(t.tensor_from_value(data), &batched_compute);
+
+        let mut data = NdArray::from_slice(&[0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8]);
+        data.reshape(&[2, 2, 2]);
+        validate_grad(t.tensor_from_value(data), &batched_right_compute);
+    }
+
+    #[test]
+    fn vector_matmul_test() {
+        let t: ComputationRecord<NdArray> = ComputationRecord::new();
+        let vector = t.tensor_from_slice(&[1., 2.]);
+        validate_grad(vector, &|v| {
+            let matrix = constant(v, &[2, 3]);
+            let row = matmul(v, &matrix);
+            let back = matmul(&matrix, &reshape(&row, &[3]));
+            matmul(&back, v)
+        });
+    }
 }
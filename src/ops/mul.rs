@@ -23,7 +23,7 @@ pub fn mul<'t, T: TensorBackend>(left: &TrackedTensor<'t, T>, other: &TrackedTen
     let op_data =
         OpData::from_blueprints(vec![left_blueprint, right_blueprint], "Mul".to_string());
 
-    let op_result = left.data().mul(&other.data());
+    let op_result = left.data().mul(other.data());
 
     left.tape.tensor_from_op_result_and_data(op_result, op_data)
 }
@@ -41,8 +41,8 @@ mod tests {
     fn mul_twice<'t>(input: &TrackedTensor<'t, NdArray>) -> TrackedTensor<'t, NdArray> {
         // [2.] + in + in
         let input_1 = input.tape.tensor_from_slice(&[2.]);
-        let x = mul(&input, &input_1);
-        let y = mul(&x, &input);
+        let x = mul(input, &input_1);
+        let y = mul(&x, input);
         y
     }
 
@@ -53,8 +53,8 @@ mod tests {
         let input_1 = input.tape.tensor_from_value(input_1);
 
 
-        let x = mul(&input, &input_1);
-        let y = mul(&x, &input);
+        let x = mul(input, &input_1);
+        let y = mul(&x, input);
         let y = sum(&y);
         y
     }
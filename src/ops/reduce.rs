@@ -0,0 +1,229 @@
+use crate::{GradFn, OpAttribute, OpData, TrackedTensor};
+use crate::tensor_backends::TensorBackend;
+
+/// Reshapes the result (or gradient) of a reduction along `axis` so it has the input rank
+/// again and broadcasts it to the input shape. Works for both keepdim variants.
+fn expand_reduced<T: TensorBackend>(reduced: &T, input_shape: &[usize], axis: usize) -> T {
+    let mut keepdim_shape = input_shape.to_vec();
+    keepdim_shape[axis] = 1;
+    let mut reduced = reduced.clone();
+    reduced.reshape(&keepdim_shape);
+    reduced.broadcast_to(input_shape)
+}
+
+/// Sums a gradient computed for a broadcast result back to the shape of the operand that was
+/// broadcast: over the extra leading axes and over the axes where the operand has length 1
+pub(crate) fn sum_to_shape<T: TensorBackend>(grad: &T, shape: &[usize]) -> T {
+    let mut grad = grad.clone();
+    while grad.shape().len() > shape.len() {
+        grad = grad.sum_axis(0, false);
+    }
+    for (axis, len) in shape.iter().enumerate() {
+        if *len == 1 && grad.shape()[axis] != 1 {
+            grad = grad.sum_axis(axis, true);
+        }
+    }
+    grad
+}
+
+/// Whether the reduced axis is in the result, which is always the case for rank 1 inputs
+fn kept_axis(keepdim: bool, input_shape: &[usize]) -> i64 {
+    (keepdim || input_shape.len() == 1) as i64
+}
+
+//noinspection DuplicatedCode
+pub fn sum_axis<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>, axis: usize, keepdim: bool) -> TrackedTensor<'t, T> {
+    let input_shape = input.shape().to_vec();
+    let grad_fn: GradFn<T> = GradFn(Box::new(
+        move |child_grad: T, self_grad: &mut T| {
+            // Every element of a lane contributed with weight 1 to the lane sum
+            let grad = expand_reduced(&child_grad, &input_shape, axis);
+            *self_grad = self_grad.add(&grad);
+        },
+    ));
+    let grad_blueprint = input.self_gradient_blueprint(grad_fn);
+
+    let op_data =
+        OpData::from_blueprints(vec![grad_blueprint], "SumAxis".to_string())
+            .with_attribute("axis", OpAttribute::Int(axis as i64))
+            .with_attribute("keepdim", OpAttribute::Int(kept_axis(keepdim, input.shape())));
+
+    let op_result = input.data().sum_axis(axis, keepdim);
+
+    input.tape.tensor_from_op_result_and_data(op_result, op_data)
+}
+
+//noinspection DuplicatedCode
+pub fn mean_axis<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>, axis: usize, keepdim: bool) -> TrackedTensor<'t, T> {
+    let input_shape = input.shape().to_vec();
+    let lane_len = input_shape[axis] as f32;
+    let grad_fn: GradFn<T> = GradFn(Box::new(
+        move |child_grad: T, self_grad: &mut T| {
+            let grad = expand_reduced(&child_grad, &input_shape, axis).mul_scalar(1. / lane_len);
+            *self_grad = self_grad.add(&grad);
+        },
+    ));
+    let grad_blueprint = input.self_gradient_blueprint(grad_fn);
+
+    let op_data =
+        OpData::from_blueprints(vec![grad_blueprint], "MeanAxis".to_string())
+            .with_attribute("axis", OpAttribute::Int(axis as i64))
+            .with_attribute("keepdim", OpAttribute::Int(kept_axis(keepdim, input.shape())));
+
+    let op_result = input.data().mean_axis(axis, keepdim);
+
+    input.tape.tensor_from_op_result_and_data(op_result, op_data)
+}
+
+/// Averages all elements reducing them to shape [1]
+//noinspection DuplicatedCode
+pub fn mean<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>) -> TrackedTensor<'t, T> {
+    let len = input.shape().iter().product::<usize>() as f32;
+    let grad_fn: GradFn<T> = GradFn(Box::new(
+        move |child_grad: T, self_grad: &mut T| {
+            let mut new = T::zeros_like(self_grad);
+            new.fill_with(child_grad.index(&[0]) / len);
+            *self_grad = self_grad.add(&new);
+        },
+    ));
+    let grad_blueprint = input.self_gradient_blueprint(grad_fn);
+
+    let op_data =
+        OpData::from_blueprints(vec![grad_blueprint], "Mean".to_string());
+
+    let op_result = T::from_slice(&[input.data().sum() / len]);
+
+    input.tape.tensor_from_op_result_and_data(op_result, op_data)
+}
+
+/// Gradient of max/min: flows only to the elements equal to the extreme value of their lane and
+/// is split evenly between them in case of ties.
+fn extreme_axis<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>, op_result: T, axis: usize, op_name: &str) -> TrackedTensor<'t, T> {
+    let input_data = input.data().clone();
+    let result = op_result.clone();
+    let grad_fn: GradFn<T> = GradFn(Box::new(
+        move |child_grad: T, self_grad: &mut T| {
+            let input_shape = input_data.shape().to_vec();
+            let extreme = expand_reduced(&result, &input_shape, axis);
+            let mask = input_data.zip_map(&extreme, |x, e| if x == e { 1. } else { 0. });
+            let ties = expand_reduced(&mask.sum_axis(axis, true), &input_shape, axis);
+            let grad = expand_reduced(&child_grad, &input_shape, axis)
+                .mul(&mask)
+                .zip_map(&ties, |g, count| g / count);
+            *self_grad = self_grad.add(&grad);
+        },
+    ));
+    let grad_blueprint = input.self_gradient_blueprint(grad_fn);
+
+    let op_data =
+        OpData::from_blueprints(vec![grad_blueprint], op_name.to_string());
+
+    input.tape.tensor_from_op_result_and_data(op_result, op_data)
+}
+
+pub fn max_axis<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>, axis: usize, keepdim: bool) -> TrackedTensor<'t, T> {
+    let op_result = input.data().max_axis(axis, keepdim);
+    extreme_axis(input, op_result, axis, "MaxAxis")
+}
+
+pub fn min_axis<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>, axis: usize, keepdim: bool) -> TrackedTensor<'t, T> {
+    let op_result = input.data().min_axis(axis, keepdim);
+    extreme_axis(input, op_result, axis, "MinAxis")
+}
+
+//noinspection DuplicatedCode
+pub fn prod_axis<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>, axis: usize, keepdim: bool) -> TrackedTensor<'t, T> {
+    let input_data = input.data().clone();
+    let grad_fn: GradFn<T> = GradFn(Box::new(
+        move |child_grad: T, self_grad: &mut T| {
+            // The gradient of each element is the product of the other elements of its lane.
+            // Computed from the product of the non zero elements and the number of zeros so
+            // it stays correct when the lane contains zeros.
+            let input_shape = input_data.shape().to_vec();
+            let mut zeros = input_data.clone();
+            zeros.map_inplace(|x| *x = if *x == 0. { 1. } else { 0. });
+            let zeros_count = expand_reduced(&zeros.sum_axis(axis, true), &input_shape, axis);
+            let mut non_zero = input_data.clone();
+            non_zero.map_inplace(|x| if *x == 0. { *x = 1. });
+            let non_zero_prod = expand_reduced(&non_zero.prod_axis(axis, true), &input_shape, axis);
+
+            let factor = input_data.zip_map(&zeros_count, |x, count| {
+                match (x == 0., count as usize) {
+                    (false, 0) => 1. / x,
+                    (true, 1) => 1.,
+                    _ => 0.,
+                }
+            });
+            let grad = expand_reduced(&child_grad, &input_shape, axis)
+                .mul(&factor)
+                .mul(&non_zero_prod);
+            *self_grad = self_grad.add(&grad);
+        },
+    ));
+    let grad_blueprint = input.self_gradient_blueprint(grad_fn);
+
+    let op_data =
+        OpData::from_blueprints(vec![grad_blueprint], "ProdAxis".to_string());
+
+    let op_result = input.data().prod_axis(axis, keepdim);
+
+    input.tape.tensor_from_op_result_and_data(op_result, op_data)
+}
+
+
+#[cfg(test)]
+mod reduce_tests {
+    use super::*;
+    use crate::ops::testing::{validate_grad, weighted_sum};
+    use crate::ops::sum::sum;
+    use crate::tensor_backends::NdArray;
+    use crate::tape::ComputationRecord;
+
+    fn matrix<'t>(t: &'t ComputationRecord<NdArray>, values: &[f32]) -> TrackedTensor<'t, NdArray> {
+        let mut data = NdArray::from_slice(values);
+        data.reshape(&[2, 3]);
+        t.tensor_from_value(data)
+    }
+
+    #[test]
+    fn sum_and_mean_axis_test() {
+        let t: ComputationRecord<NdArray> = ComputationRecord::new();
+        let values = [1., 2., 3., 4., 5., 6.];
+        validate_grad(matrix(&t, &values), &|x| weighted_sum(&sum_axis(x, 0, false)));
+        validate_grad(matrix(&t, &values), &|x| weighted_sum(&sum_axis(x, 1, true)));
+        validate_grad(matrix(&t, &values), &|x| weighted_sum(&mean_axis(x, 1, false)));
+        validate_grad(matrix(&t, &values), &|x| weighted_sum(&mean_axis(x, 0, true)));
+        validate_grad(matrix(&t, &values), &|x| mean(x));
+    }
+
+    #[test]
+    fn max_min_axis_test() {
+        let t: ComputationRecord<NdArray> = ComputationRecord::new();
+        let values = [1., 5., 3., 4., 2., 6.];
+        validate_grad(matrix(&t, &values), &|x| weighted_sum(&max_axis(x, 1, false)));
+        validate_grad(matrix(&t, &values), &|x| weighted_sum(&min_axis(x, 0, true)));
+    }
+
+    #[test]
+    fn max_axis_ties_split_gradient() {
+        let t: ComputationRecord<NdArray> = ComputationRecord::new();
+        let input = matrix(&t, &[7., 7., 1., 2., 9., 9.]);
+        let output = sum(&max_axis(&input, 1, false));
+        let mut expected = NdArray::from_slice(&[0.5, 0.5, 0., 0., 0.5, 0.5]);
+        expected.reshape(&[2, 3]);
+        assert_eq!(output.grad().wrt(&input).data(), &expected);
+    }
+
+    #[test]
+    fn prod_axis_test() {
+        let t: ComputationRecord<NdArray> = ComputationRecord::new();
+        validate_grad(matrix(&t, &[1., 2., 3., 0.5, 1.5, 2.5]), &|x| weighted_sum(&prod_axis(x, 1, false)));
+
+        // Lanes with one and with two zeros
+        let input = matrix(&t, &[2., 0., 3., 0., 4., 0.]);
+        let output = sum(&prod_axis(&input, 1, false));
+        let mut expected = NdArray::from_slice(&[0., 6., 0., 0., 0., 0.]);
+        expected.reshape(&[2, 3]);
+        assert_eq!(output.grad().wrt(&input).data(), &expected);
+    }
+}
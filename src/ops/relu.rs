@@ -1,17 +1,27 @@
-use crate::{GradFn, OpData, TrackedTensor};
+use crate::{GradFn, OpAttribute, OpData, TrackedTensor};
 use crate::tensor_backends::TensorBackend;
 
 
+/// max(0, x)
 pub fn relu<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>) -> TrackedTensor<'t, T> {
-    let mut closure_input_data_clone = input.data().clone();
+    negative_slope_op(input, 0., "Relu")
+}
+
+/// x for x >= 0 and slope * x otherwise
+pub fn leaky_relu<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>, slope: f32) -> TrackedTensor<'t, T> {
+    negative_slope_op(input, slope, "LeakyRelu")
+}
+
+fn negative_slope_op<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>, slope: f32, op_name: &str) -> TrackedTensor<'t, T> {
+    let closure_input_data_clone = input.data().clone();
     let grad_fn: GradFn<T> = GradFn(Box::new(
         move |child_grad: T, self_grad: &mut T| {
-            // If value < 0 => grad = 0.1
+            // If value < 0 => grad = slope
             // else grad = 1.
             let mut input_data = closure_input_data_clone.clone();
             input_data.map_inplace(|single_data|{
                 if *single_data < 0.{
-                    *single_data = 0.1;
+                    *single_data = slope;
                 }else{
                     *single_data = 1.;
                 }
@@ -24,12 +34,13 @@ pub fn relu<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>) -> TrackedTensor
     let blueprint = input.self_gradient_blueprint(grad_fn);
 
     let op_data =
-        OpData::from_blueprints(vec![blueprint], "Relu".to_string());
+        OpData::from_blueprints(vec![blueprint], op_name.to_string())
+            .with_attribute("alpha", OpAttribute::Float(slope));
 
     let mut input_data_clone = input.data().clone();
     input_data_clone.map_inplace(|single_data|{
         if *single_data < 0.{
-            *single_data = *single_data*0.1;
+            *single_data *= slope;
         }
     });
 
@@ -48,14 +59,28 @@ mod relu_tests {
 
 
     fn relu_comp<'t>(input: &TrackedTensor<'t, NdArray>) -> TrackedTensor<'t, NdArray> {
-        let y = relu(&input);
+        let y = relu(input);
         sum(&y)
     }
 
+    fn leaky_relu_comp<'t>(input: &TrackedTensor<'t, NdArray>) -> TrackedTensor<'t, NdArray> {
+        let y = leaky_relu(input, 0.2);
+        sum(&mul(&y, &y))
+    }
+
     #[test]
     fn relu_test() {
         let t: ComputationRecord<NdArray> = ComputationRecord::new();
         let input_0 = t.tensor_from_slice(&[-2., -1., 0., 1., 2.]);
+        assert_eq!(relu(&input_0).data(), &NdArray::from_slice(&[0., 0., 0., 1., 2.]));
         validate_grad(input_0, &relu_comp);
     }
+
+    #[test]
+    fn leaky_relu_test() {
+        let t: ComputationRecord<NdArray> = ComputationRecord::new();
+        let input_0 = t.tensor_from_slice(&[-2., -1., 0., 1., 2.]);
+        assert_eq!(leaky_relu(&input_0, 0.2).data(), &NdArray::from_slice(&[-0.4, -0.2, 0., 1., 2.]));
+        validate_grad(input_0, &leaky_relu_comp);
+    }
 }
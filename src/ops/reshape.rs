@@ -0,0 +1,162 @@
+use crate::{GradFn, OpAttribute, OpData, TrackedTensor};
+use crate::tensor_backends::TensorBackend;
+use crate::ops::reduce::sum_to_shape;
+
+/// Gives the data a new shape with the same number of elements, in row major order
+//noinspection DuplicatedCode
+pub fn reshape<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>, shape: &[usize]) -> TrackedTensor<'t, T> {
+    let input_shape = input.shape().to_vec();
+    let grad_fn: GradFn<T> = GradFn(Box::new(
+        move |child_grad: T, self_grad: &mut T| {
+            let mut grad = child_grad;
+            grad.reshape(&input_shape);
+            *self_grad = self_grad.add(&grad);
+        },
+    ));
+    let grad_blueprint = input.self_gradient_blueprint(grad_fn);
+
+    let op_data =
+        OpData::from_blueprints(vec![grad_blueprint], "Reshape".to_string())
+            .with_attribute("shape", OpAttribute::Ints(shape.iter().map(|len| *len as i64).collect()));
+
+    let mut op_result = input.data().clone();
+    op_result.reshape(shape);
+
+    input.tape.tensor_from_op_result_and_data(op_result, op_data)
+}
+
+/// Merges the axes from `start_axis` to `end_axis` (both inclusive) into one,
+/// e.g. flatten(x, 1, 3) turns [B, C, H, W] into [B, C*H*W]
+pub fn flatten<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>, start_axis: usize, end_axis: usize) -> TrackedTensor<'t, T> {
+    let shape = input.shape();
+    assert!(start_axis <= end_axis && end_axis < shape.len(), "Invalid axes {}..={} to flatten shape {:?}", start_axis, end_axis, shape);
+    let mut new_shape = shape[..start_axis].to_vec();
+    new_shape.push(shape[start_axis..=end_axis].iter().product());
+    new_shape.extend_from_slice(&shape[end_axis + 1..]);
+    reshape(input, &new_shape)
+}
+
+/// Removes `axis`, which must have length 1
+pub fn squeeze<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>, axis: usize) -> TrackedTensor<'t, T> {
+    let mut new_shape = input.shape().to_vec();
+    assert_eq!(new_shape.get(axis), Some(&1), "Can only squeeze axes of length 1, but axis {} of {:?} is not", axis, input.shape());
+    new_shape.remove(axis);
+    reshape(input, &new_shape)
+}
+
+/// Inserts an axis of length 1 at `axis`
+pub fn unsqueeze<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>, axis: usize) -> TrackedTensor<'t, T> {
+    let mut new_shape = input.shape().to_vec();
+    assert!(axis <= new_shape.len(), "Can not insert axis {} in shape {:?}", axis, input.shape());
+    new_shape.insert(axis, 1);
+    reshape(input, &new_shape)
+}
+
+/// Reorders the axes: axis i of the result is axis axes[i] of the input
+//noinspection DuplicatedCode
+pub fn permute<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>, axes: &[usize]) -> TrackedTensor<'t, T> {
+    // The gradient goes through the inverse permutation
+    let mut inverse = vec![0; axes.len()];
+    for (i, axis) in axes.iter().enumerate() {
+        inverse[*axis] = i;
+    }
+    let grad_fn: GradFn<T> = GradFn(Box::new(
+        move |child_grad: T, self_grad: &mut T| {
+            *self_grad = self_grad.add(&child_grad.permute(&inverse));
+        },
+    ));
+    let grad_blueprint = input.self_gradient_blueprint(grad_fn);
+
+    let op_data =
+        OpData::from_blueprints(vec![grad_blueprint], "Permute".to_string())
+            .with_attribute("axes", OpAttribute::Ints(axes.iter().map(|axis| *axis as i64).collect()));
+
+    let op_result = input.data().permute(axes);
+
+    input.tape.tensor_from_op_result_and_data(op_result, op_data)
+}
+
+/// Swaps two axes
+pub fn transpose<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>, axis_a: usize, axis_b: usize) -> TrackedTensor<'t, T> {
+    let mut axes: Vec<usize> = (0..input.shape().len()).collect();
+    axes.swap(axis_a, axis_b);
+    permute(input, &axes)
+}
+
+/// Broadcasts to `shape` following NumPy rules, the gradient is summed back over the
+/// broadcast axes
+//noinspection DuplicatedCode
+pub fn broadcast_to<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>, shape: &[usize]) -> TrackedTensor<'t, T> {
+    let input_shape = input.shape().to_vec();
+    let grad_fn: GradFn<T> = GradFn(Box::new(
+        move |child_grad: T, self_grad: &mut T| {
+            let mut grad = sum_to_shape(&child_grad, &input_shape);
+            grad.reshape(&input_shape);
+            *self_grad = self_grad.add(&grad);
+        },
+    ));
+    let grad_blueprint = input.self_gradient_blueprint(grad_fn);
+
+    let op_data =
+        OpData::from_blueprints(vec![grad_blueprint], "BroadcastTo".to_string())
+            .with_attribute("shape", OpAttribute::Ints(shape.iter().map(|len| *len as i64).collect()));
+
+    let op_result = input.data().broadcast_to(shape);
+
+    input.tape.tensor_from_op_result_and_data(op_result, op_data)
+}
+
+
+#[cfg(test)]
+mod reshape_tests {
+    use super::*;
+    use crate::ops::testing::{validate_grad, weighted_sum};
+    use crate::ops::*;
+    use crate::tensor_backends::NdArray;
+    use crate::tape::ComputationRecord;
+
+    fn reshape_comp<'t>(input: &TrackedTensor<'t, NdArray>) -> TrackedTensor<'t, NdArray> {
+        // [2, 3] -> [2, 1, 3] -> [3, 2] -> [6] -> [1, 6]
+        let x = unsqueeze(input, 1);
+        let x = reshape(&x, &[3, 2]);
+        let x = flatten(&x, 0, 1);
+        let x = squeeze(&unsqueeze(&x, 0), 0);
+        let x = unsqueeze(&x, 0);
+        weighted_sum(&x)
+    }
+
+    fn permute_comp<'t>(input: &TrackedTensor<'t, NdArray>) -> TrackedTensor<'t, NdArray> {
+        // [2, 3] -> [3, 2] and [1, 3, 2] -> [2, 3, 1], then back to [2, 3]
+        let transposed = transpose(input, 0, 1);
+        let permuted = permute(&unsqueeze(&transposed, 0), &[2, 1, 0]);
+        let x = mul(&reshape(&permuted, &[2, 3]), &transpose(&transposed, 1, 0));
+        weighted_sum(&x)
+    }
+
+    #[test]
+    fn reshape_test() {
+        let t: ComputationRecord<NdArray> = ComputationRecord::new();
+        let mut data = NdArray::from_slice(&[1., 2., 3., 4., 5., 6.]);
+        data.reshape(&[2, 3]);
+        validate_grad(t.tensor_from_value(data), &reshape_comp);
+    }
+
+    #[test]
+    fn broadcast_to_test() {
+        let t: ComputationRecord<NdArray> = ComputationRecord::new();
+        let mut data = NdArray::from_slice(&[1., 2., 3.]);
+        data.reshape(&[3, 1]);
+        validate_grad(t.tensor_from_value(data), &|x| {
+            let x = broadcast_to(x, &[2, 3, 4]);
+            weighted_sum(&x)
+        });
+    }
+
+    #[test]
+    fn permute_test() {
+        let t: ComputationRecord<NdArray> = ComputationRecord::new();
+        let mut data = NdArray::from_slice(&[0.1, 0.2, 0.3, 0.4, 0.5, 0.6]);
+        data.reshape(&[2, 3]);
+        validate_grad(t.tensor_from_value(data), &permute_comp);
+    }
+}
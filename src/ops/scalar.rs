@@ -0,0 +1,61 @@
+use crate::{GradFn, OpAttribute, OpData, TrackedTensor};
+use crate::tensor_backends::TensorBackend;
+
+/// Records an op between a Tensor and a constant whose gradient is the child gradient times
+/// the constant `factor`. The scalar operand is recorded as an attribute.
+fn scalar_op<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>, op_result: T, op_name: &str, scalar: f32, factor: f32) -> TrackedTensor<'t, T> {
+    let grad_fn: GradFn<T> = GradFn(Box::new(
+        move |child_grad: T, self_grad: &mut T| {
+            *self_grad = self_grad.add(&child_grad.mul_scalar(factor));
+        },
+    ));
+    let grad_blueprint = input.self_gradient_blueprint(grad_fn);
+
+    let op_data =
+        OpData::from_blueprints(vec![grad_blueprint], op_name.to_string())
+            .with_attribute("scalar", OpAttribute::Float(scalar));
+
+    input.tape.tensor_from_op_result_and_data(op_result, op_data)
+}
+
+pub fn add_scalar<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>, scalar: f32) -> TrackedTensor<'t, T> {
+    scalar_op(input, input.data().add_scalar(scalar), "AddScalar", scalar, 1.)
+}
+
+pub fn mul_scalar<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>, scalar: f32) -> TrackedTensor<'t, T> {
+    scalar_op(input, input.data().mul_scalar(scalar), "MulScalar", scalar, scalar)
+}
+
+pub fn div_scalar<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>, scalar: f32) -> TrackedTensor<'t, T> {
+    scalar_op(input, input.data().div_scalar(scalar), "DivScalar", scalar, 1. / scalar)
+}
+
+/// Reversed subtraction: scalar - input
+pub fn rsub<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>, scalar: f32) -> TrackedTensor<'t, T> {
+    scalar_op(input, input.data().neg().add_scalar(scalar), "RSub", scalar, -1.)
+}
+
+
+#[cfg(test)]
+mod scalar_tests {
+    use super::*;
+    use crate::ops::testing::validate_grad;
+    use crate::ops::*;
+    use crate::tensor_backends::NdArray;
+    use crate::tape::ComputationRecord;
+
+    fn scalar_comp<'t>(input: &TrackedTensor<'t, NdArray>) -> TrackedTensor<'t, NdArray> {
+        // sum((3 - in * 2) * (in / 4 + 1))
+        let left = rsub(&mul_scalar(input, 2.), 3.);
+        let right = add_scalar(&div_scalar(input, 4.), 1.);
+        sum(&mul(&left, &right))
+    }
+
+    #[test]
+    fn scalar_test() {
+        let t: ComputationRecord<NdArray> = ComputationRecord::new();
+        let input_0 = t.tensor_from_slice(&[1., -2., 0.5]);
+        validate_grad(input_0, &scalar_comp);
+        assert_eq!(rsub(&t.tensor_from_slice(&[1., 4.]), 3.).data(), &NdArray::from_slice(&[2., -1.]));
+    }
+}
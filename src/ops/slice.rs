@@ -0,0 +1,51 @@
+use crate::{GradFn, OpData, TrackedTensor};
+use crate::tensor_backends::TensorBackend;
+use crate::tensor_backends::slicing::SliceElem;
+
+/// Takes the sub Tensor described by `info`, like `x[:, 1:3]` in NumPy. See `SliceElem`.
+//noinspection DuplicatedCode
+pub fn slice<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>, info: &[SliceElem]) -> TrackedTensor<'t, T> {
+    let closure_info = info.to_vec();
+    let grad_fn: GradFn<T> = GradFn(Box::new(
+        move |child_grad: T, self_grad: &mut T| {
+            // Only the sliced elements contributed to the output, so the child gradient is
+            // scattered back into their positions and everything else gets zero
+            self_grad.slice_add_assign(&closure_info, &child_grad);
+        },
+    ));
+    let grad_blueprint = input.self_gradient_blueprint(grad_fn);
+
+    let op_data =
+        OpData::from_blueprints(vec![grad_blueprint], "Slice".to_string());
+
+    let op_result = input.data().slice(info);
+
+    input.tape.tensor_from_op_result_and_data(op_result, op_data)
+}
+
+
+#[cfg(test)]
+mod slice_tests {
+    use super::*;
+    use crate::ops::testing::validate_grad;
+    use crate::ops::*;
+    use crate::tensor_backends::NdArray;
+    use crate::tape::ComputationRecord;
+
+    fn slice_comp<'t>(input: &TrackedTensor<'t, NdArray>) -> TrackedTensor<'t, NdArray> {
+        // x[:, 1:3] * x[:, ::-2][None]
+        let middle = slice(input, &[SliceElem::full(), (1..3).into()]);
+        let reversed = slice(input, &[SliceElem::NewAxis, SliceElem::full(), SliceElem::full().step_by(-2)]);
+        let y = mul(&middle, &slice(&reversed, &[SliceElem::Index(0)]));
+        sum(&y)
+    }
+
+    #[test]
+    fn slice_test() {
+        let t: ComputationRecord<NdArray> = ComputationRecord::new();
+        let mut data = NdArray::from_slice(&[1., 2., 3., 4., 5., 6., 7., 8.]);
+        data.reshape(&[2, 4]);
+        let input_0 = t.tensor_from_value(data);
+        validate_grad(input_0, &slice_comp);
+    }
+}
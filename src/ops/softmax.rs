@@ -0,0 +1,123 @@
+use crate::{GradFn, OpAttribute, OpData, TrackedTensor};
+use crate::tensor_backends::TensorBackend;
+
+/// log(softmax(x)) along `axis`, computed as (x - max) - log(sum(exp(x - max))) so large
+/// inputs do not overflow
+pub(crate) fn log_softmax_data<T: TensorBackend>(input: &T, axis: usize) -> T {
+    let shape = input.shape().to_vec();
+    let shifted = input.sub(&input.max_axis(axis, true).broadcast_to(&shape));
+    let log_sum_exp = shifted.exp().sum_axis(axis, true).log();
+    shifted.sub(&log_sum_exp.broadcast_to(&shape))
+}
+
+/// Softmax along `axis`, e.g. per row of a [batch, classes] Tensor with axis 1
+//noinspection DuplicatedCode
+pub fn softmax<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>, axis: usize) -> TrackedTensor<'t, T> {
+    let op_result = log_softmax_data(input.data(), axis).exp();
+
+    let softmax_data = op_result.clone();
+    let grad_fn: GradFn<T> = GradFn(Box::new(
+        move |child_grad: T, self_grad: &mut T| {
+            // s * (g - sum(g * s))
+            let shape = softmax_data.shape().to_vec();
+            let weighted_sum = child_grad.mul(&softmax_data).sum_axis(axis, true).broadcast_to(&shape);
+            let grad = softmax_data.mul(&child_grad.sub(&weighted_sum));
+            *self_grad = self_grad.add(&grad);
+        },
+    ));
+    let grad_blueprint = input.self_gradient_blueprint(grad_fn);
+
+    let op_data =
+        OpData::from_blueprints(vec![grad_blueprint], "Softmax".to_string())
+            .with_attribute("axis", OpAttribute::Int(axis as i64));
+
+    input.tape.tensor_from_op_result_and_data(op_result, op_data)
+}
+
+/// Log of the softmax along `axis`, more accurate than composing log and softmax
+//noinspection DuplicatedCode
+pub fn log_softmax<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>, axis: usize) -> TrackedTensor<'t, T> {
+    let op_result = log_softmax_data(input.data(), axis);
+
+    let softmax_data = op_result.exp();
+    let grad_fn: GradFn<T> = GradFn(Box::new(
+        move |child_grad: T, self_grad: &mut T| {
+            // g - softmax * sum(g)
+            let shape = softmax_data.shape().to_vec();
+            let grad_sum = child_grad.sum_axis(axis, true).broadcast_to(&shape);
+            let grad = child_grad.sub(&softmax_data.mul(&grad_sum));
+            *self_grad = self_grad.add(&grad);
+        },
+    ));
+    let grad_blueprint = input.self_gradient_blueprint(grad_fn);
+
+    let op_data =
+        OpData::from_blueprints(vec![grad_blueprint], "LogSoftmax".to_string())
+            .with_attribute("axis", OpAttribute::Int(axis as i64));
+
+    input.tape.tensor_from_op_result_and_data(op_result, op_data)
+}
+
+
+#[cfg(test)]
+mod softmax_tests {
+    use super::*;
+    use crate::ops::testing::{validate_grad, weighted_sum};
+    use crate::ops::*;
+    use crate::tensor_backends::NdArray;
+    use crate::tape::ComputationRecord;
+
+    fn logits<'t>(t: &'t ComputationRecord<NdArray>, values: &[f32]) -> TrackedTensor<'t, NdArray> {
+        let mut data = NdArray::from_slice(values);
+        data.reshape(&[2, 3]);
+        t.tensor_from_value(data)
+    }
+
+    #[test]
+    fn softmax_test() {
+        let t: ComputationRecord<NdArray> = ComputationRecord::new();
+        let values = [1., 2., 3., -1., 0.5, 0.];
+        validate_grad(logits(&t, &values), &|x| weighted_sum(&softmax(x, 1)));
+        validate_grad(logits(&t, &values), &|x| weighted_sum(&softmax(x, 0)));
+
+        let rows = sum_axis(&softmax(&logits(&t, &values), 1), 1, false);
+        for row in 0..2 {
+            assert!((rows.data().index(&[row]) - 1.).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn log_softmax_test() {
+        let t: ComputationRecord<NdArray> = ComputationRecord::new();
+        let values = [1., 2., 3., -1., 0.5, 0.];
+        validate_grad(logits(&t, &values), &|x| weighted_sum(&log_softmax(x, 1)));
+        validate_grad(logits(&t, &values), &|x| weighted_sum(&log_softmax(x, 0)));
+    }
+
+    #[test]
+    fn large_magnitude_inputs() {
+        let t: ComputationRecord<NdArray> = ComputationRecord::new();
+        let small = logits(&t, &[1., 2., 3., -1., 0.5, 0.]);
+        let large = logits(&t, &[1001., 1002., 1003., -1001., -999.5, -1000.]);
+
+        let small_log = log_softmax(&small, 1);
+        let large_log = log_softmax(&large, 1);
+        let large_soft = softmax(&large, 1);
+        for row in 0..2 {
+            for col in 0..3 {
+                let expected = small_log.data().index(&[row, col]);
+                assert!((large_log.data().index(&[row, col]) - expected).abs() < 1e-4);
+                assert!((large_soft.data().index(&[row, col]) - expected.exp()).abs() < 1e-4);
+            }
+        }
+
+        let grad = weighted_sum(&large_log).grad().wrt(&large);
+        let expected_grad = weighted_sum(&small_log).grad().wrt(&small);
+        for row in 0..2 {
+            for col in 0..3 {
+                let difference = grad.data().index(&[row, col]) - expected_grad.data().index(&[row, col]);
+                assert!(difference.abs() < 1e-4);
+            }
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use crate::{GradFn, OpData, TrackedTensor};
+use crate::tensor_backends::TensorBackend;
+use crate::tensor_backends::slicing::{self, SliceElem};
+
+/// Splits along `axis` into pieces of the given sizes, which must add up to the axis length
+//noinspection DuplicatedCode
+pub fn split<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>, sizes: &[usize], axis: usize) -> Vec<TrackedTensor<'t, T>> {
+    let pieces = input.data().split(sizes, axis);
+    let mut start = 0;
+    pieces.into_iter().zip(sizes).map(|(piece, size)| {
+        // Each piece sends its gradient back to the part of the input it was taken from
+        let info = slicing::along_axis(axis, SliceElem::range(start as isize, (start + size) as isize));
+        start += size;
+        let grad_fn: GradFn<T> = GradFn(Box::new(
+            move |child_grad: T, self_grad: &mut T| {
+                self_grad.slice_add_assign(&info, &child_grad);
+            },
+        ));
+        let grad_blueprint = input.self_gradient_blueprint(grad_fn);
+        let op_data =
+            OpData::from_blueprints(vec![grad_blueprint], "Split".to_string());
+        input.tape.tensor_from_op_result_and_data(piece, op_data)
+    }).collect()
+}
+
+/// Splits along `axis` into at most `chunks` pieces of equal size, the last one may be smaller
+pub fn chunk<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>, chunks: usize, axis: usize) -> Vec<TrackedTensor<'t, T>> {
+    let sizes = slicing::chunk_sizes(input.shape()[axis], chunks);
+    split(input, &sizes, axis)
+}
+
+
+#[cfg(test)]
+mod split_tests {
+    use super::*;
+    use crate::ops::testing::validate_grad;
+    use crate::ops::*;
+    use crate::tensor_backends::NdArray;
+    use crate::tape::ComputationRecord;
+
+    fn split_comp<'t>(input: &TrackedTensor<'t, NdArray>) -> TrackedTensor<'t, NdArray> {
+        // Splitting an intermediate result, every piece flows back into the same Tensor
+        let squared = mul(input, input);
+        let pieces = split(&squared, &[1, 2], 0);
+        let first = sum(&mul(&pieces[0], &pieces[0]));
+        let second = sum(&pieces[1]);
+        add(&mul(&first, &first), &second)
+    }
+
+    fn chunk_comp<'t>(input: &TrackedTensor<'t, NdArray>) -> TrackedTensor<'t, NdArray> {
+        let pieces = chunk(input, 2, 1);
+        assert_eq!(pieces.len(), 2);
+        let products = mul(&pieces[0], &slice(&pieces[1], &[SliceElem::full(), (0..2).into()]));
+        add(&sum(&products), &sum(&pieces[1]))
+    }
+
+    #[test]
+    fn split_test() {
+        let t: ComputationRecord<NdArray> = ComputationRecord::new();
+        let input_0 = t.tensor_from_slice(&[0.5, 1., 2.]);
+        validate_grad(input_0, &split_comp);
+    }
+
+    #[test]
+    fn chunk_test() {
+        let t: ComputationRecord<NdArray> = ComputationRecord::new();
+        let mut data = NdArray::from_slice(&[1., 2., 3., 4., 5., 6., 7., 8.]);
+        data.reshape(&[2, 4]);
+        validate_grad(t.tensor_from_value(data), &chunk_comp);
+    }
+}
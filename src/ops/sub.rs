@@ -0,0 +1,53 @@
+use crate::{GradFn, OpData, TrackedTensor};
+use crate::tensor_backends::TensorBackend;
+
+//noinspection DuplicatedCode
+pub fn sub<'t, T: TensorBackend>(left: &TrackedTensor<'t, T>, other: &TrackedTensor<'t, T>) -> TrackedTensor<'t, T> {
+
+    let left_grad_fn_sub: GradFn<T> = GradFn(Box::new(
+        move |child_grad: T, self_grad: &mut T| {
+            *self_grad = self_grad.add(&child_grad);
+        },
+    ));
+
+    let right_grad_fn_sub: GradFn<T> = GradFn(Box::new(
+        move |child_grad: T, self_grad: &mut T| {
+            *self_grad = self_grad.sub(&child_grad);
+        },
+    ));
+
+
+    let left_blueprint = left.self_gradient_blueprint(left_grad_fn_sub);
+    let right_blueprint = other.self_gradient_blueprint(right_grad_fn_sub);
+
+    let op_data =
+        OpData::from_blueprints(vec![left_blueprint, right_blueprint], "Sub".to_string());
+
+    let op_result = left.data().sub(other.data());
+    left.tape.tensor_from_op_result_and_data(op_result, op_data)
+}
+
+
+#[cfg(test)]
+mod sub_tests {
+    use super::*;
+    use crate::ops::testing::validate_grad;
+    use crate::ops::*;
+    use crate::tensor_backends::NdArray;
+    use crate::tape::ComputationRecord;
+
+
+    fn sub_comp<'t>(input: &TrackedTensor<'t, NdArray>) -> TrackedTensor<'t, NdArray> {
+        // sum((in - [2, 3]) * in)
+        let input_1 = input.tape.tensor_from_slice(&[2., 3.]);
+        let x = sub(input, &input_1);
+        sum(&mul(&x, input))
+    }
+
+    #[test]
+    fn sub_test() {
+        let t: ComputationRecord<NdArray> = ComputationRecord::new();
+        let input_0 = t.tensor_from_slice(&[1., -1.]);
+        validate_grad(input_0, &sub_comp);
+    }
+}
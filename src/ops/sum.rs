@@ -34,7 +34,7 @@ mod tests {
     use crate::ops::testing::validate_grad;
 
     fn sum_computation<'t>(input: &TrackedTensor<'t, NdArray>) -> TrackedTensor<'t, NdArray> {
-        let y = sum(&input);
+        let y = sum(input);
         y
     }
 
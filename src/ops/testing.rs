@@ -1,5 +1,6 @@
-use crate::tensor_backends::TensorBackend;
-use crate::TrackedTensor;
+use crate::tensor_backends::{NdArray, TensorBackend};
+use crate::{ComputationRecord, TrackedTensor};
+use crate::ops::{mul, sum};
 use crate::tensor_backends::indexing::Indexer;
 
 /// Here we calculate the output to a given input. Save the output gradient w.r.t. the input
@@ -8,17 +9,16 @@ use crate::tensor_backends::indexing::Indexer;
 pub fn validate_grad<T: TensorBackend>(input: TrackedTensor<T>, computation: &dyn for<'b> Fn(&TrackedTensor<'b, T>) -> TrackedTensor<'b, T>){
     // Here we should index each element of the input tensor and verify that its gradient is correct
     // This looks like, for Rank 3: [0, 0, 0], [0, 0, 1], [0, 1, 0] ... and so on.
-    let mut indexer = Indexer::from(input.shape());
     let output: TrackedTensor<T> = computation(&input);
-    while let Some(i) = indexer.next() {
+    for i in Indexer::from(input.shape()) {
         println!("verifying index {:?}", i);
         let first_index: &[usize] = &[0usize];
         let output_no_delta = output.data().index(first_index);
-        let output_grad_wrt_input = output.grad().wrt(&input).data().index(i);
+        let output_grad_wrt_input = output.grad().wrt(&input).data().index(&i);
 
         let delta = 0.01;
         let mut data_plus_delta = input.data().clone();
-        let index_0: &mut f32 = data_plus_delta._index_mut(i);
+        let index_0: &mut f32 = data_plus_delta._index_mut(&i);
         *index_0 += delta;
 
         let input_with_delta = output.tape.tensor_from_value(data_plus_delta);
@@ -37,4 +37,32 @@ pub fn validate_grad<T: TensorBackend>(input: TrackedTensor<T>, computation: &dy
         assert!(error < 0.001);
     }
 
-}
\ No newline at end of file
+}
+
+/// Validates the gradient of sum(op(x)) for x with the given values
+pub fn check(values: &[f32], op: &dyn for<'b> Fn(&TrackedTensor<'b, NdArray>) -> TrackedTensor<'b, NdArray>) {
+    let t: ComputationRecord<NdArray> = ComputationRecord::new();
+    let input_0 = t.tensor_from_slice(values);
+    validate_grad(input_0, &|x| sum(&op(x)));
+}
+
+/// Distinct weights in (0, 1] with the shape of `like`, so a weighted sum depends on every
+/// element differently
+fn weights<'t, T: TensorBackend>(like: &TrackedTensor<'t, T>) -> TrackedTensor<'t, T> {
+    let len = like.shape().iter().product::<usize>();
+    let mut weights = T::from_slice(&(1..=len).map(|w| w as f32 / len as f32).collect::<Vec<f32>>());
+    weights.reshape(like.shape());
+    like.tape.tensor_from_value(weights)
+}
+
+/// Reduces `x` to a scalar to validate its gradient, see `weights`
+pub fn weighted_sum<'t, T: TensorBackend>(x: &TrackedTensor<'t, T>) -> TrackedTensor<'t, T> {
+    sum(&mul(x, &weights(x)))
+}
+
+pub fn assert_close(actual: &[f32], expected: &[f32]) {
+    assert_eq!(actual.len(), expected.len());
+    for (actual, expected) in actual.iter().zip(expected) {
+        assert!((actual - expected).abs() < 1e-6, "{:?} != {:?}", actual, expected);
+    }
+}
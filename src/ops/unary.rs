@@ -0,0 +1,174 @@
+use crate::{GradFn, OpData, TrackedTensor};
+use crate::tensor_backends::TensorBackend;
+
+/// Records an element wise op. Its gradient is the child gradient times `derivative`, which
+/// gets the op input and output.
+pub(crate) fn unary_op<'t, T, F>(input: &TrackedTensor<'t, T>, op_result: T, op_name: &str, derivative: F) -> TrackedTensor<'t, T>
+    where T: TensorBackend, F: Fn(&T, &T) -> T + 'static {
+    let input_data = input.data().clone();
+    let output_data = op_result.clone();
+    let grad_fn: GradFn<T> = GradFn(Box::new(
+        move |child_grad: T, self_grad: &mut T| {
+            let grad = child_grad.mul(&derivative(&input_data, &output_data));
+            *self_grad = self_grad.add(&grad);
+        },
+    ));
+    let grad_blueprint = input.self_gradient_blueprint(grad_fn);
+
+    let op_data =
+        OpData::from_blueprints(vec![grad_blueprint], op_name.to_string());
+
+    input.tape.tensor_from_op_result_and_data(op_result, op_data)
+}
+
+pub fn exp<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>) -> TrackedTensor<'t, T> {
+    unary_op(input, input.data().exp(), "Exp", |_input, output| output.clone())
+}
+
+/// Natural logarithm
+pub fn log<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>) -> TrackedTensor<'t, T> {
+    unary_op(input, input.data().log(), "Log", |input, _output| input.reciprocal())
+}
+
+pub fn sqrt<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>) -> TrackedTensor<'t, T> {
+    unary_op(input, input.data().sqrt(), "Sqrt", |_input, output| output.reciprocal().mul_scalar(0.5))
+}
+
+pub fn pow<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>, exponent: f32) -> TrackedTensor<'t, T> {
+    unary_op(input, input.data().pow_scalar(exponent), "Pow", move |input, _output| {
+        input.pow_scalar(exponent - 1.).mul_scalar(exponent)
+    })
+}
+
+/// The gradient at 0 is taken as 0
+pub fn abs<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>) -> TrackedTensor<'t, T> {
+    unary_op(input, input.data().abs(), "Abs", |input, _output| {
+        let mut sign = input.clone();
+        sign.map_inplace(|x| *x = if *x > 0. { 1. } else if *x < 0. { -1. } else { 0. });
+        sign
+    })
+}
+
+pub fn neg<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>) -> TrackedTensor<'t, T> {
+    unary_op(input, input.data().neg(), "Neg", |input, _output| {
+        let mut minus_one = T::zeros_like(input);
+        minus_one.fill_with(-1.);
+        minus_one
+    })
+}
+
+pub fn sin<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>) -> TrackedTensor<'t, T> {
+    unary_op(input, input.data().sin(), "Sin", |input, _output| input.cos())
+}
+
+pub fn cos<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>) -> TrackedTensor<'t, T> {
+    unary_op(input, input.data().cos(), "Cos", |input, _output| input.sin().neg())
+}
+
+pub fn tanh<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>) -> TrackedTensor<'t, T> {
+    // 1 - tanh(x)^2
+    unary_op(input, input.data().tanh(), "Tanh", |_input, output| output.square().neg().add_scalar(1.))
+}
+
+pub fn sigmoid<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>) -> TrackedTensor<'t, T> {
+    // sigmoid(x) * (1 - sigmoid(x))
+    unary_op(input, input.data().sigmoid(), "Sigmoid", |_input, output| {
+        output.mul(&output.neg().add_scalar(1.))
+    })
+}
+
+pub fn softplus<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>) -> TrackedTensor<'t, T> {
+    unary_op(input, input.data().softplus(), "Softplus", |input, _output| input.sigmoid())
+}
+
+/// Limits the values to [min, max], the gradient is 0 for the clamped values
+pub fn clamp<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>, min: f32, max: f32) -> TrackedTensor<'t, T> {
+    unary_op(input, input.data().clamp(min, max), "Clamp", move |input, _output| {
+        let mut inside = input.clone();
+        inside.map_inplace(|x| *x = if *x >= min && *x <= max { 1. } else { 0. });
+        inside
+    })
+}
+
+pub fn reciprocal<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>) -> TrackedTensor<'t, T> {
+    // -1 / x^2
+    unary_op(input, input.data().reciprocal(), "Reciprocal", |_input, output| output.square().neg())
+}
+
+pub fn square<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>) -> TrackedTensor<'t, T> {
+    unary_op(input, input.data().square(), "Square", |input, _output| input.mul_scalar(2.))
+}
+
+
+#[cfg(test)]
+mod unary_tests {
+    use super::*;
+    use crate::ops::testing::check;
+
+    #[test]
+    fn exp_test() {
+        check(&[-1., 0., 0.5], &|x| exp(x));
+    }
+
+    #[test]
+    fn log_test() {
+        check(&[0.5, 1., 3.], &|x| log(x));
+    }
+
+    #[test]
+    fn sqrt_test() {
+        check(&[0.5, 1., 4.], &|x| sqrt(x));
+    }
+
+    #[test]
+    fn pow_test() {
+        check(&[0.5, 1., 1.5], &|x| pow(x, 3.));
+        check(&[0.5, 1., 4.], &|x| pow(x, -0.5));
+    }
+
+    #[test]
+    fn abs_test() {
+        check(&[-2., -0.5, 1.], &|x| abs(x));
+    }
+
+    #[test]
+    fn neg_test() {
+        check(&[-2., 0., 1.], &|x| neg(x));
+    }
+
+    #[test]
+    fn sin_cos_test() {
+        check(&[-2., 0., 1.], &|x| sin(x));
+        check(&[-2., 0., 1.], &|x| cos(x));
+    }
+
+    #[test]
+    fn tanh_test() {
+        check(&[-2., 0., 1.], &|x| tanh(x));
+    }
+
+    #[test]
+    fn sigmoid_test() {
+        check(&[-2., 0., 1.], &|x| sigmoid(x));
+    }
+
+    #[test]
+    fn softplus_test() {
+        check(&[-2., 0., 1.], &|x| softplus(x));
+    }
+
+    #[test]
+    fn clamp_test() {
+        check(&[-2., 0.5, 3.], &|x| clamp(x, -1., 1.));
+    }
+
+    #[test]
+    fn reciprocal_test() {
+        check(&[-2., 1., 3.], &|x| reciprocal(x));
+    }
+
+    #[test]
+    fn square_test() {
+        check(&[-2., 0., 1.], &|x| square(x));
+    }
+}
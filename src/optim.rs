@@ -0,0 +1,130 @@
+use crate::TrackedTensor;
+use crate::tensor_backends::TensorBackend;
+use crate::tape::Grad;
+use std::collections::HashMap;
+
+mod sgd;
+pub use sgd::{Sgd, SgdConfig};
+mod adam;
+pub use adam::{Adam, AdamConfig};
+mod rmsprop;
+pub use rmsprop::{RmsProp, RmsPropConfig};
+mod adagrad;
+pub use adagrad::{Adagrad, AdagradConfig};
+mod adadelta;
+pub use adadelta::{Adadelta, AdadeltaConfig};
+pub mod lr_scheduler;
+#[cfg(test)]
+pub(crate) mod testing;
+
+/// Updates parameters using their gradients.
+///
+/// The parameters are given as (name, tensor) pairs, like the ones returned by
+/// `Module::named_parameters`, and the updated values are written to the parameter store under
+/// the same names. Any state, like momentum buffers, is kept per parameter name so the optimizer
+/// can outlive the ComputationRecord used for a single step.
+pub trait Optimizer<T: TensorBackend> {
+    /// Parameters which do not influence the output `grad` was calculated for are left untouched
+    fn step(&mut self, params: &[(String, &TrackedTensor<'_, T>)], grad: &Grad<T>, params_store: &mut HashMap<String, T>);
+
+    /// Learning rate of the parameters not in any of the ParamGroups
+    fn learning_rate(&self) -> f32;
+
+    /// Also rescales the learning rates of the ParamGroups by the same factor
+    fn set_learning_rate(&mut self, learning_rate: f32);
+
+    /// Optimizer state keyed by "{parameter name}.{buffer name}"
+    fn state_dict(&self) -> HashMap<String, T>;
+
+    fn load_state_dict(&mut self, state: HashMap<String, T>);
+}
+
+/// Learning rates for groups of parameters selected by name prefix, e.g. a smaller learning rate
+/// for everything under "encoder.". The first matching group is used, parameters not matching any
+/// group use the optimizer learning rate.
+///
+/// Group learning rates are given for the optimizer learning rate in the config. When the optimizer
+/// learning rate changes, e.g. through a scheduler, every group keeps its ratio to it.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ParamGroups {
+    groups: Vec<(String, f32)>,
+    /// Optimizer learning rate the groups were configured for, known once it first changes
+    base_learning_rate: Option<f32>,
+}
+
+impl ParamGroups {
+    pub fn new() -> Self {
+        ParamGroups {
+            groups: vec![],
+            base_learning_rate: None,
+        }
+    }
+
+    pub fn with_group(mut self, prefix: &str, learning_rate: f32) -> Self {
+        self.groups.push((prefix.to_string(), learning_rate));
+        self
+    }
+
+    /// Learning rate of `param_name` when the optimizer learning rate is `default`
+    pub fn learning_rate(&self, param_name: &str, default: f32) -> f32 {
+        self.groups.iter()
+            .find(|(prefix, _learning_rate)| param_name.starts_with(prefix.as_str()))
+            .map(|(_prefix, learning_rate)| match self.base_learning_rate {
+                Some(base) if base != 0. => learning_rate * default / base,
+                _ => *learning_rate,
+            })
+            .unwrap_or(default)
+    }
+
+    /// Changes the optimizer `learning_rate` the groups belong to. The configured one is remembered
+    /// the first time, as the group learning rates are relative to it.
+    pub(crate) fn set_learning_rate(&mut self, learning_rate: &mut f32, new_learning_rate: f32) {
+        self.base_learning_rate.get_or_insert(*learning_rate);
+        *learning_rate = new_learning_rate;
+    }
+}
+
+/// Per parameter buffers of an optimizer, keyed by "{parameter name}.{buffer name}"
+#[derive(Debug)]
+pub(crate) struct ParamState<T: TensorBackend> {
+    buffers: HashMap<String, T>,
+}
+
+impl <T: TensorBackend> ParamState<T> {
+    pub(crate) fn new() -> Self {
+        ParamState {
+            buffers: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn get(&self, param_name: &str, buffer: &str) -> Option<&T> {
+        self.buffers.get(&format!("{}.{}", param_name, buffer))
+    }
+
+    /// Buffer value or zeros of the given shape if it was not created yet
+    pub(crate) fn get_or_zeros(&self, param_name: &str, buffer: &str, shape: &[usize]) -> T {
+        self.get(param_name, buffer).cloned().unwrap_or_else(|| T::zeros(shape))
+    }
+
+    pub(crate) fn insert(&mut self, param_name: &str, buffer: &str, value: T) {
+        self.buffers.insert(format!("{}.{}", param_name, buffer), value);
+    }
+
+    /// Increments the number of steps taken for the parameter and returns it, starting at 1.
+    /// Stored as a tensor of shape [1] so it is saved along with the other buffers.
+    pub(crate) fn increment_step(&mut self, param_name: &str) -> i32 {
+        let step = self.get(param_name, "step").map(|step| step.index(&[0]) as i32).unwrap_or(0) + 1;
+        self.insert(param_name, "step", T::from_slice(&[step as f32]));
+        step
+    }
+
+    pub(crate) fn to_map(&self) -> HashMap<String, T> {
+        self.buffers.clone()
+    }
+
+    pub(crate) fn from_map(buffers: HashMap<String, T>) -> Self {
+        ParamState {
+            buffers,
+        }
+    }
+}
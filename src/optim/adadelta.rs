@@ -0,0 +1,114 @@
+use crate::TrackedTensor;
+use crate::tensor_backends::TensorBackend;
+use crate::tape::Grad;
+use crate::optim::{Optimizer, ParamGroups, ParamState};
+use std::collections::HashMap;
+
+
+/// Configuration of the Adadelta optimizer
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdadeltaConfig {
+    /// Scales the computed update, 1.0 in the original algorithm
+    pub learning_rate: f32,
+    /// Decay rate of the running averages
+    pub rho: f32,
+    pub eps: f32,
+    pub weight_decay: f32,
+    pub param_groups: ParamGroups,
+}
+
+impl AdadeltaConfig {
+    pub fn new(learning_rate: f32) -> Self {
+        AdadeltaConfig {
+            learning_rate,
+            rho: 0.9,
+            eps: 1e-6,
+            weight_decay: 0.,
+            param_groups: ParamGroups::new(),
+        }
+    }
+}
+
+/// Adadelta, following the PyTorch formulation:
+///
+/// g = grad + weight_decay * p
+/// v = rho * v + (1 - rho) * g^2
+/// delta = sqrt(u + eps) / sqrt(v + eps) * g
+/// u = rho * u + (1 - rho) * delta^2
+/// p = p - learning_rate * delta
+#[derive(Debug)]
+pub struct Adadelta<T: TensorBackend> {
+    config: AdadeltaConfig,
+    state: ParamState<T>,
+}
+
+impl <T: TensorBackend> Adadelta<T> {
+    pub fn new(config: AdadeltaConfig) -> Self {
+        Adadelta {
+            config,
+            state: ParamState::new(),
+        }
+    }
+
+    pub fn config(&self) -> &AdadeltaConfig {
+        &self.config
+    }
+}
+
+impl <T: TensorBackend> Optimizer<T> for Adadelta<T> {
+    fn step(&mut self, params: &[(String, &TrackedTensor<'_, T>)], grad: &Grad<T>, params_store: &mut HashMap<String, T>) {
+        let config = &self.config;
+        let rho = config.rho;
+        for (name, param) in params {
+            let mut grad = match grad.wrt_data(param) {
+                None => continue,
+                Some(grad) => grad.clone(),
+            };
+            if config.weight_decay != 0. {
+                grad = grad.add(&param.data().mul_scalar(config.weight_decay));
+            }
+            let square_avg = self.state.get_or_zeros(name, "square_avg", grad.shape())
+                .mul_scalar(rho)
+                .add(&grad.square().mul_scalar(1. - rho));
+            let acc_delta = self.state.get_or_zeros(name, "acc_delta", grad.shape());
+            let delta = acc_delta.add_scalar(config.eps).sqrt()
+                .div(&square_avg.add_scalar(config.eps).sqrt())
+                .mul(&grad);
+            let acc_delta = acc_delta.mul_scalar(rho).add(&delta.square().mul_scalar(1. - rho));
+            let learning_rate = config.param_groups.learning_rate(name, config.learning_rate);
+            params_store.insert(name.clone(), param.data().sub(&delta.mul_scalar(learning_rate)));
+            self.state.insert(name, "square_avg", square_avg);
+            self.state.insert(name, "acc_delta", acc_delta);
+        }
+    }
+
+    fn learning_rate(&self) -> f32 {
+        self.config.learning_rate
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: f32) {
+        self.config.param_groups.set_learning_rate(&mut self.config.learning_rate, learning_rate);
+    }
+
+    fn state_dict(&self) -> HashMap<String, T> {
+        self.state.to_map()
+    }
+
+    fn load_state_dict(&mut self, state: HashMap<String, T>) {
+        self.state = ParamState::from_map(state);
+    }
+}
+
+
+#[cfg(test)]
+mod adadelta_tests {
+    use super::*;
+    use crate::optim::testing::{quadratic_trajectory, assert_trajectory_close};
+
+    #[test]
+    fn adadelta() {
+        let config = AdadeltaConfig { weight_decay: 0.1, ..AdadeltaConfig::new(1.) };
+        let trajectory = quadratic_trajectory(&mut Adadelta::new(config), 3);
+        assert_trajectory_close(&trajectory, &[[0.9968377, -1.9968377], [0.9935982, -1.9935957], [0.9903091, -1.9903008]]);
+    }
+}
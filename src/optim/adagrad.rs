@@ -0,0 +1,115 @@
+use crate::TrackedTensor;
+use crate::tensor_backends::TensorBackend;
+use crate::tape::Grad;
+use crate::optim::{Optimizer, ParamGroups, ParamState};
+use std::collections::HashMap;
+
+
+/// Configuration of the Adagrad optimizer
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdagradConfig {
+    pub learning_rate: f32,
+    /// The learning rate at step t is learning_rate / (1 + (t - 1) * learning_rate_decay)
+    pub learning_rate_decay: f32,
+    pub weight_decay: f32,
+    /// Starting value of the sum of squared gradients
+    pub initial_accumulator_value: f32,
+    pub eps: f32,
+    pub param_groups: ParamGroups,
+}
+
+impl AdagradConfig {
+    pub fn new(learning_rate: f32) -> Self {
+        AdagradConfig {
+            learning_rate,
+            learning_rate_decay: 0.,
+            weight_decay: 0.,
+            initial_accumulator_value: 0.,
+            eps: 1e-10,
+            param_groups: ParamGroups::new(),
+        }
+    }
+}
+
+/// Adagrad, following the PyTorch formulation:
+///
+/// g = grad + weight_decay * p
+/// sum = sum + g^2
+/// p = p - learning_rate / (1 + (t - 1) * learning_rate_decay) * g / (sqrt(sum) + eps)
+#[derive(Debug)]
+pub struct Adagrad<T: TensorBackend> {
+    config: AdagradConfig,
+    state: ParamState<T>,
+}
+
+impl <T: TensorBackend> Adagrad<T> {
+    pub fn new(config: AdagradConfig) -> Self {
+        Adagrad {
+            config,
+            state: ParamState::new(),
+        }
+    }
+
+    pub fn config(&self) -> &AdagradConfig {
+        &self.config
+    }
+}
+
+impl <T: TensorBackend> Optimizer<T> for Adagrad<T> {
+    fn step(&mut self, params: &[(String, &TrackedTensor<'_, T>)], grad: &Grad<T>, params_store: &mut HashMap<String, T>) {
+        let config = &self.config;
+        for (name, param) in params {
+            let mut grad = match grad.wrt_data(param) {
+                None => continue,
+                Some(grad) => grad.clone(),
+            };
+            if config.weight_decay != 0. {
+                grad = grad.add(&param.data().mul_scalar(config.weight_decay));
+            }
+            let step = self.state.increment_step(name);
+            let sum = match self.state.get(name, "sum") {
+                None => {
+                    let mut sum = T::zeros(grad.shape());
+                    sum.fill_with(config.initial_accumulator_value);
+                    sum
+                }
+                Some(sum) => sum.clone(),
+            }.add(&grad.square());
+            let learning_rate = config.param_groups.learning_rate(name, config.learning_rate)
+                / (1. + (step - 1) as f32 * config.learning_rate_decay);
+            let update = grad.div(&sum.sqrt().add_scalar(config.eps)).mul_scalar(learning_rate);
+            params_store.insert(name.clone(), param.data().sub(&update));
+            self.state.insert(name, "sum", sum);
+        }
+    }
+
+    fn learning_rate(&self) -> f32 {
+        self.config.learning_rate
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: f32) {
+        self.config.param_groups.set_learning_rate(&mut self.config.learning_rate, learning_rate);
+    }
+
+    fn state_dict(&self) -> HashMap<String, T> {
+        self.state.to_map()
+    }
+
+    fn load_state_dict(&mut self, state: HashMap<String, T>) {
+        self.state = ParamState::from_map(state);
+    }
+}
+
+
+#[cfg(test)]
+mod adagrad_tests {
+    use super::*;
+    use crate::optim::testing::{quadratic_trajectory, assert_trajectory_close};
+
+    #[test]
+    fn adagrad() {
+        let config = AdagradConfig { learning_rate_decay: 0.1, initial_accumulator_value: 0.1, ..AdagradConfig::new(0.1) };
+        let trajectory = quadratic_trajectory(&mut Adagrad::new(config), 3);
+        assert_trajectory_close(&trajectory, &[[0.9046537, -1.901227], [0.8452765, -1.8389995], [0.8018653, -1.7929944]]);
+    }
+}
@@ -0,0 +1,164 @@
+use crate::TrackedTensor;
+use crate::tensor_backends::TensorBackend;
+use crate::tape::Grad;
+use crate::optim::{Optimizer, ParamGroups, ParamState};
+use std::collections::HashMap;
+
+
+/// Configuration of the Adam optimizer, see `AdamConfig::adam_w` for AdamW
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdamConfig {
+    pub learning_rate: f32,
+    /// Decay rates of the running averages of the gradient and of its square
+    pub betas: (f32, f32),
+    pub eps: f32,
+    pub weight_decay: f32,
+    /// If true the weight decay is applied directly to the parameters (AdamW) instead of being
+    /// added to the gradient as an L2 penalty
+    pub decoupled_weight_decay: bool,
+    /// Use the maximum of the past squared gradient averages (AMSGrad)
+    pub amsgrad: bool,
+    pub param_groups: ParamGroups,
+}
+
+impl AdamConfig {
+    pub fn new(learning_rate: f32) -> Self {
+        AdamConfig {
+            learning_rate,
+            betas: (0.9, 0.999),
+            eps: 1e-8,
+            weight_decay: 0.,
+            decoupled_weight_decay: false,
+            amsgrad: false,
+            param_groups: ParamGroups::new(),
+        }
+    }
+
+    /// AdamW, Adam with decoupled weight decay
+    pub fn adam_w(learning_rate: f32, weight_decay: f32) -> Self {
+        AdamConfig {
+            weight_decay,
+            decoupled_weight_decay: true,
+            ..Self::new(learning_rate)
+        }
+    }
+}
+
+/// Adam, following the PyTorch formulation:
+///
+/// p = p * (1 - learning_rate * weight_decay) if decoupled else g = g + weight_decay * p
+/// m = beta1 * m + (1 - beta1) * g
+/// v = beta2 * v + (1 - beta2) * g^2, v_max = max(v_max, v) if amsgrad
+/// p = p - learning_rate * (m / (1 - beta1^t)) / (sqrt(v or v_max) / sqrt(1 - beta2^t) + eps)
+#[derive(Debug)]
+pub struct Adam<T: TensorBackend> {
+    config: AdamConfig,
+    state: ParamState<T>,
+}
+
+impl <T: TensorBackend> Adam<T> {
+    pub fn new(config: AdamConfig) -> Self {
+        Adam {
+            config,
+            state: ParamState::new(),
+        }
+    }
+
+    pub fn config(&self) -> &AdamConfig {
+        &self.config
+    }
+}
+
+impl <T: TensorBackend> Optimizer<T> for Adam<T> {
+    fn step(&mut self, params: &[(String, &TrackedTensor<'_, T>)], grad: &Grad<T>, params_store: &mut HashMap<String, T>) {
+        let config = &self.config;
+        let (beta1, beta2) = config.betas;
+        for (name, param) in params {
+            let mut grad = match grad.wrt_data(param) {
+                None => continue,
+                Some(grad) => grad.clone(),
+            };
+            let learning_rate = config.param_groups.learning_rate(name, config.learning_rate);
+            let mut value = param.data().clone();
+            if config.weight_decay != 0. {
+                if config.decoupled_weight_decay {
+                    value = value.mul_scalar(1. - learning_rate * config.weight_decay);
+                } else {
+                    grad = grad.add(&value.mul_scalar(config.weight_decay));
+                }
+            }
+            let step = self.state.increment_step(name);
+            let exp_avg = self.state.get_or_zeros(name, "exp_avg", grad.shape())
+                .mul_scalar(beta1)
+                .add(&grad.mul_scalar(1. - beta1));
+            let exp_avg_sq = self.state.get_or_zeros(name, "exp_avg_sq", grad.shape())
+                .mul_scalar(beta2)
+                .add(&grad.square().mul_scalar(1. - beta2));
+            let second_moment = if config.amsgrad {
+                let max_exp_avg_sq = self.state.get_or_zeros(name, "max_exp_avg_sq", grad.shape())
+                    .zip_map(&exp_avg_sq, f32::max);
+                self.state.insert(name, "max_exp_avg_sq", max_exp_avg_sq.clone());
+                max_exp_avg_sq
+            } else {
+                exp_avg_sq.clone()
+            };
+            let bias_correction1 = 1. - beta1.powi(step);
+            let bias_correction2 = 1. - beta2.powi(step);
+            let denominator = second_moment.sqrt()
+                .div_scalar(bias_correction2.sqrt())
+                .add_scalar(config.eps);
+            let update = exp_avg.div(&denominator).mul_scalar(learning_rate / bias_correction1);
+            params_store.insert(name.clone(), value.sub(&update));
+            self.state.insert(name, "exp_avg", exp_avg);
+            self.state.insert(name, "exp_avg_sq", exp_avg_sq);
+        }
+    }
+
+    fn learning_rate(&self) -> f32 {
+        self.config.learning_rate
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: f32) {
+        self.config.param_groups.set_learning_rate(&mut self.config.learning_rate, learning_rate);
+    }
+
+    fn state_dict(&self) -> HashMap<String, T> {
+        self.state.to_map()
+    }
+
+    fn load_state_dict(&mut self, state: HashMap<String, T>) {
+        self.state = ParamState::from_map(state);
+    }
+}
+
+
+#[cfg(test)]
+mod adam_tests {
+    use super::*;
+    use crate::optim::testing::{quadratic_trajectory, assert_trajectory_close};
+
+    #[test]
+    fn adam() {
+        let trajectory = quadratic_trajectory(&mut Adam::new(AdamConfig::new(0.1)), 3);
+        assert_trajectory_close(&trajectory, &[[0.9, -1.9], [0.8004122, -1.8001665], [0.7015863, -1.7006234]]);
+    }
+
+    #[test]
+    fn amsgrad() {
+        // With beta2 = 0.5 the squared gradient average of p[0] drops at step 3, so AMSGrad keeps
+        // the maximum and takes a smaller step than Adam
+        let config = AdamConfig { betas: (0.9, 0.5), ..AdamConfig::new(0.1) };
+        let trajectory = quadratic_trajectory(&mut Adam::new(config.clone()), 4);
+        assert_trajectory_close(&trajectory, &[[0.9, -1.9], [0.7986255, -1.799304], [0.6947884, -1.6974448], [0.5871884, -1.5939687]]);
+
+        let config = AdamConfig { amsgrad: true, ..config };
+        let trajectory = quadratic_trajectory(&mut Adam::new(config), 4);
+        assert_trajectory_close(&trajectory, &[[0.9, -1.9], [0.7986255, -1.799304], [0.6954723, -1.6974448], [0.5955522, -1.595174]]);
+    }
+
+    #[test]
+    fn adam_w() {
+        let trajectory = quadratic_trajectory(&mut Adam::new(AdamConfig::adam_w(0.1, 0.1)), 3);
+        assert_trajectory_close(&trajectory, &[[0.89, -1.88], [0.7815719, -1.761409], [0.6751012, -1.6443687]]);
+    }
+}
@@ -0,0 +1,408 @@
+use crate::tensor_backends::TensorBackend;
+use crate::optim::Optimizer;
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+
+/// Learning rate as a function of the number of steps taken. What a step is (an optimizer step or
+/// an epoch) is up to the caller, it is whatever calls `LrScheduler::step`.
+pub trait LrSchedule: std::fmt::Debug {
+    fn learning_rate_at(&self, base_learning_rate: f32, step: usize) -> f32;
+}
+
+/// Multiplies the learning rate by gamma every step_size steps
+#[derive(Debug, Clone, PartialEq)]
+pub struct StepLr {
+    pub step_size: usize,
+    pub gamma: f32,
+}
+
+impl LrSchedule for StepLr {
+    fn learning_rate_at(&self, base_learning_rate: f32, step: usize) -> f32 {
+        assert!(self.step_size > 0, "The step_size must be positive");
+        base_learning_rate * self.gamma.powi((step / self.step_size) as i32)
+    }
+}
+
+/// Multiplies the learning rate by gamma at each of the milestones
+#[derive(Debug, Clone, PartialEq)]
+pub struct MultiStepLr {
+    pub milestones: Vec<usize>,
+    pub gamma: f32,
+}
+
+impl LrSchedule for MultiStepLr {
+    fn learning_rate_at(&self, base_learning_rate: f32, step: usize) -> f32 {
+        let passed = self.milestones.iter().filter(|milestone| **milestone <= step).count();
+        base_learning_rate * self.gamma.powi(passed as i32)
+    }
+}
+
+/// Multiplies the learning rate by gamma every step
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExponentialLr {
+    pub gamma: f32,
+}
+
+impl LrSchedule for ExponentialLr {
+    fn learning_rate_at(&self, base_learning_rate: f32, step: usize) -> f32 {
+        base_learning_rate * self.gamma.powi(step as i32)
+    }
+}
+
+/// Anneals from the base learning rate to min_learning_rate following a cosine over period
+/// steps, then restarts with a period t_mult times longer (SGDR)
+#[derive(Debug, Clone, PartialEq)]
+pub struct CosineAnnealingWarmRestarts {
+    pub period: usize,
+    pub t_mult: usize,
+    pub min_learning_rate: f32,
+}
+
+impl LrSchedule for CosineAnnealingWarmRestarts {
+    fn learning_rate_at(&self, base_learning_rate: f32, step: usize) -> f32 {
+        assert!(self.period > 0 && self.t_mult > 0, "The period and t_mult must be positive");
+        let mut period = self.period;
+        let mut position = step;
+        while position >= period {
+            position -= period;
+            period *= self.t_mult;
+        }
+        cosine_interpolation(base_learning_rate, self.min_learning_rate, position as f32 / period as f32)
+    }
+}
+
+/// Goes linearly from start_factor * base learning rate to the base learning rate over
+/// warmup_steps steps and then follows another schedule, constant by default
+#[derive(Debug)]
+pub struct LinearWarmup {
+    pub warmup_steps: usize,
+    pub start_factor: f32,
+    pub then: Option<Box<dyn LrSchedule>>,
+}
+
+impl LinearWarmup {
+    pub fn new(warmup_steps: usize) -> Self {
+        LinearWarmup {
+            warmup_steps,
+            start_factor: 0.,
+            then: None,
+        }
+    }
+
+    /// Schedule used after the warmup, its steps are counted from the end of the warmup
+    pub fn then<S: LrSchedule + 'static>(mut self, schedule: S) -> Self {
+        self.then = Some(Box::new(schedule));
+        self
+    }
+}
+
+impl LrSchedule for LinearWarmup {
+    fn learning_rate_at(&self, base_learning_rate: f32, step: usize) -> f32 {
+        if step < self.warmup_steps {
+            let progress = step as f32 / self.warmup_steps as f32;
+            return base_learning_rate * (self.start_factor + (1. - self.start_factor) * progress);
+        }
+        match &self.then {
+            None => base_learning_rate,
+            Some(schedule) => schedule.learning_rate_at(base_learning_rate, step - self.warmup_steps),
+        }
+    }
+}
+
+/// The 1cycle policy: anneals from max_learning_rate / div_factor up to max_learning_rate during
+/// the first pct_start of total_steps and then down to
+/// max_learning_rate / (div_factor * final_div_factor), following cosines.
+/// The base learning rate of the optimizer is ignored.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OneCycle {
+    pub max_learning_rate: f32,
+    pub total_steps: usize,
+    pub pct_start: f32,
+    pub div_factor: f32,
+    pub final_div_factor: f32,
+}
+
+impl OneCycle {
+    pub fn new(max_learning_rate: f32, total_steps: usize) -> Self {
+        OneCycle {
+            max_learning_rate,
+            total_steps,
+            pct_start: 0.3,
+            div_factor: 25.,
+            final_div_factor: 1e4,
+        }
+    }
+}
+
+impl LrSchedule for OneCycle {
+    fn learning_rate_at(&self, _base_learning_rate: f32, step: usize) -> f32 {
+        let initial = self.max_learning_rate / self.div_factor;
+        let min = initial / self.final_div_factor;
+        let warmup_end = self.pct_start * self.total_steps as f32 - 1.;
+        let last_step = self.total_steps as f32 - 1.;
+        let step = (step as f32).min(last_step);
+        if step <= warmup_end {
+            // A warmup ending at step 0 starts right at the maximum
+            let progress = if warmup_end > 0. { step / warmup_end } else { 1. };
+            cosine_interpolation(initial, self.max_learning_rate, progress)
+        } else {
+            cosine_interpolation(self.max_learning_rate, min, (step - warmup_end) / (last_step - warmup_end))
+        }
+    }
+}
+
+/// Goes from start (progress 0) to end (progress 1) following half a cosine
+fn cosine_interpolation(start: f32, end: f32, progress: f32) -> f32 {
+    end + (start - end) / 2. * (1. + (PI * progress).cos())
+}
+
+/// Sets the learning rate of an optimizer following a schedule. The learning rate of the
+/// optimizer when the scheduler is created is used as the base learning rate.
+#[derive(Debug)]
+pub struct LrScheduler<S: LrSchedule> {
+    schedule: S,
+    base_learning_rate: f32,
+    steps: usize,
+}
+
+impl <S: LrSchedule> LrScheduler<S> {
+    /// Also sets the learning rate for step 0
+    pub fn new<T: TensorBackend>(schedule: S, optimizer: &mut dyn Optimizer<T>) -> Self {
+        let base_learning_rate = optimizer.learning_rate();
+        let scheduler = LrScheduler {
+            schedule,
+            base_learning_rate,
+            steps: 0,
+        };
+        optimizer.set_learning_rate(scheduler.learning_rate());
+        scheduler
+    }
+
+    pub fn step<T: TensorBackend>(&mut self, optimizer: &mut dyn Optimizer<T>) {
+        self.steps += 1;
+        optimizer.set_learning_rate(self.learning_rate());
+    }
+
+    /// Learning rate for the current step
+    pub fn learning_rate(&self) -> f32 {
+        self.schedule.learning_rate_at(self.base_learning_rate, self.steps)
+    }
+
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    /// In the same format as the parameter store so they can be saved together
+    pub fn state_dict<T: TensorBackend>(&self) -> HashMap<String, T> {
+        let mut state = HashMap::new();
+        state.insert("base_learning_rate".to_string(), T::from_slice(&[self.base_learning_rate]));
+        state.insert("steps".to_string(), T::from_slice(&[self.steps as f32]));
+        state
+    }
+
+    /// Restores the position in the schedule and sets the matching learning rate
+    pub fn load_state_dict<T: TensorBackend>(&mut self, state: &HashMap<String, T>, optimizer: &mut dyn Optimizer<T>) {
+        self.base_learning_rate = scalar_state(state, "base_learning_rate");
+        self.steps = scalar_state(state, "steps") as usize;
+        optimizer.set_learning_rate(self.learning_rate());
+    }
+}
+
+fn scalar_state<T: TensorBackend>(state: &HashMap<String, T>, key: &str) -> f32 {
+    match state.get(key) {
+        None => panic!("Missing {} in the scheduler state", key),
+        Some(value) => value.index(&[0]),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlateauMode {
+    /// Lower metric values are better, like a loss
+    Min,
+    /// Higher metric values are better, like an accuracy
+    Max,
+}
+
+/// Multiplies the learning rate by factor when a metric stops improving for more than patience
+/// steps. An improvement must beat the best value by a relative threshold.
+#[derive(Debug)]
+pub struct ReduceLrOnPlateau {
+    pub mode: PlateauMode,
+    pub factor: f32,
+    pub patience: usize,
+    pub threshold: f32,
+    /// Steps to wait after a reduction before counting bad steps again
+    pub cooldown: usize,
+    pub min_learning_rate: f32,
+    best: Option<f32>,
+    bad_steps: usize,
+    cooldown_left: usize,
+}
+
+impl ReduceLrOnPlateau {
+    pub fn new(mode: PlateauMode) -> Self {
+        ReduceLrOnPlateau {
+            mode,
+            factor: 0.1,
+            patience: 10,
+            threshold: 1e-4,
+            cooldown: 0,
+            min_learning_rate: 0.,
+            best: None,
+            bad_steps: 0,
+            cooldown_left: 0,
+        }
+    }
+
+    fn is_improvement(&self, metric: f32) -> bool {
+        match (self.best, self.mode) {
+            (None, _) => true,
+            (Some(best), PlateauMode::Min) => metric < best * (1. - self.threshold),
+            (Some(best), PlateauMode::Max) => metric > best * (1. + self.threshold),
+        }
+    }
+
+    pub fn step<T: TensorBackend>(&mut self, metric: f32, optimizer: &mut dyn Optimizer<T>) {
+        if self.is_improvement(metric) {
+            self.best = Some(metric);
+            self.bad_steps = 0;
+        } else {
+            self.bad_steps += 1;
+        }
+        if self.cooldown_left > 0 {
+            self.cooldown_left -= 1;
+            self.bad_steps = 0;
+        }
+        if self.bad_steps > self.patience {
+            let reduced = (optimizer.learning_rate() * self.factor).max(self.min_learning_rate);
+            optimizer.set_learning_rate(reduced);
+            self.cooldown_left = self.cooldown;
+            self.bad_steps = 0;
+        }
+    }
+
+    /// In the same format as the parameter store so they can be saved together
+    pub fn state_dict<T: TensorBackend>(&self) -> HashMap<String, T> {
+        let mut state = HashMap::new();
+        if let Some(best) = self.best {
+            state.insert("best".to_string(), T::from_slice(&[best]));
+        }
+        state.insert("bad_steps".to_string(), T::from_slice(&[self.bad_steps as f32]));
+        state.insert("cooldown_left".to_string(), T::from_slice(&[self.cooldown_left as f32]));
+        state
+    }
+
+    pub fn load_state_dict<T: TensorBackend>(&mut self, state: &HashMap<String, T>) {
+        self.best = state.get("best").map(|best| best.index(&[0]));
+        self.bad_steps = scalar_state(state, "bad_steps") as usize;
+        self.cooldown_left = scalar_state(state, "cooldown_left") as usize;
+    }
+}
+
+
+#[cfg(test)]
+mod lr_scheduler_tests {
+    use super::*;
+    use crate::tensor_backends::NdArray;
+    use crate::optim::{Sgd, SgdConfig, ParamGroups};
+    use crate::ops::testing::assert_close;
+
+    fn learning_rates<S: LrSchedule>(schedule: &S, base_learning_rate: f32, steps: usize) -> Vec<f32> {
+        (0..steps).map(|step| schedule.learning_rate_at(base_learning_rate, step)).collect()
+    }
+
+    #[test]
+    fn step_schedules() {
+        assert_close(&learning_rates(&StepLr { step_size: 2, gamma: 0.5 }, 1., 5), &[1., 1., 0.5, 0.5, 0.25]);
+        let multi_step = MultiStepLr { milestones: vec![1, 4], gamma: 0.1 };
+        assert_close(&learning_rates(&multi_step, 1., 5), &[1., 0.1, 0.1, 0.1, 0.01]);
+        assert_close(&learning_rates(&ExponentialLr { gamma: 0.5 }, 2., 3), &[2., 1., 0.5]);
+    }
+
+    #[test]
+    #[should_panic(expected = "The step_size must be positive")]
+    fn zero_step_size() {
+        StepLr { step_size: 0, gamma: 0.5 }.learning_rate_at(1., 3);
+    }
+
+    #[test]
+    fn cosine_warm_restarts() {
+        let schedule = CosineAnnealingWarmRestarts { period: 2, t_mult: 2, min_learning_rate: 0. };
+        // Periods of 2 and 4 steps: cos(0), cos(pi/2), restart, cos(0), cos(pi/4), cos(pi/2), ...
+        let half_sqrt2 = (1. + 0.5f32.sqrt()) / 2.;
+        assert_close(&learning_rates(&schedule, 1., 7), &[1., 0.5, 1., half_sqrt2, 0.5, 1. - half_sqrt2, 1.]);
+    }
+
+    #[test]
+    fn warmup_then_decay() {
+        let schedule = LinearWarmup::new(4).then(ExponentialLr { gamma: 0.5 });
+        assert_close(&learning_rates(&schedule, 1., 6), &[0., 0.25, 0.5, 0.75, 1., 0.5]);
+    }
+
+    #[test]
+    fn one_cycle() {
+        let schedule = OneCycle { pct_start: 0.5, div_factor: 10., final_div_factor: 10., ..OneCycle::new(1., 5) };
+        // Up from 0.1 to 1 over steps 0..=1.5 then down to 0.01 at step 4
+        let rates = learning_rates(&schedule, 123., 6);
+        assert_close(&rates[..1], &[0.1]);
+        assert!(rates[1] > 0.7 && rates[1] < 1.);
+        assert_close(&rates[4..], &[0.01, 0.01]);
+        assert!(rates[2] > rates[3] && rates[3] > rates[4]);
+
+        // A warmup of a single step peaks right away
+        let schedule = OneCycle { pct_start: 0.25, ..OneCycle::new(1., 4) };
+        let rates = learning_rates(&schedule, 1., 4);
+        assert_close(&rates[..1], &[1.]);
+        assert!(rates.iter().all(|rate| rate.is_finite()));
+    }
+
+    #[test]
+    fn drives_optimizer() {
+        let config = SgdConfig { param_groups: ParamGroups::new().with_group("head.", 0.2), ..SgdConfig::new(0.1) };
+        let mut optimizer: Sgd<NdArray> = Sgd::new(config);
+        let mut scheduler = LrScheduler::new(StepLr { step_size: 1, gamma: 0.5 }, &mut optimizer);
+        scheduler.step(&mut optimizer);
+        assert!((optimizer.learning_rate() - 0.05).abs() < 1e-7);
+        assert!((optimizer.config().param_groups.learning_rate("head.bias", 0.05) - 0.1).abs() < 1e-7);
+
+        let state: HashMap<String, NdArray> = scheduler.state_dict();
+        let mut fresh: Sgd<NdArray> = Sgd::new(SgdConfig::new(0.1));
+        let mut restored = LrScheduler::new(StepLr { step_size: 1, gamma: 0.5 }, &mut fresh);
+        restored.load_state_dict(&state, &mut fresh);
+        assert_eq!(restored.steps(), 1);
+        assert!((fresh.learning_rate() - 0.05).abs() < 1e-7);
+    }
+
+    #[test]
+    fn warms_up_param_groups_from_zero() {
+        let config = SgdConfig { param_groups: ParamGroups::new().with_group("encoder.", 0.1), ..SgdConfig::new(0.2) };
+        let mut optimizer: Sgd<NdArray> = Sgd::new(config);
+        let mut scheduler = LrScheduler::new(LinearWarmup::new(2), &mut optimizer);
+        assert_eq!(optimizer.config().param_groups.learning_rate("encoder.w", optimizer.learning_rate()), 0.);
+        scheduler.step(&mut optimizer);
+        let encoder_learning_rate = optimizer.config().param_groups.learning_rate("encoder.w", optimizer.learning_rate());
+        assert!((encoder_learning_rate - 0.05).abs() < 1e-7);
+        scheduler.step(&mut optimizer);
+        let encoder_learning_rate = optimizer.config().param_groups.learning_rate("encoder.w", optimizer.learning_rate());
+        assert!((encoder_learning_rate - 0.1).abs() < 1e-7);
+    }
+
+    #[test]
+    fn reduce_on_plateau() {
+        let mut optimizer: Sgd<NdArray> = Sgd::new(SgdConfig::new(1.));
+        let mut scheduler = ReduceLrOnPlateau { patience: 1, cooldown: 1, factor: 0.5, ..ReduceLrOnPlateau::new(PlateauMode::Min) };
+        let mut rates = vec![];
+        for metric in &[1., 0.5, 0.6, 0.6, 0.6, 0.6, 0.6, 0.4] {
+            scheduler.step(*metric, &mut optimizer);
+            rates.push(optimizer.learning_rate());
+        }
+        // Second bad step reduces, one step of cooldown, then two more bad steps reduce again
+        assert_close(&rates, &[1., 1., 1., 0.5, 0.5, 0.5, 0.25, 0.25]);
+
+        let state: HashMap<String, NdArray> = scheduler.state_dict();
+        let mut restored = ReduceLrOnPlateau::new(PlateauMode::Min);
+        restored.load_state_dict(&state);
+        assert_eq!(restored.best, Some(0.4));
+    }
+}
@@ -0,0 +1,136 @@
+use crate::TrackedTensor;
+use crate::tensor_backends::TensorBackend;
+use crate::tape::Grad;
+use crate::optim::{Optimizer, ParamGroups, ParamState};
+use std::collections::HashMap;
+
+
+/// Configuration of the RmsProp optimizer
+#[derive(Debug, Clone, PartialEq)]
+pub struct RmsPropConfig {
+    pub learning_rate: f32,
+    /// Decay rate of the running average of the squared gradient
+    pub alpha: f32,
+    pub eps: f32,
+    pub weight_decay: f32,
+    pub momentum: f32,
+    /// Normalize by an estimate of the gradient variance instead of its second moment
+    pub centered: bool,
+    pub param_groups: ParamGroups,
+}
+
+impl RmsPropConfig {
+    pub fn new(learning_rate: f32) -> Self {
+        RmsPropConfig {
+            learning_rate,
+            alpha: 0.99,
+            eps: 1e-8,
+            weight_decay: 0.,
+            momentum: 0.,
+            centered: false,
+            param_groups: ParamGroups::new(),
+        }
+    }
+}
+
+/// RMSprop, following the PyTorch formulation:
+///
+/// g = grad + weight_decay * p
+/// v = alpha * v + (1 - alpha) * g^2
+/// avg = sqrt(v - g_avg^2) + eps with g_avg = alpha * g_avg + (1 - alpha) * g if centered
+///       else sqrt(v) + eps
+/// buf = momentum * buf + g / avg, p = p - learning_rate * buf if momentum > 0
+///       else p = p - learning_rate * g / avg
+#[derive(Debug)]
+pub struct RmsProp<T: TensorBackend> {
+    config: RmsPropConfig,
+    state: ParamState<T>,
+}
+
+impl <T: TensorBackend> RmsProp<T> {
+    pub fn new(config: RmsPropConfig) -> Self {
+        RmsProp {
+            config,
+            state: ParamState::new(),
+        }
+    }
+
+    pub fn config(&self) -> &RmsPropConfig {
+        &self.config
+    }
+}
+
+impl <T: TensorBackend> Optimizer<T> for RmsProp<T> {
+    fn step(&mut self, params: &[(String, &TrackedTensor<'_, T>)], grad: &Grad<T>, params_store: &mut HashMap<String, T>) {
+        let config = &self.config;
+        let alpha = config.alpha;
+        for (name, param) in params {
+            let mut grad = match grad.wrt_data(param) {
+                None => continue,
+                Some(grad) => grad.clone(),
+            };
+            if config.weight_decay != 0. {
+                grad = grad.add(&param.data().mul_scalar(config.weight_decay));
+            }
+            let square_avg = self.state.get_or_zeros(name, "square_avg", grad.shape())
+                .mul_scalar(alpha)
+                .add(&grad.square().mul_scalar(1. - alpha));
+            let avg = if config.centered {
+                let grad_avg = self.state.get_or_zeros(name, "grad_avg", grad.shape())
+                    .mul_scalar(alpha)
+                    .add(&grad.mul_scalar(1. - alpha));
+                let avg = square_avg.sub(&grad_avg.square()).sqrt().add_scalar(config.eps);
+                self.state.insert(name, "grad_avg", grad_avg);
+                avg
+            } else {
+                square_avg.sqrt().add_scalar(config.eps)
+            };
+            let mut update = grad.div(&avg);
+            if config.momentum > 0. {
+                update = self.state.get_or_zeros(name, "momentum_buffer", grad.shape())
+                    .mul_scalar(config.momentum)
+                    .add(&update);
+                self.state.insert(name, "momentum_buffer", update.clone());
+            }
+            let learning_rate = config.param_groups.learning_rate(name, config.learning_rate);
+            params_store.insert(name.clone(), param.data().sub(&update.mul_scalar(learning_rate)));
+            self.state.insert(name, "square_avg", square_avg);
+        }
+    }
+
+    fn learning_rate(&self) -> f32 {
+        self.config.learning_rate
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: f32) {
+        self.config.param_groups.set_learning_rate(&mut self.config.learning_rate, learning_rate);
+    }
+
+    fn state_dict(&self) -> HashMap<String, T> {
+        self.state.to_map()
+    }
+
+    fn load_state_dict(&mut self, state: HashMap<String, T>) {
+        self.state = ParamState::from_map(state);
+    }
+}
+
+
+#[cfg(test)]
+mod rmsprop_tests {
+    use super::*;
+    use crate::optim::testing::{quadratic_trajectory, assert_trajectory_close};
+
+    #[test]
+    fn rmsprop() {
+        let trajectory = quadratic_trajectory(&mut RmsProp::new(RmsPropConfig::new(0.01)), 3);
+        assert_trajectory_close(&trajectory, &[[0.9, -1.9], [0.832918, -1.8309433], [0.7799823, -1.7753495]]);
+    }
+
+    #[test]
+    fn centered_with_momentum() {
+        let config = RmsPropConfig { centered: true, momentum: 0.9, ..RmsPropConfig::new(0.01) };
+        let trajectory = quadratic_trajectory(&mut RmsProp::new(config), 3);
+        assert_trajectory_close(&trajectory, &[[0.8994962, -1.8994962], [0.741306, -1.7392988], [0.5496502, -1.540678]]);
+    }
+}
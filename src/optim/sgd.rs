@@ -0,0 +1,195 @@
+use crate::TrackedTensor;
+use crate::tensor_backends::TensorBackend;
+use crate::tape::Grad;
+use crate::optim::{Optimizer, ParamGroups, ParamState};
+use std::collections::HashMap;
+
+
+/// Configuration of the Sgd optimizer, the defaults are plain SGD
+#[derive(Debug, Clone, PartialEq)]
+pub struct SgdConfig {
+    pub learning_rate: f32,
+    pub momentum: f32,
+    /// Fraction of the gradient left out of the momentum buffer update
+    pub dampening: f32,
+    /// L2 penalty, adds weight_decay * parameter to the gradient
+    pub weight_decay: f32,
+    /// Use Nesterov momentum, requires momentum > 0 and no dampening
+    pub nesterov: bool,
+    pub param_groups: ParamGroups,
+}
+
+impl SgdConfig {
+    pub fn new(learning_rate: f32) -> Self {
+        SgdConfig {
+            learning_rate,
+            momentum: 0.,
+            dampening: 0.,
+            weight_decay: 0.,
+            nesterov: false,
+            param_groups: ParamGroups::new(),
+        }
+    }
+}
+
+/// Stochastic gradient descent, following the PyTorch formulation:
+///
+/// g = grad + weight_decay * p
+/// buf = momentum * buf + (1 - dampening) * g  (buf = g on the first step)
+/// g = g + momentum * buf if nesterov else buf
+/// p = p - learning_rate * g
+#[derive(Debug)]
+pub struct Sgd<T: TensorBackend> {
+    config: SgdConfig,
+    state: ParamState<T>,
+}
+
+impl <T: TensorBackend> Sgd<T> {
+    pub fn new(config: SgdConfig) -> Self {
+        assert!(!config.nesterov || (config.momentum > 0. && config.dampening == 0.),
+                "Nesterov momentum requires a momentum and zero dampening");
+        Sgd {
+            config,
+            state: ParamState::new(),
+        }
+    }
+
+    pub fn config(&self) -> &SgdConfig {
+        &self.config
+    }
+}
+
+impl <T: TensorBackend> Optimizer<T> for Sgd<T> {
+    fn step(&mut self, params: &[(String, &TrackedTensor<'_, T>)], grad: &Grad<T>, params_store: &mut HashMap<String, T>) {
+        let config = &self.config;
+        for (name, param) in params {
+            let mut update = match grad.wrt_data(param) {
+                None => continue,
+                Some(grad) => grad.clone(),
+            };
+            if config.weight_decay != 0. {
+                update = update.add(&param.data().mul_scalar(config.weight_decay));
+            }
+            if config.momentum != 0. {
+                let buffer = match self.state.get(name, "momentum_buffer") {
+                    None => update.clone(),
+                    Some(buffer) => buffer.mul_scalar(config.momentum)
+                        .add(&update.mul_scalar(1. - config.dampening)),
+                };
+                update = if config.nesterov {
+                    update.add(&buffer.mul_scalar(config.momentum))
+                } else {
+                    buffer.clone()
+                };
+                self.state.insert(name, "momentum_buffer", buffer);
+            }
+            let learning_rate = config.param_groups.learning_rate(name, config.learning_rate);
+            let updated = param.data().sub(&update.mul_scalar(learning_rate));
+            params_store.insert(name.clone(), updated);
+        }
+    }
+
+    fn learning_rate(&self) -> f32 {
+        self.config.learning_rate
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: f32) {
+        self.config.param_groups.set_learning_rate(&mut self.config.learning_rate, learning_rate);
+    }
+
+    fn state_dict(&self) -> HashMap<String, T> {
+        self.state.to_map()
+    }
+
+    fn load_state_dict(&mut self, state: HashMap<String, T>) {
+        self.state = ParamState::from_map(state);
+    }
+}
+
+
+#[cfg(test)]
+mod sgd_tests {
+    use super::*;
+    use crate::tape::ComputationRecord;
+    use crate::tensor_backends::NdArray;
+    use crate::ops::*;
+    use crate::optim::testing::quadratic_trajectory;
+
+    fn assert_trajectory(actual: Vec<Vec<f32>>, first_coordinate: &[f32]) {
+        for (step, (actual, expected)) in actual.iter().zip(first_coordinate).enumerate() {
+            assert!((actual[0] - expected).abs() < 1e-5, "step {}: {} != {}", step, actual[0], expected);
+            // The problem is linear so the second coordinate follows the first one scaled by -2
+            assert!((actual[1] + 2. * expected).abs() < 1e-5, "step {}: {}", step, actual[1]);
+        }
+    }
+
+    #[test]
+    fn plain_sgd() {
+        // p = p - 0.1 * p
+        assert_trajectory(quadratic_trajectory(&mut Sgd::new(SgdConfig::new(0.1)), 3), &[0.9, 0.81, 0.729]);
+    }
+
+    #[test]
+    fn momentum_dampening_and_weight_decay() {
+        // buf_1 = 1, p_1 = 0.9; buf_2 = 0.9 + 0.9 = 1.8, p_2 = 0.72; buf_3 = 1.62 + 0.72 = 2.34, p_3 = 0.486
+        let config = SgdConfig { momentum: 0.9, ..SgdConfig::new(0.1) };
+        assert_trajectory(quadratic_trajectory(&mut Sgd::new(config), 3), &[0.9, 0.72, 0.486]);
+
+        // buf_1 = 1, p_1 = 0.9; buf_2 = 0.5 + 0.5 * 0.9 = 0.95, p_2 = 0.805
+        let config = SgdConfig { momentum: 0.5, dampening: 0.5, ..SgdConfig::new(0.1) };
+        assert_trajectory(quadratic_trajectory(&mut Sgd::new(config), 2), &[0.9, 0.805]);
+
+        // Weight decay 1 doubles the gradient
+        let config = SgdConfig { weight_decay: 1., ..SgdConfig::new(0.1) };
+        assert_trajectory(quadratic_trajectory(&mut Sgd::new(config), 2), &[0.8, 0.64]);
+    }
+
+    #[test]
+    fn nesterov() {
+        // buf_1 = 1, g = 1 + 0.9, p_1 = 0.81; buf_2 = 0.9 + 0.81 = 1.71, g = 0.81 + 1.539, p_2 = 0.5751
+        let config = SgdConfig { momentum: 0.9, nesterov: true, ..SgdConfig::new(0.1) };
+        assert_trajectory(quadratic_trajectory(&mut Sgd::new(config), 2), &[0.81, 0.5751]);
+    }
+
+    #[test]
+    fn state_round_trip() {
+        let config = SgdConfig { momentum: 0.9, ..SgdConfig::new(0.1) };
+        let mut optimizer: Sgd<NdArray> = Sgd::new(config.clone());
+        let mut store = HashMap::new();
+        let rec: ComputationRecord<NdArray> = ComputationRecord::new();
+        let p = rec.tensor_from_slice(&[1., -2.]);
+        let unused = rec.tensor_from_slice(&[3.]);
+        let loss = sum(&p);
+        let params = [("p".to_string(), &p), ("unused".to_string(), &unused)];
+        optimizer.step(&params, &loss.grad(), &mut store);
+        assert!(!store.contains_key("unused"));
+
+        let state = optimizer.state_dict();
+        assert_eq!(state.keys().collect::<Vec<_>>(), vec!["p.momentum_buffer"]);
+        let mut restored: Sgd<NdArray> = Sgd::new(config);
+        restored.load_state_dict(state);
+        assert_eq!(restored.state_dict(), optimizer.state_dict());
+    }
+
+    #[test]
+    fn param_groups() {
+        let config = SgdConfig {
+            param_groups: ParamGroups::new().with_group("encoder.", 0.1),
+            ..SgdConfig::new(0.2)
+        };
+        let mut optimizer: Sgd<NdArray> = Sgd::new(config);
+        let mut store = HashMap::new();
+        for expected_lr in &[(0.1, 0.2), (0.05, 0.1)] {
+            let rec: ComputationRecord<NdArray> = ComputationRecord::new();
+            let encoder = rec.tensor_from_slice(&[1.]);
+            let head = rec.tensor_from_slice(&[1.]);
+            let loss = add(&encoder, &head);
+            let params = [("encoder.weight".to_string(), &encoder), ("head.weight".to_string(), &head)];
+            optimizer.step(&params, &loss.grad(), &mut store);
+            assert_eq!(store["encoder.weight"], NdArray::from_slice(&[1. - expected_lr.0]));
+            assert_eq!(store["head.weight"], NdArray::from_slice(&[1. - expected_lr.1]));
+            // The group keeps its ratio to the optimizer learning rate
+            optimizer.set_learning_rate(0.1);
+        }
+    }
+}
@@ -0,0 +1,30 @@
+use crate::tape::ComputationRecord;
+use crate::tensor_backends::{NdArray, TensorBackend};
+use crate::optim::Optimizer;
+use crate::ops::*;
+use std::collections::HashMap;
+
+/// Runs the optimizer on f(p) = sum(p^2 / 2), whose gradient is p, starting at p = [1, -2].
+/// Returns p after each step.
+pub fn quadratic_trajectory(optimizer: &mut dyn Optimizer<NdArray>, steps: usize) -> Vec<Vec<f32>> {
+    let mut store = HashMap::new();
+    store.insert("p".to_string(), NdArray::from_slice(&[1., -2.]));
+    let mut trajectory = vec![];
+    for _step in 0..steps {
+        let rec: ComputationRecord<NdArray> = ComputationRecord::new();
+        let p = rec.tensor_from_value(store["p"].clone());
+        let loss = mul_scalar(&sum(&mul(&p, &p)), 0.5);
+        optimizer.step(&[("p".to_string(), &p)], &loss.grad(), &mut store);
+        trajectory.push(vec![store["p"].index(&[0]), store["p"].index(&[1])]);
+    }
+    trajectory
+}
+
+pub fn assert_trajectory_close(actual: &[Vec<f32>], expected: &[[f32; 2]]) {
+    assert_eq!(actual.len(), expected.len());
+    for (step, (actual, expected)) in actual.iter().zip(expected).enumerate() {
+        for (actual, expected) in actual.iter().zip(expected) {
+            assert!((actual - expected).abs() < 1e-5, "step {}: {:?} != {:?}", step + 1, actual, expected);
+        }
+    }
+}
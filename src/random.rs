@@ -0,0 +1,81 @@
+//! Seedable random number generation.
+//!
+//! The random constructors of the backends take a `Generator`, `T::rand` and the tape proxies use
+//! the global one. Seeding the global generator with `manual_seed` makes them reproducible, a
+//! `Generator` of its own keeps a stream independent of everything else.
+
+use std::sync::Mutex;
+use ndarray_rand::rand::rngs::StdRng;
+use ndarray_rand::rand::{Error, RngCore, SeedableRng};
+
+/// Random number generator with a reproducible stream for a given seed
+#[derive(Debug, Clone)]
+pub struct Generator {
+    rng: StdRng,
+}
+
+impl Generator {
+    pub fn new(seed: u64) -> Self {
+        Generator {
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Seeded from the operating system, not reproducible
+    pub fn from_entropy() -> Self {
+        Generator {
+            rng: StdRng::from_entropy(),
+        }
+    }
+}
+
+impl RngCore for Generator {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.rng.try_fill_bytes(dest)
+    }
+}
+
+/// Seeded from entropy on first use unless `manual_seed` was called before
+static GLOBAL: Mutex<Option<Generator>> = Mutex::new(None);
+
+/// Reseeds the global generator
+pub fn manual_seed(seed: u64) {
+    *GLOBAL.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(Generator::new(seed));
+}
+
+/// Runs `f` with the global generator. Calls from several threads are serialized, so the values
+/// each thread gets depend on the order in which they run.
+pub fn with_global_generator<R, F: FnOnce(&mut Generator) -> R>(f: F) -> R {
+    let mut global = GLOBAL.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    f(global.get_or_insert_with(Generator::from_entropy))
+}
+
+
+#[cfg(test)]
+mod random_tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_stream() {
+        let mut a = Generator::new(7);
+        let mut b = Generator::new(7);
+        let mut c = Generator::new(8);
+        let a_values: Vec<u64> = (0..4).map(|_i| a.next_u64()).collect();
+        let b_values: Vec<u64> = (0..4).map(|_i| b.next_u64()).collect();
+        let c_values: Vec<u64> = (0..4).map(|_i| c.next_u64()).collect();
+        assert_eq!(a_values, b_values);
+        assert_ne!(a_values, c_values);
+    }
+}
@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use crate::tensor_backends::TensorBackend;
+
+pub mod checkpoint;
+pub mod npy;
+pub mod onnx;
+pub mod safetensors;
+mod json;
+mod zip;
+
+#[derive(Debug)]
+pub enum SerializationError {
+    Io(std::io::Error),
+    /// The data does not follow the expected format
+    InvalidFormat(String),
+    ChecksumMismatch { stored: u32, computed: u32 },
+    /// Returned by strict loads when the loaded tensors do not match the expected ones
+    StateMismatch(LoadReport),
+    /// A graph node whose op can not be exported or imported
+    UnsupportedOp { node: String, op_type: String },
+    /// The graph is well formed but can not be converted, like a leaf which is neither an input
+    /// nor a parameter
+    InvalidGraph(String),
+}
+
+impl Display for SerializationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SerializationError::Io(error) => write!(f, "IO error: {}", error),
+            SerializationError::InvalidFormat(message) => write!(f, "Invalid format: {}", message),
+            SerializationError::ChecksumMismatch { stored, computed } => {
+                write!(f, "Checksum mismatch: stored {:08x}, computed {:08x}", stored, computed)
+            }
+            SerializationError::StateMismatch(report) => write!(f, "State mismatch: {}", report),
+            SerializationError::UnsupportedOp { node, op_type } => write!(f, "Unsupported op {} in node {}", op_type, node),
+            SerializationError::InvalidGraph(message) => write!(f, "Invalid graph: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for SerializationError {}
+
+impl From<std::io::Error> for SerializationError {
+    fn from(error: std::io::Error) -> Self {
+        SerializationError::Io(error)
+    }
+}
+
+/// Differences between a loaded state and the expected one
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LoadReport {
+    /// Expected but not in the loaded state
+    pub missing_keys: Vec<String>,
+    /// In the loaded state but not expected
+    pub unexpected_keys: Vec<String>,
+    /// (name, expected shape, loaded shape)
+    pub shape_mismatches: Vec<(String, Vec<usize>, Vec<usize>)>,
+}
+
+impl LoadReport {
+    pub fn is_clean(&self) -> bool {
+        self.missing_keys.is_empty() && self.unexpected_keys.is_empty() && self.shape_mismatches.is_empty()
+    }
+}
+
+impl Display for LoadReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "missing keys {:?}, unexpected keys {:?}", self.missing_keys, self.unexpected_keys)?;
+        for (name, expected, loaded) in &self.shape_mismatches {
+            write!(f, ", {} has shape {:?} instead of {:?}", name, loaded, expected)?;
+        }
+        Ok(())
+    }
+}
+
+/// Copies the loaded tensors into the store, whose current keys and shapes are the expected ones
+/// (e.g. `Module::state_dict`).
+///
+/// If strict, any difference is an error and the store is left untouched. Otherwise the tensors
+/// with a matching name and shape are copied and the differences are returned.
+pub fn load_state_dict<T: TensorBackend>(store: &mut HashMap<String, T>, loaded: HashMap<String, T>, strict: bool) -> Result<LoadReport, SerializationError> {
+    let mut report = LoadReport::default();
+    for name in store.keys() {
+        if !loaded.contains_key(name) {
+            report.missing_keys.push(name.clone());
+        }
+    }
+    let mut matching = vec![];
+    for (name, value) in loaded {
+        match store.get(&name) {
+            None => report.unexpected_keys.push(name),
+            Some(expected) if expected.shape() != value.shape() => {
+                report.shape_mismatches.push((name, expected.shape().to_vec(), value.shape().to_vec()));
+            }
+            Some(_) => matching.push((name, value)),
+        }
+    }
+    report.missing_keys.sort();
+    report.unexpected_keys.sort();
+    report.shape_mismatches.sort();
+    if strict && !report.is_clean() {
+        return Err(SerializationError::StateMismatch(report));
+    }
+    store.extend(matching);
+    Ok(report)
+}
+
+/// CRC-32 with the IEEE polynomial, as used by zip and png
+pub(crate) fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _bit in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
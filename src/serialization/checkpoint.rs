@@ -0,0 +1,284 @@
+//! Binary checkpoint format for named tensors, like a parameter store or optimizer state.
+//!
+//! All integers are little endian.
+//!
+//! ```text
+//! magic        4 bytes  "BPCK"
+//! version      u32      1
+//! count        u32      number of tensors
+//! count times, sorted by name:
+//!   name_len   u32
+//!   name       name_len bytes of UTF-8
+//!   dtype      u8       0 = f32
+//!   rank       u32
+//!   dims       rank times u64
+//! count times, in the same order:
+//!   data       product(dims) values of the dtype, row major (C) order
+//! checksum     u32      CRC-32 (IEEE) of every preceding byte
+//! ```
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use crate::tensor_backends::TensorBackend;
+use crate::serialization::{crc32, SerializationError};
+
+const MAGIC: &[u8; 4] = b"BPCK";
+const VERSION: u32 = 1;
+const DTYPE_F32: u8 = 0;
+
+/// Parameters and optimizer state saved together. Stored in a single checkpoint with the keys
+/// prefixed by "params/" and "optimizer/".
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checkpoint<T: TensorBackend> {
+    pub params: HashMap<String, T>,
+    pub optimizer: HashMap<String, T>,
+}
+
+impl <T: TensorBackend> Checkpoint<T> {
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SerializationError> {
+        let mut tensors = HashMap::new();
+        for (prefix, section) in &[("params/", &self.params), ("optimizer/", &self.optimizer)] {
+            for (name, value) in section.iter() {
+                tensors.insert(format!("{}{}", prefix, name), value.clone());
+            }
+        }
+        save(path, &tensors)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, SerializationError> {
+        let mut checkpoint = Checkpoint {
+            params: HashMap::new(),
+            optimizer: HashMap::new(),
+        };
+        for (name, value) in load(path)? {
+            if let Some(name) = name.strip_prefix("params/") {
+                checkpoint.params.insert(name.to_string(), value);
+            } else if let Some(name) = name.strip_prefix("optimizer/") {
+                checkpoint.optimizer.insert(name.to_string(), value);
+            } else {
+                return Err(SerializationError::InvalidFormat(format!("Unexpected checkpoint key {}", name)));
+            }
+        }
+        Ok(checkpoint)
+    }
+}
+
+pub fn save<T: TensorBackend, P: AsRef<Path>>(path: P, tensors: &HashMap<String, T>) -> Result<(), SerializationError> {
+    write(File::create(path)?, tensors)
+}
+
+pub fn load<T: TensorBackend, P: AsRef<Path>>(path: P) -> Result<HashMap<String, T>, SerializationError> {
+    read(File::open(path)?)
+}
+
+pub fn write<T: TensorBackend, W: Write>(mut writer: W, tensors: &HashMap<String, T>) -> Result<(), SerializationError> {
+    let mut names: Vec<&String> = tensors.keys().collect();
+    names.sort();
+
+    let mut bytes = vec![];
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    bytes.extend_from_slice(&(names.len() as u32).to_le_bytes());
+    for name in &names {
+        bytes.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(name.as_bytes());
+        bytes.push(DTYPE_F32);
+        let shape = tensors[*name].shape();
+        bytes.extend_from_slice(&(shape.len() as u32).to_le_bytes());
+        for dim in shape {
+            bytes.extend_from_slice(&(*dim as u64).to_le_bytes());
+        }
+    }
+    for name in &names {
+        for value in tensors[*name].to_vec() {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    let checksum = crc32(&bytes);
+    bytes.extend_from_slice(&checksum.to_le_bytes());
+    writer.write_all(&bytes)?;
+    Ok(())
+}
+
+pub fn read<T: TensorBackend, R: Read>(mut reader: R) -> Result<HashMap<String, T>, SerializationError> {
+    let mut bytes = vec![];
+    reader.read_to_end(&mut bytes)?;
+    if bytes.len() < 4 {
+        return Err(SerializationError::InvalidFormat("File too short".to_string()));
+    }
+    let (content, checksum) = bytes.split_at(bytes.len() - 4);
+    let stored = u32::from_le_bytes(checksum.try_into().unwrap());
+    let computed = crc32(content);
+    if stored != computed {
+        return Err(SerializationError::ChecksumMismatch { stored, computed });
+    }
+
+    let mut cursor = ByteCursor { bytes: content, position: 0 };
+    if cursor.take(4)? != MAGIC {
+        return Err(SerializationError::InvalidFormat("Not a checkpoint, wrong magic bytes".to_string()));
+    }
+    let version = cursor.u32()?;
+    if version != VERSION {
+        return Err(SerializationError::InvalidFormat(format!("Unsupported version {}", version)));
+    }
+    let count = cursor.u32()?;
+    let mut headers = vec![];
+    for _i in 0..count {
+        let name_len = cursor.u32()? as usize;
+        let name = String::from_utf8(cursor.take(name_len)?.to_vec())
+            .map_err(|_| SerializationError::InvalidFormat("Tensor name is not UTF-8".to_string()))?;
+        let dtype = cursor.take(1)?[0];
+        if dtype != DTYPE_F32 {
+            return Err(SerializationError::InvalidFormat(format!("Unsupported dtype {} of {}", dtype, name)));
+        }
+        let rank = cursor.u32()?;
+        let mut shape = vec![];
+        for _axis in 0..rank {
+            shape.push(cursor.u64()? as usize);
+        }
+        headers.push((name, shape));
+    }
+    let mut tensors = HashMap::new();
+    for (name, shape) in headers {
+        let byte_len = shape.iter().try_fold(4usize, |len, dim| len.checked_mul(*dim))
+            .ok_or_else(|| SerializationError::InvalidFormat(format!("Shape {:?} of {} is too large", shape, name)))?;
+        let values = cursor.take(byte_len)?
+            .chunks_exact(4)
+            .map(|value| f32::from_le_bytes(value.try_into().unwrap()))
+            .collect();
+        tensors.insert(name, T::from_shape_vec(&shape, values));
+    }
+    if cursor.position != content.len() {
+        return Err(SerializationError::InvalidFormat("Trailing bytes after the tensor data".to_string()));
+    }
+    Ok(tensors)
+}
+
+struct ByteCursor<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl <'a> ByteCursor<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SerializationError> {
+        let end = self.position.checked_add(len)
+            .ok_or_else(|| SerializationError::InvalidFormat("Unexpected end of data".to_string()))?;
+        match self.bytes.get(self.position..end) {
+            None => Err(SerializationError::InvalidFormat("Unexpected end of data".to_string())),
+            Some(taken) => {
+                self.position += len;
+                Ok(taken)
+            }
+        }
+    }
+
+    fn u32(&mut self) -> Result<u32, SerializationError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, SerializationError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod checkpoint_tests {
+    use super::*;
+    use crate::tensor_backends::NdArray;
+    use crate::serialization::{load_state_dict, LoadReport};
+
+    fn store() -> HashMap<String, NdArray> {
+        let mut store = HashMap::new();
+        store.insert("fc.weight".to_string(), NdArray::from_shape_vec(&[2, 3], vec![1., 2., 3., 4., 5., 6.]));
+        store.insert("fc.bias".to_string(), NdArray::from_slice(&[-1.5, 0.25, 7.]));
+        store
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut bytes = vec![];
+        write(&mut bytes, &store()).unwrap();
+        let loaded: HashMap<String, NdArray> = read(bytes.as_slice()).unwrap();
+        assert_eq!(loaded, store());
+
+        // Transposed data is written in logical order
+        let mut transposed = HashMap::new();
+        transposed.insert("t".to_string(), store()["fc.weight"].transpose(0, 1));
+        let mut bytes = vec![];
+        write(&mut bytes, &transposed).unwrap();
+        let loaded: HashMap<String, NdArray> = read(bytes.as_slice()).unwrap();
+        assert_eq!(loaded["t"].to_vec(), vec![1., 4., 2., 5., 3., 6.]);
+    }
+
+    #[test]
+    fn corruption_is_detected() {
+        let mut bytes = vec![];
+        write(&mut bytes, &store()).unwrap();
+        let last_value = bytes.len() - 5;
+        bytes[last_value] ^= 1;
+        match read::<NdArray, _>(bytes.as_slice()) {
+            Err(SerializationError::ChecksumMismatch { .. }) => {}
+            other => panic!("Expected a checksum mismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn oversized_shape_is_rejected() {
+        let mut bytes = MAGIC.to_vec();
+        for value in &[VERSION, 1, 1] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.extend_from_slice(&[b'x', DTYPE_F32]);
+        bytes.extend_from_slice(&3u32.to_le_bytes());
+        for _axis in 0..3 {
+            bytes.extend_from_slice(&(1u64 << 32).to_le_bytes());
+        }
+        let checksum = crc32(&bytes);
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+        match read::<NdArray, _>(bytes.as_slice()) {
+            Err(SerializationError::InvalidFormat(_)) => {}
+            other => panic!("Expected an invalid format error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn checkpoint_file() {
+        let path = std::env::temp_dir().join(format!("backprop_checkpoint_{}.bpck", std::process::id()));
+        let mut optimizer = HashMap::new();
+        optimizer.insert("fc.weight.step".to_string(), NdArray::from_slice(&[3.]));
+        let checkpoint = Checkpoint { params: store(), optimizer };
+        checkpoint.save(&path).unwrap();
+        let loaded = Checkpoint::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, checkpoint);
+    }
+
+    #[test]
+    fn strict_and_non_strict_load() {
+        let mut loaded = store();
+        loaded.remove("fc.bias");
+        loaded.insert("fc.weight".to_string(), NdArray::zeros(&[3, 2]));
+        loaded.insert("head.weight".to_string(), NdArray::zeros(&[1]));
+        let expected_report = LoadReport {
+            missing_keys: vec!["fc.bias".to_string()],
+            unexpected_keys: vec!["head.weight".to_string()],
+            shape_mismatches: vec![("fc.weight".to_string(), vec![2, 3], vec![3, 2])],
+        };
+
+        let mut model_state = store();
+        match load_state_dict(&mut model_state, loaded.clone(), true) {
+            Err(SerializationError::StateMismatch(report)) => assert_eq!(report, expected_report),
+            other => panic!("Expected a state mismatch, got {:?}", other),
+        }
+        assert_eq!(model_state, store());
+
+        loaded.insert("fc.weight".to_string(), NdArray::zeros(&[2, 3]));
+        let report = load_state_dict(&mut model_state, loaded, false).unwrap();
+        assert!(report.shape_mismatches.is_empty());
+        assert_eq!(model_state["fc.weight"], NdArray::zeros(&[2, 3]));
+        assert_eq!(model_state["fc.bias"], store()["fc.bias"]);
+    }
+}
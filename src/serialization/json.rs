@@ -0,0 +1,292 @@
+//! Minimal JSON values, enough for file headers like the safetensors one
+
+use std::fmt::Write;
+use crate::serialization::SerializationError;
+
+/// Arrays and objects nested deeper than this are rejected instead of overflowing the stack while
+/// parsing, file headers only need a few levels
+const MAX_DEPTH: usize = 32;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    /// Keys in the order they appear
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub(crate) fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(entries) => entries.iter().find(|(name, _value)| name == key).map(|(_name, value)| value),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Some if the value is a non negative integer
+    pub(crate) fn as_usize(&self) -> Option<usize> {
+        match self {
+            Json::Number(value) if *value >= 0. && value.fract() == 0. => Some(*value as usize),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn parse(text: &str) -> Result<Json, SerializationError> {
+        let mut parser = Parser { chars: text.chars().collect(), position: 0, depth: 0 };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.position != parser.chars.len() {
+            return Err(parser.error("Trailing characters"));
+        }
+        Ok(value)
+    }
+
+    pub(crate) fn to_json_string(&self) -> String {
+        let mut text = String::new();
+        self.write(&mut text);
+        text
+    }
+
+    fn write(&self, text: &mut String) {
+        match self {
+            Json::Null => text.push_str("null"),
+            Json::Bool(value) => text.push_str(if *value { "true" } else { "false" }),
+            Json::Number(value) => write!(text, "{}", value).unwrap(),
+            Json::String(value) => write_string(value, text),
+            Json::Array(values) => {
+                text.push('[');
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        text.push(',');
+                    }
+                    value.write(text);
+                }
+                text.push(']');
+            }
+            Json::Object(entries) => {
+                text.push('{');
+                for (index, (key, value)) in entries.iter().enumerate() {
+                    if index > 0 {
+                        text.push(',');
+                    }
+                    write_string(key, text);
+                    text.push(':');
+                    value.write(text);
+                }
+                text.push('}');
+            }
+        }
+    }
+}
+
+fn write_string(value: &str, text: &mut String) {
+    text.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => text.push_str("\\\""),
+            '\\' => text.push_str("\\\\"),
+            '\n' => text.push_str("\\n"),
+            '\r' => text.push_str("\\r"),
+            '\t' => text.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(text, "\\u{:04x}", c as u32).unwrap(),
+            c => text.push(c),
+        }
+    }
+    text.push('"');
+}
+
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+    /// Number of arrays and objects currently open
+    depth: usize,
+}
+
+impl Parser {
+    fn error(&self, message: &str) -> SerializationError {
+        SerializationError::InvalidFormat(format!("Invalid JSON at character {}: {}", self.position, message))
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.get(self.position).is_some_and(|c| c.is_whitespace()) {
+            self.position += 1;
+        }
+    }
+
+    fn next(&mut self) -> Result<char, SerializationError> {
+        let c = *self.chars.get(self.position).ok_or_else(|| self.error("Unexpected end"))?;
+        self.position += 1;
+        Ok(c)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), SerializationError> {
+        self.skip_whitespace();
+        match self.next()? {
+            c if c == expected => Ok(()),
+            c => Err(self.error(&format!("Expected '{}', found '{}'", expected, c))),
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str, value: Json) -> Result<Json, SerializationError> {
+        for expected in keyword.chars() {
+            if self.next()? != expected {
+                return Err(self.error("Unknown keyword"));
+            }
+        }
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<Json, SerializationError> {
+        self.skip_whitespace();
+        match self.chars.get(self.position) {
+            None => Err(self.error("Unexpected end")),
+            Some('{') => self.nested(Self::object),
+            Some('[') => self.nested(Self::array),
+            Some('"') => Ok(Json::String(self.string()?)),
+            Some('t') => self.keyword("true", Json::Bool(true)),
+            Some('f') => self.keyword("false", Json::Bool(false)),
+            Some('n') => self.keyword("null", Json::Null),
+            Some(_) => self.number(),
+        }
+    }
+
+    fn nested(&mut self, parse: fn(&mut Self) -> Result<Json, SerializationError>) -> Result<Json, SerializationError> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error("Nested too deeply"));
+        }
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn object(&mut self) -> Result<Json, SerializationError> {
+        self.expect('{')?;
+        let mut entries = vec![];
+        self.skip_whitespace();
+        if self.chars.get(self.position) == Some(&'}') {
+            self.position += 1;
+            return Ok(Json::Object(entries));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.expect(':')?;
+            entries.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.next()? {
+                ',' => continue,
+                '}' => return Ok(Json::Object(entries)),
+                _ => return Err(self.error("Expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, SerializationError> {
+        self.expect('[')?;
+        let mut values = vec![];
+        self.skip_whitespace();
+        if self.chars.get(self.position) == Some(&']') {
+            self.position += 1;
+            return Ok(Json::Array(values));
+        }
+        loop {
+            values.push(self.value()?);
+            self.skip_whitespace();
+            match self.next()? {
+                ',' => continue,
+                ']' => return Ok(Json::Array(values)),
+                _ => return Err(self.error("Expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, SerializationError> {
+        if self.next()? != '"' {
+            return Err(self.error("Expected a string"));
+        }
+        let mut value = String::new();
+        loop {
+            match self.next()? {
+                '"' => return Ok(value),
+                '\\' => match self.next()? {
+                    'n' => value.push('\n'),
+                    'r' => value.push('\r'),
+                    't' => value.push('\t'),
+                    'b' => value.push('\u{8}'),
+                    'f' => value.push('\u{c}'),
+                    'u' => {
+                        let mut code = self.hex4()?;
+                        // Characters outside the basic plane are written as surrogate pairs
+                        if (0xD800..0xDC00).contains(&code) {
+                            if self.next()? != '\\' || self.next()? != 'u' {
+                                return Err(self.error("Unpaired surrogate"));
+                            }
+                            let low = self.hex4()?;
+                            code = 0x10000 + ((code - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF);
+                        }
+                        value.push(std::char::from_u32(code).ok_or_else(|| self.error("Invalid unicode escape"))?);
+                    }
+                    c @ ('"' | '\\' | '/') => value.push(c),
+                    _ => return Err(self.error("Invalid escape")),
+                },
+                c => value.push(c),
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, SerializationError> {
+        let mut code = 0;
+        for _digit in 0..4 {
+            let digit = self.next()?.to_digit(16).ok_or_else(|| self.error("Invalid unicode escape"))?;
+            code = code * 16 + digit;
+        }
+        Ok(code)
+    }
+
+    fn number(&mut self) -> Result<Json, SerializationError> {
+        let start = self.position;
+        while self.chars.get(self.position).is_some_and(|c| c.is_ascii_digit() || "+-.eE".contains(*c)) {
+            self.position += 1;
+        }
+        let text: String = self.chars[start..self.position].iter().collect();
+        text.parse::<f64>().map(Json::Number).map_err(|_| self.error("Invalid number"))
+    }
+}
+
+
+#[cfg(test)]
+mod json_tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_write() {
+        let text = r#" {"a": [1, 2.5e1, -3], "b\né": {"c": true, "d": null}, "e": "\ud83d\ude00"} "#;
+        let json = Json::parse(text).unwrap();
+        assert_eq!(json.get("a").unwrap().as_array().unwrap()[1], Json::Number(25.));
+        assert_eq!(json.get("b\n\u{e9}").unwrap().get("c"), Some(&Json::Bool(true)));
+        assert_eq!(json.get("e").unwrap().as_str(), Some("\u{1F600}"));
+        assert_eq!(Json::parse(&json.to_json_string()).unwrap(), json);
+        assert!(Json::parse("{\"a\": 1,}").is_err());
+        assert!(Json::parse("[1] 2").is_err());
+        assert!(Json::parse(&format!("{}{}", "[".repeat(MAX_DEPTH), "]".repeat(MAX_DEPTH))).is_ok());
+        assert!(Json::parse(&"[".repeat(1_000_000)).is_err());
+    }
+}
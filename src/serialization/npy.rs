@@ -0,0 +1,366 @@
+//! NumPy .npy files (format versions 1, 2 and 3) and .npz archives of them.
+//!
+//! Arrays of little or big endian f32 and f64 in C or Fortran order can be read, f64 values are
+//! converted to f32. Writing uses f32 little endian C order unless other `NpyOptions` are given.
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use crate::tensor_backends::TensorBackend;
+use crate::serialization::SerializationError;
+use crate::serialization::zip;
+
+const MAGIC: &[u8; 6] = b"\x93NUMPY";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NpyDtype {
+    F32LittleEndian,
+    F32BigEndian,
+    F64LittleEndian,
+    F64BigEndian,
+}
+
+impl NpyDtype {
+    fn descr(self) -> &'static str {
+        match self {
+            NpyDtype::F32LittleEndian => "<f4",
+            NpyDtype::F32BigEndian => ">f4",
+            NpyDtype::F64LittleEndian => "<f8",
+            NpyDtype::F64BigEndian => ">f8",
+        }
+    }
+
+    fn from_descr(descr: &str) -> Result<Self, SerializationError> {
+        let native_little_endian = cfg!(target_endian = "little");
+        match descr {
+            "<f4" => Ok(NpyDtype::F32LittleEndian),
+            ">f4" => Ok(NpyDtype::F32BigEndian),
+            "<f8" => Ok(NpyDtype::F64LittleEndian),
+            ">f8" => Ok(NpyDtype::F64BigEndian),
+            "=f4" if native_little_endian => Ok(NpyDtype::F32LittleEndian),
+            "=f4" => Ok(NpyDtype::F32BigEndian),
+            "=f8" if native_little_endian => Ok(NpyDtype::F64LittleEndian),
+            "=f8" => Ok(NpyDtype::F64BigEndian),
+            _ => Err(SerializationError::InvalidFormat(format!("Unsupported dtype {}, only f32 and f64 are supported", descr))),
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            NpyDtype::F32LittleEndian | NpyDtype::F32BigEndian => 4,
+            NpyDtype::F64LittleEndian | NpyDtype::F64BigEndian => 8,
+        }
+    }
+
+    fn decode(self, bytes: &[u8]) -> f32 {
+        match self {
+            NpyDtype::F32LittleEndian => f32::from_le_bytes(bytes.try_into().unwrap()),
+            NpyDtype::F32BigEndian => f32::from_be_bytes(bytes.try_into().unwrap()),
+            NpyDtype::F64LittleEndian => f64::from_le_bytes(bytes.try_into().unwrap()) as f32,
+            NpyDtype::F64BigEndian => f64::from_be_bytes(bytes.try_into().unwrap()) as f32,
+        }
+    }
+
+    fn encode(self, value: f32, bytes: &mut Vec<u8>) {
+        match self {
+            NpyDtype::F32LittleEndian => bytes.extend_from_slice(&value.to_le_bytes()),
+            NpyDtype::F32BigEndian => bytes.extend_from_slice(&value.to_be_bytes()),
+            NpyDtype::F64LittleEndian => bytes.extend_from_slice(&(value as f64).to_le_bytes()),
+            NpyDtype::F64BigEndian => bytes.extend_from_slice(&(value as f64).to_be_bytes()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NpyOptions {
+    pub dtype: NpyDtype,
+    /// Store the values in column major order
+    pub fortran_order: bool,
+}
+
+impl Default for NpyOptions {
+    fn default() -> Self {
+        NpyOptions {
+            dtype: NpyDtype::F32LittleEndian,
+            fortran_order: false,
+        }
+    }
+}
+
+pub fn load_npy<T: TensorBackend, P: AsRef<Path>>(path: P) -> Result<T, SerializationError> {
+    read_npy(File::open(path)?)
+}
+
+pub fn save_npy<T: TensorBackend, P: AsRef<Path>>(path: P, tensor: &T) -> Result<(), SerializationError> {
+    write_npy(File::create(path)?, tensor, NpyOptions::default())
+}
+
+pub fn read_npy<T: TensorBackend, R: Read>(mut reader: R) -> Result<T, SerializationError> {
+    let mut bytes = vec![];
+    reader.read_to_end(&mut bytes)?;
+    parse_npy(&bytes)
+}
+
+pub fn write_npy<T: TensorBackend, W: Write>(mut writer: W, tensor: &T, options: NpyOptions) -> Result<(), SerializationError> {
+    writer.write_all(&encode_npy(tensor, options))?;
+    Ok(())
+}
+
+/// Reads every array of the archive, keyed by file name without the ".npy" extension like
+/// `numpy.load` does. Both `numpy.savez` and `numpy.savez_compressed` archives are supported.
+pub fn load_npz<T: TensorBackend, P: AsRef<Path>>(path: P) -> Result<HashMap<String, T>, SerializationError> {
+    read_npz(File::open(path)?)
+}
+
+/// Writes every tensor as "{name}.npy", so a parameter store round trips through `numpy.savez`.
+/// zip64 is not supported, archives of 4GiB or more, or with 65535 tensors or more, fail with
+/// `InvalidFormat`.
+pub fn save_npz<T: TensorBackend, P: AsRef<Path>>(path: P, tensors: &HashMap<String, T>) -> Result<(), SerializationError> {
+    write_npz(File::create(path)?, tensors)
+}
+
+pub fn read_npz<T: TensorBackend, R: Read>(mut reader: R) -> Result<HashMap<String, T>, SerializationError> {
+    let mut bytes = vec![];
+    reader.read_to_end(&mut bytes)?;
+    let mut tensors = HashMap::new();
+    for (file_name, content) in zip::read_archive(&bytes)? {
+        let name = file_name.strip_suffix(".npy").unwrap_or(&file_name).to_string();
+        tensors.insert(name, parse_npy(&content)?);
+    }
+    Ok(tensors)
+}
+
+pub fn write_npz<T: TensorBackend, W: Write>(mut writer: W, tensors: &HashMap<String, T>) -> Result<(), SerializationError> {
+    let mut names: Vec<&String> = tensors.keys().collect();
+    names.sort();
+    let files: Vec<(String, Vec<u8>)> = names.into_iter()
+        .map(|name| (format!("{}.npy", name), encode_npy(&tensors[name], NpyOptions::default())))
+        .collect();
+    writer.write_all(&zip::write_archive(&files)?)?;
+    Ok(())
+}
+
+fn parse_npy<T: TensorBackend>(bytes: &[u8]) -> Result<T, SerializationError> {
+    let invalid = |message: &str| SerializationError::InvalidFormat(message.to_string());
+    if bytes.len() < 10 || &bytes[..6] != MAGIC {
+        return Err(invalid("Not an npy file, wrong magic bytes"));
+    }
+    let (header_len, header_start): (usize, usize) = match bytes[6] {
+        1 => (u16::from_le_bytes([bytes[8], bytes[9]]) as usize, 10),
+        2 | 3 if bytes.len() >= 12 => (u32::from_le_bytes(bytes[8..12].try_into().unwrap()) as usize, 12),
+        version => return Err(SerializationError::InvalidFormat(format!("Unsupported npy version {}", version))),
+    };
+    let header_end = header_start.checked_add(header_len).ok_or_else(|| invalid("Invalid npy header"))?;
+    let header = bytes.get(header_start..header_end)
+        .and_then(|header| std::str::from_utf8(header).ok())
+        .ok_or_else(|| invalid("Invalid npy header"))?;
+
+    let dtype = NpyDtype::from_descr(header_string_value(header, "descr")?)?;
+    let fortran_order = match header_raw_value(header, "fortran_order")? {
+        value if value.starts_with("True") => true,
+        value if value.starts_with("False") => false,
+        _ => return Err(invalid("Invalid fortran_order in npy header")),
+    };
+    let shape = header_shape(header)?;
+
+    let data = &bytes[header_end..];
+    let byte_len = shape.iter().try_fold(dtype.size(), |len, dim| len.checked_mul(*dim));
+    if byte_len != Some(data.len()) {
+        return Err(SerializationError::InvalidFormat(format!("Expected values of shape {:?}, got {} bytes", shape, data.len())));
+    }
+    let values: Vec<f32> = data.chunks_exact(dtype.size()).map(|value| dtype.decode(value)).collect();
+    if shape.is_empty() {
+        // 0-d arrays are represented as shape [1], like every other scalar in the crate
+        return Ok(T::from_slice(&values));
+    }
+    if fortran_order {
+        let reversed_shape: Vec<usize> = shape.iter().rev().cloned().collect();
+        let reversed_axes: Vec<usize> = (0..shape.len()).rev().collect();
+        Ok(T::from_shape_vec(&reversed_shape, values).permute(&reversed_axes))
+    } else {
+        Ok(T::from_shape_vec(&shape, values))
+    }
+}
+
+fn encode_npy<T: TensorBackend>(tensor: &T, options: NpyOptions) -> Vec<u8> {
+    let shape = tensor.shape();
+    let shape_str = match shape.len() {
+        1 => format!("({},)", shape[0]),
+        _ => format!("({})", shape.iter().map(|dim| dim.to_string()).collect::<Vec<_>>().join(", ")),
+    };
+    let fortran_order = if options.fortran_order { "True" } else { "False" };
+    let mut header = format!("{{'descr': '{}', 'fortran_order': {}, 'shape': {}, }}", options.dtype.descr(), fortran_order, shape_str);
+
+    // The header is padded with spaces and ends with a newline so the data is 64 byte aligned
+    let version = if header.len() + 11 <= u16::MAX as usize { 1 } else { 2 };
+    let prefix_len = if version == 1 { 10 } else { 12 };
+    let padding = (64 - (prefix_len + header.len() + 1) % 64) % 64;
+    header.push_str(&" ".repeat(padding));
+    header.push('\n');
+
+    let mut bytes = vec![];
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&[version, 0]);
+    if version == 1 {
+        bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    } else {
+        bytes.extend_from_slice(&(header.len() as u32).to_le_bytes());
+    }
+    bytes.extend_from_slice(header.as_bytes());
+
+    let values = if options.fortran_order {
+        let reversed_axes: Vec<usize> = (0..shape.len()).rev().collect();
+        tensor.permute(&reversed_axes).to_vec()
+    } else {
+        tensor.to_vec()
+    };
+    for value in values {
+        options.dtype.encode(value, &mut bytes);
+    }
+    bytes
+}
+
+/// Text after "'key':" in the header dict
+fn header_raw_value<'h>(header: &'h str, key: &str) -> Result<&'h str, SerializationError> {
+    let pattern = format!("'{}':", key);
+    match header.find(&pattern) {
+        None => Err(SerializationError::InvalidFormat(format!("Missing {} in npy header", key))),
+        Some(start) => Ok(header[start + pattern.len()..].trim_start()),
+    }
+}
+
+fn header_string_value<'h>(header: &'h str, key: &str) -> Result<&'h str, SerializationError> {
+    let value = header_raw_value(header, key)?;
+    let quote = value.chars().next().filter(|quote| *quote == '\'' || *quote == '"');
+    let value = quote.and_then(|quote| value[1..].split(quote).next());
+    value.ok_or_else(|| SerializationError::InvalidFormat(format!("Invalid {} in npy header", key)))
+}
+
+fn header_shape(header: &str) -> Result<Vec<usize>, SerializationError> {
+    let invalid = || SerializationError::InvalidFormat("Invalid shape in npy header".to_string());
+    let value = header_raw_value(header, "shape")?;
+    let inner = value.strip_prefix('(').and_then(|value| value.split(')').next()).ok_or_else(invalid)?;
+    inner.split(',')
+        .map(|dim| dim.trim())
+        .filter(|dim| !dim.is_empty())
+        .map(|dim| dim.trim_end_matches('L').parse::<usize>().map_err(|_| invalid()))
+        .collect()
+}
+
+
+#[cfg(test)]
+mod npy_tests {
+    use super::*;
+    use crate::tensor_backends::NdArray;
+
+    fn matrix() -> NdArray {
+        NdArray::from_shape_vec(&[2, 3], vec![1., 2., 3., 4., 5., 6.5])
+    }
+
+    /// An npy file as numpy writes it, with a version 1 header
+    fn handwritten_npy(descr: &str, fortran_order: bool, shape: &str, data: &[u8]) -> Vec<u8> {
+        let fortran_order = if fortran_order { "True" } else { "False" };
+        let mut header = format!("{{'descr': '{}', 'fortran_order': {}, 'shape': {}, }}", descr, fortran_order, shape);
+        while (10 + header.len() + 1) % 64 != 0 {
+            header.push(' ');
+        }
+        header.push('\n');
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&[1, 0]);
+        bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(header.as_bytes());
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    #[test]
+    fn reads_numpy_layouts() {
+        let little_f32: Vec<u8> = [1f32, 2., 3., 4., 5., 6.5].iter().flat_map(|v| v.to_le_bytes().to_vec()).collect();
+        let read: NdArray = parse_npy(&handwritten_npy("<f4", false, "(2, 3)", &little_f32)).unwrap();
+        assert_eq!(read, matrix());
+
+        // Column major big endian f64
+        let big_f64_fortran: Vec<u8> = [1f64, 4., 2., 5., 3., 6.5].iter().flat_map(|v| v.to_be_bytes().to_vec()).collect();
+        let read: NdArray = parse_npy(&handwritten_npy(">f8", true, "(2, 3)", &big_f64_fortran)).unwrap();
+        assert_eq!(read, matrix());
+
+        let vector: NdArray = parse_npy(&handwritten_npy("<f8", false, "(2,)", &[0, 0, 0, 0, 0, 0, 0xf0, 0x3f, 0, 0, 0, 0, 0, 0, 0, 0x40])).unwrap();
+        assert_eq!(vector, NdArray::from_slice(&[1., 2.]));
+
+        match parse_npy::<NdArray>(&handwritten_npy("<i8", false, "(1,)", &[0; 8])) {
+            Err(SerializationError::InvalidFormat(message)) => assert!(message.contains("<i8")),
+            other => panic!("Expected an unsupported dtype error, got {:?}", other),
+        }
+        match parse_npy::<NdArray>(&handwritten_npy("<f4", false, "(4294967296, 4294967296, 4294967296)", &[0; 4])) {
+            Err(SerializationError::InvalidFormat(message)) => assert!(message.contains("shape")),
+            other => panic!("Expected a shape error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn round_trip_all_layouts() {
+        for dtype in &[NpyDtype::F32LittleEndian, NpyDtype::F32BigEndian, NpyDtype::F64LittleEndian, NpyDtype::F64BigEndian] {
+            for fortran_order in &[false, true] {
+                let options = NpyOptions { dtype: *dtype, fortran_order: *fortran_order };
+                let bytes = encode_npy(&matrix(), options);
+                assert_eq!((bytes.len() - dtype.size() * 6) % 64, 0);
+                let read: NdArray = parse_npy(&bytes).unwrap();
+                assert_eq!(read, matrix(), "{:?}", options);
+            }
+        }
+        let bytes = encode_npy(&NdArray::from_slice(&[1.]), NpyOptions::default());
+        assert_eq!(bytes.len(), 128 + 4);
+        assert!(String::from_utf8_lossy(&bytes[10..]).starts_with("{'descr': '<f4', 'fortran_order': False, 'shape': (1,), }"));
+    }
+
+    #[test]
+    fn npz_round_trip() {
+        let mut store = HashMap::new();
+        store.insert("fc.weight".to_string(), matrix());
+        store.insert("fc.bias".to_string(), NdArray::from_slice(&[0.5, -0.5, 1.]));
+        let mut bytes = vec![];
+        write_npz(&mut bytes, &store).unwrap();
+        let read: HashMap<String, NdArray> = read_npz(bytes.as_slice()).unwrap();
+        assert_eq!(read, store);
+    }
+
+    #[test]
+    fn reads_compressed_npz() {
+        // Written by Python's zipfile the way numpy.savez_compressed does it: deflated entries
+        // with zip64 extra fields. Holds "weights", a (2, 3) <f4 array, and "bias", a (3,) <f8 one.
+        let archive = [
+        0x50, 0x4b, 0x03, 0x04, 0x2d, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x21, 0x00, 0x2d, 0x8e,
+        0x1c, 0xe8, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x0b, 0x00, 0x14, 0x00, 0x77, 0x65,
+        0x69, 0x67, 0x68, 0x74, 0x73, 0x2e, 0x6e, 0x70, 0x79, 0x01, 0x00, 0x10, 0x00, 0x98, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x58, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x9b, 0xec, 0x17,
+        0xea, 0x1b, 0x10, 0xc9, 0xc8, 0x50, 0xc6, 0x50, 0xad, 0x9e, 0x92, 0x5a, 0x9c, 0x5c, 0xa4, 0x6e,
+        0xa5, 0xa0, 0x6e, 0x93, 0x66, 0xa2, 0xae, 0xa3, 0xa0, 0x9e, 0x96, 0x5f, 0x54, 0x52, 0x94, 0x98,
+        0x17, 0x9f, 0x5f, 0x94, 0x92, 0x0a, 0x12, 0x77, 0x4b, 0xcc, 0x29, 0x4e, 0x05, 0x8a, 0x17, 0x67,
+        0x24, 0x16, 0xa4, 0x02, 0xf9, 0x1a, 0x46, 0x3a, 0x0a, 0xc6, 0x9a, 0x3a, 0x0a, 0xb5, 0x0a, 0x64,
+        0x03, 0x2e, 0x06, 0x86, 0x06, 0x7b, 0x06, 0x06, 0x06, 0x07, 0x20, 0x02, 0xe2, 0x06, 0x20, 0x5e,
+        0x00, 0xc4, 0x17, 0x1c, 0x00, 0x50, 0x4b, 0x03, 0x04, 0x2d, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00,
+        0x00, 0x21, 0x00, 0x10, 0x9b, 0xcc, 0x08, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x08,
+        0x00, 0x14, 0x00, 0x62, 0x69, 0x61, 0x73, 0x2e, 0x6e, 0x70, 0x79, 0x01, 0x00, 0x10, 0x00, 0x98,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x4f, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x9b,
+        0xec, 0x17, 0xea, 0x1b, 0x10, 0xc9, 0xc8, 0x50, 0xc6, 0x50, 0xad, 0x9e, 0x92, 0x5a, 0x9c, 0x5c,
+        0xa4, 0x6e, 0xa5, 0xa0, 0x6e, 0x93, 0x66, 0xa1, 0xae, 0xa3, 0xa0, 0x9e, 0x96, 0x5f, 0x54, 0x52,
+        0x94, 0x98, 0x17, 0x9f, 0x5f, 0x94, 0x92, 0x0a, 0x12, 0x77, 0x4b, 0xcc, 0x29, 0x4e, 0x05, 0x8a,
+        0x17, 0x67, 0x24, 0x16, 0xa4, 0x02, 0xf9, 0x1a, 0xc6, 0x3a, 0x9a, 0x3a, 0x0a, 0xb5, 0x0a, 0x14,
+        0x00, 0x2e, 0x06, 0x30, 0x78, 0x60, 0x0f, 0xa5, 0xf7, 0x43, 0xe8, 0x0f, 0xf6, 0x00, 0x50, 0x4b,
+        0x01, 0x02, 0x2d, 0x03, 0x2d, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x21, 0x00, 0x2d, 0x8e,
+        0x1c, 0xe8, 0x58, 0x00, 0x00, 0x00, 0x98, 0x00, 0x00, 0x00, 0x0b, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80, 0x01, 0x00, 0x00, 0x00, 0x00, 0x77, 0x65, 0x69, 0x67,
+        0x68, 0x74, 0x73, 0x2e, 0x6e, 0x70, 0x79, 0x50, 0x4b, 0x01, 0x02, 0x2d, 0x03, 0x2d, 0x00, 0x00,
+        0x00, 0x08, 0x00, 0x00, 0x00, 0x21, 0x00, 0x10, 0x9b, 0xcc, 0x08, 0x4f, 0x00, 0x00, 0x00, 0x98,
+        0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80,
+        0x01, 0x95, 0x00, 0x00, 0x00, 0x62, 0x69, 0x61, 0x73, 0x2e, 0x6e, 0x70, 0x79, 0x50, 0x4b, 0x05,
+        0x06, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x02, 0x00, 0x6f, 0x00, 0x00, 0x00, 0x1e, 0x01, 0x00,
+        0x00, 0x00, 0x00,
+        ];
+        let read: HashMap<String, NdArray> = read_npz(&archive[..]).unwrap();
+        assert_eq!(read["weights"], matrix());
+        assert_eq!(read["bias"], NdArray::from_slice(&[0.5, -0.5, 1.]));
+    }
+}
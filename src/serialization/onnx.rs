@@ -0,0 +1,289 @@
+//! ONNX export of the graph recorded in a ComputationRecord, and import into an `OnnxModel`
+//! which can be run or tracked on a ComputationRecord for fine-tuning (see `import`).
+//!
+//! The Ops the output depends on become nodes, in the order they were recorded. Leaves must be
+//! either graph inputs or parameters, which are stored as initializers. The model uses IR
+//! version 8 and opset 13, values are float tensors with the shapes seen while recording.
+
+use std::collections::HashMap;
+use std::path::Path;
+use crate::{OpAttribute, OpData, TrackedTensor};
+use crate::tensor_backends::TensorBackend;
+use crate::serialization::SerializationError;
+
+mod protobuf;
+pub mod proto;
+mod import;
+
+pub use import::{OnnxModel, OnnxModule};
+pub use proto::{AttributeProto, AttributeValue, GraphProto, ModelProto, NodeProto, TensorData, TensorProto, ValueInfoProto};
+
+pub const IR_VERSION: i64 = 8;
+pub const OPSET_VERSION: i64 = 13;
+
+/// Name of the graph output when it is not a leaf
+const OUTPUT_NAME: &str = "output";
+
+/// Exports the computation of `output` from `inputs`, with `params` (e.g.
+/// `Module::named_parameters`) as initializers.
+pub fn export<T: TensorBackend>(inputs: &[(&str, &TrackedTensor<'_, T>)], params: &[(String, &TrackedTensor<'_, T>)], output: &TrackedTensor<'_, T>) -> Result<ModelProto, SerializationError> {
+    let record = output.tape;
+    let named_leaves = inputs.iter().map(|(name, tensor)| (name.to_string(), *tensor))
+        .chain(params.iter().map(|(name, tensor)| (name.clone(), *tensor)));
+    for (name, tensor) in named_leaves {
+        if !std::ptr::eq(tensor.tape, record) {
+            return Err(SerializationError::InvalidGraph(format!("{} is not on the ComputationRecord of the output", name)));
+        }
+    }
+
+    let ops_data = record.ops_data();
+    let output_index = output.parent_op_index;
+    let mut reached = vec![false; output_index + 1];
+    reached[output_index] = true;
+    for index in (0..=output_index).rev() {
+        if reached[index] {
+            for blueprint in &ops_data[index].operands_grad_blueprint {
+                reached[blueprint.operand_tape_index()] = true;
+            }
+        }
+    }
+
+    let mut graph = GraphProto {
+        name: "backprop".to_string(),
+        ..GraphProto::default()
+    };
+    let mut names: HashMap<usize, String> = HashMap::new();
+    for index in (0..=output_index).filter(|index| reached[*index]) {
+        let op_data = &ops_data[index];
+        if op_data.operands_grad_blueprint.is_empty() {
+            let name = export_leaf(&mut graph, index, inputs, params)?;
+            names.insert(index, name);
+            continue;
+        }
+        let operands = op_data.operands_grad_blueprint.iter()
+            .map(|blueprint| names[&blueprint.operand_tape_index()].clone())
+            .collect();
+        let name = if index == output_index { OUTPUT_NAME.to_string() } else { format!("t{}", index) };
+        export_op(&mut graph, index, op_data, operands, &name)?;
+        names.insert(index, name);
+    }
+    graph.outputs.push(ValueInfoProto {
+        name: names[&output_index].clone(),
+        shape: dims(output.shape()),
+    });
+
+    Ok(ModelProto {
+        ir_version: IR_VERSION,
+        producer_name: "backprop".to_string(),
+        opset_version: OPSET_VERSION,
+        graph,
+    })
+}
+
+/// Exports and writes the model to `path`, see `export`
+pub fn save<T: TensorBackend, P: AsRef<Path>>(path: P, inputs: &[(&str, &TrackedTensor<'_, T>)], params: &[(String, &TrackedTensor<'_, T>)], output: &TrackedTensor<'_, T>) -> Result<(), SerializationError> {
+    let model = export(inputs, params, output)?;
+    std::fs::write(path, model.encode())?;
+    Ok(())
+}
+
+fn dims(shape: &[usize]) -> Vec<i64> {
+    shape.iter().map(|len| *len as i64).collect()
+}
+
+fn export_leaf<T: TensorBackend>(graph: &mut GraphProto, index: usize, inputs: &[(&str, &TrackedTensor<'_, T>)], params: &[(String, &TrackedTensor<'_, T>)]) -> Result<String, SerializationError> {
+    if let Some((name, tensor)) = inputs.iter().find(|(_name, tensor)| tensor.parent_op_index == index) {
+        graph.inputs.push(ValueInfoProto {
+            name: name.to_string(),
+            shape: dims(tensor.shape()),
+        });
+        return Ok(name.to_string());
+    }
+    if let Some((name, tensor)) = params.iter().find(|(_name, tensor)| tensor.parent_op_index == index) {
+        graph.initializers.push(TensorProto {
+            name: name.clone(),
+            dims: dims(tensor.shape()),
+            data: TensorData::Float(tensor.data().to_vec()),
+        });
+        return Ok(name.clone());
+    }
+    Err(SerializationError::InvalidGraph(format!("The leaf at tape index {} is neither an input nor a parameter", index)))
+}
+
+fn attribute<'a, T: TensorBackend>(op_data: &'a OpData<T>, node: &str, name: &str) -> Result<&'a OpAttribute, SerializationError> {
+    op_data.attribute(name)
+        .ok_or_else(|| SerializationError::InvalidGraph(format!("Node {} has no {} attribute", node, name)))
+}
+
+fn float_attribute<T: TensorBackend>(op_data: &OpData<T>, node: &str, name: &str) -> Result<f32, SerializationError> {
+    match attribute(op_data, node, name)? {
+        OpAttribute::Float(value) => Ok(*value),
+        _ => Err(SerializationError::InvalidGraph(format!("Attribute {} of node {} is not a float", name, node))),
+    }
+}
+
+fn int_attribute<T: TensorBackend>(op_data: &OpData<T>, node: &str, name: &str) -> Result<i64, SerializationError> {
+    match attribute(op_data, node, name)? {
+        OpAttribute::Int(value) => Ok(*value),
+        _ => Err(SerializationError::InvalidGraph(format!("Attribute {} of node {} is not an int", name, node))),
+    }
+}
+
+fn ints_attribute<T: TensorBackend>(op_data: &OpData<T>, node: &str, name: &str) -> Result<Vec<i64>, SerializationError> {
+    match attribute(op_data, node, name)? {
+        OpAttribute::Ints(values) => Ok(values.clone()),
+        _ => Err(SerializationError::InvalidGraph(format!("Attribute {} of node {} is not a list of ints", name, node))),
+    }
+}
+
+fn node(name: &str, op_type: &str, inputs: Vec<String>, output: &str) -> NodeProto {
+    NodeProto {
+        name: name.to_string(),
+        op_type: op_type.to_string(),
+        inputs,
+        outputs: vec![output.to_string()],
+        attributes: vec![],
+    }
+}
+
+fn with_attribute(mut node: NodeProto, name: &str, value: AttributeValue) -> NodeProto {
+    node.attributes.push(AttributeProto { name: name.to_string(), value });
+    node
+}
+
+/// Adds an initializer holding a constant operand of a node and returns its name
+fn constant(graph: &mut GraphProto, node: &str, suffix: &str, dims: Vec<i64>, data: TensorData) -> String {
+    let name = format!("{}_{}", node, suffix);
+    graph.initializers.push(TensorProto { name: name.clone(), dims, data });
+    name
+}
+
+fn export_op<T: TensorBackend>(graph: &mut GraphProto, index: usize, op_data: &OpData<T>, operands: Vec<String>, output: &str) -> Result<(), SerializationError> {
+    let op_name = op_data.op_name.as_str();
+    let name = format!("{}_{}", op_name, index);
+    let exported = match op_name {
+        "Add" | "Sub" | "Mul" | "Div" | "Relu" | "Exp" | "Log" | "Sqrt" | "Abs" | "Neg" | "Sin" | "Cos"
+        | "Tanh" | "Sigmoid" | "Softplus" | "Reciprocal" => node(&name, op_name, operands, output),
+        "Matmul" => node(&name, "MatMul", operands, output),
+        "LeakyRelu" => {
+            let alpha = float_attribute(op_data, &name, "alpha")?;
+            with_attribute(node(&name, "LeakyRelu", operands, output), "alpha", AttributeValue::Float(alpha))
+        }
+        "Softmax" | "LogSoftmax" => {
+            let axis = int_attribute(op_data, &name, "axis")?;
+            with_attribute(node(&name, op_name, operands, output), "axis", AttributeValue::Int(axis))
+        }
+        "AddScalar" | "MulScalar" | "DivScalar" | "RSub" => {
+            let scalar = float_attribute(op_data, &name, "scalar")?;
+            let scalar = constant(graph, &name, "scalar", vec![], TensorData::Float(vec![scalar]));
+            let input = operands[0].clone();
+            match op_name {
+                "AddScalar" => node(&name, "Add", vec![input, scalar], output),
+                "MulScalar" => node(&name, "Mul", vec![input, scalar], output),
+                "DivScalar" => node(&name, "Div", vec![input, scalar], output),
+                _ => node(&name, "Sub", vec![scalar, input], output),
+            }
+        }
+        "Sum" | "Mean" => {
+            // Reducing all axes gives a scalar, which is reshaped to [1] like in the record
+            let reduced = format!("{}_reduced", name);
+            let reduce_type = if op_name == "Sum" { "ReduceSum" } else { "ReduceMean" };
+            graph.nodes.push(with_attribute(node(&format!("{}_reduce", name), reduce_type, operands, &reduced), "keepdims", AttributeValue::Int(0)));
+            let shape = constant(graph, &name, "shape", vec![1], TensorData::Int64(vec![1]));
+            node(&name, "Reshape", vec![reduced, shape], output)
+        }
+        "SumAxis" => {
+            let axis = int_attribute(op_data, &name, "axis")?;
+            let keepdims = int_attribute(op_data, &name, "keepdim")?;
+            let axes = constant(graph, &name, "axes", vec![1], TensorData::Int64(vec![axis]));
+            let inputs = vec![operands[0].clone(), axes];
+            with_attribute(node(&name, "ReduceSum", inputs, output), "keepdims", AttributeValue::Int(keepdims))
+        }
+        "MeanAxis" => {
+            // Before opset 18 ReduceMean takes the axes as attribute, unlike ReduceSum
+            let axis = int_attribute(op_data, &name, "axis")?;
+            let keepdims = int_attribute(op_data, &name, "keepdim")?;
+            let reduce = with_attribute(node(&name, "ReduceMean", operands, output), "axes", AttributeValue::Ints(vec![axis]));
+            with_attribute(reduce, "keepdims", AttributeValue::Int(keepdims))
+        }
+        "Reshape" | "BroadcastTo" => {
+            let target_shape = ints_attribute(op_data, &name, "shape")?;
+            let shape = constant(graph, &name, "shape", vec![target_shape.len() as i64], TensorData::Int64(target_shape));
+            let op_type = if op_name == "Reshape" { "Reshape" } else { "Expand" };
+            node(&name, op_type, vec![operands[0].clone(), shape], output)
+        }
+        "Permute" => {
+            let axes = ints_attribute(op_data, &name, "axes")?;
+            with_attribute(node(&name, "Transpose", operands, output), "perm", AttributeValue::Ints(axes))
+        }
+        _ => return Err(SerializationError::UnsupportedOp { node: name, op_type: op_name.to_string() }),
+    };
+    graph.nodes.push(exported);
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod onnx_tests {
+    use super::*;
+    use crate::ComputationRecord;
+    use crate::layers::{LinearConfig, LinearLayer};
+    use crate::module::Module;
+    use crate::ops::*;
+    use crate::tensor_backends::NdArray;
+
+    #[test]
+    fn export_round_trip() {
+        let rec: ComputationRecord<NdArray> = ComputationRecord::new();
+        let store = HashMap::new();
+        let hidden = LinearLayer::from_config(&rec, LinearConfig { name: Some("hidden".to_string()), ..LinearConfig::new(3, 4) }, &store);
+        let head = LinearLayer::from_config(&rec, LinearConfig { bias: false, name: Some("head".to_string()), ..LinearConfig::new(4, 2) }, &store);
+        let mut data = NdArray::from_slice(&[1., 2., 3., -1., 0., 1.]);
+        data.reshape(&[2, 3]);
+        let input = rec.tensor_from_value(data);
+        // Unrelated Ops recorded before the model are not exported
+        let _unused = relu(&input);
+        let logits = head.forward(&leaky_relu(&relu(&hidden.forward(&input)), 0.1));
+        let output = sum(&mul_scalar(&log_softmax(&logits, 1), -1.));
+
+        let mut params = hidden.named_parameters();
+        params.extend(head.named_parameters());
+        let bytes = export(&[("input", &input)], &params, &output).unwrap().encode();
+        let model = ModelProto::decode(&bytes).unwrap();
+
+        assert_eq!(model.ir_version, IR_VERSION);
+        assert_eq!(model.opset_version, OPSET_VERSION);
+        let graph = &model.graph;
+        let op_types: Vec<&str> = graph.nodes.iter().map(|node| node.op_type.as_str()).collect();
+        assert_eq!(op_types, vec!["MatMul", "Expand", "Add", "Relu", "LeakyRelu", "MatMul", "LogSoftmax", "Mul", "ReduceSum", "Reshape"]);
+        assert_eq!(graph.nodes[4].attribute("alpha"), Some(&AttributeValue::Float(0.1)));
+        assert_eq!(graph.nodes[6].attribute("axis"), Some(&AttributeValue::Int(1)));
+        assert_eq!(graph.nodes[0].inputs, vec!["input", "hidden.weight"]);
+        assert_eq!(graph.nodes[9].outputs, vec![OUTPUT_NAME]);
+
+        assert_eq!(graph.inputs, vec![ValueInfoProto { name: "input".to_string(), shape: vec![2, 3] }]);
+        assert_eq!(graph.outputs, vec![ValueInfoProto { name: OUTPUT_NAME.to_string(), shape: vec![1] }]);
+        for (name, param) in &params {
+            let initializer = graph.initializers.iter().find(|initializer| &initializer.name == name).unwrap();
+            assert_eq!(initializer.dims, dims(param.shape()));
+            assert_eq!(initializer.data, TensorData::Float(param.data().to_vec()));
+        }
+        let scalar = graph.initializers.iter().find(|initializer| initializer.name == graph.nodes[7].inputs[1]).unwrap();
+        assert_eq!(scalar.data, TensorData::Float(vec![-1.]));
+    }
+
+    #[test]
+    fn export_errors() {
+        let rec: ComputationRecord<NdArray> = ComputationRecord::new();
+        let input = rec.tensor_from_slice(&[1., 2.]);
+        let other = rec.tensor_from_slice(&[3., 4.]);
+        match export(&[("input", &input)], &[], &cat(&[&input, &input], 0)) {
+            Err(SerializationError::UnsupportedOp { node, op_type }) => {
+                assert_eq!(op_type, "Cat");
+                assert!(node.starts_with("Cat_"));
+            }
+            result => panic!("Expected an unsupported op error, got {:?}", result),
+        }
+        assert!(matches!(export(&[("input", &input)], &[], &add(&input, &other)), Err(SerializationError::InvalidGraph(_))));
+    }
+}
@@ -0,0 +1,702 @@
+//! Execution of imported ONNX graphs with the tracked Ops, so the imported weights can be
+//! fine-tuned like any other parameters.
+//!
+//! Supported are the ops the exporter writes plus Gemm, Flatten, Identity and Constant, which are
+//! common in models exported by other frameworks. Rank 0 tensors are represented with shape [1],
+//! like the scalars of the ComputationRecord. Shapes and axes given as inputs (Reshape, Expand,
+//! ReduceSum) must be constants. Models must use opset 13 or later.
+
+use std::collections::HashMap;
+use std::path::Path;
+use crate::{ComputationRecord, TrackedTensor};
+use crate::module::Module;
+use crate::ops::*;
+use crate::tensor_backends::TensorBackend;
+use crate::serialization::SerializationError;
+use crate::serialization::onnx::OPSET_VERSION;
+use crate::serialization::onnx::proto::{AttributeValue, ModelProto, NodeProto, TensorData, TensorProto};
+
+const SUPPORTED_OPS: &[&str] = &[
+    "Identity", "Constant", "Add", "Sub", "Mul", "Div", "MatMul", "Gemm", "Relu", "LeakyRelu", "Exp",
+    "Log", "Sqrt", "Abs", "Neg", "Sin", "Cos", "Tanh", "Sigmoid", "Softplus", "Reciprocal", "Softmax",
+    "LogSoftmax", "ReduceSum", "ReduceMean", "Reshape", "Expand", "Transpose", "Flatten",
+];
+
+/// Inputs which must be constant INT64 tensors, by op type
+fn int_input_positions(op_type: &str) -> &'static [usize] {
+    match op_type {
+        "Reshape" | "Expand" | "ReduceSum" | "ReduceMean" => &[1],
+        _ => &[],
+    }
+}
+
+/// The ones of `int_input_positions` which can not be left out
+fn required_int_input_positions(op_type: &str) -> &'static [usize] {
+    match op_type {
+        "Reshape" | "Expand" => &[1],
+        _ => &[],
+    }
+}
+
+/// An ONNX graph ready to be executed, see `run` and `track`
+#[derive(Debug, Clone)]
+pub struct OnnxModel<T: TensorBackend> {
+    /// Without the Constant nodes, which are in the constants
+    nodes: Vec<NodeProto>,
+    /// Float initializers of rank 1 or more, which are the trainable parameters
+    params: Vec<(String, T)>,
+    /// Rank 0 float initializers and float Constant nodes
+    float_constants: HashMap<String, T>,
+    /// INT64 initializers and Constant nodes, like the target shape of a Reshape
+    int_constants: HashMap<String, Vec<i64>>,
+    inputs: Vec<(String, Vec<i64>)>,
+    outputs: Vec<String>,
+}
+
+/// Name of the node for error messages, its position if it has no name
+fn node_label(index: usize, node: &NodeProto) -> String {
+    if node.name.is_empty() {
+        format!("#{}", index)
+    } else {
+        node.name.clone()
+    }
+}
+
+fn to_usize_dims(dims: &[i64], name: &str) -> Result<Vec<usize>, SerializationError> {
+    if dims.iter().any(|dim| *dim < 0) {
+        return Err(SerializationError::InvalidGraph(format!("Tensor {} has negative dims {:?}", name, dims)));
+    }
+    if dims.is_empty() {
+        Ok(vec![1])
+    } else {
+        Ok(dims.iter().map(|dim| *dim as usize).collect())
+    }
+}
+
+impl <T: TensorBackend> OnnxModel<T> {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, SerializationError> {
+        let bytes = std::fs::read(path)?;
+        Self::from_proto(&ModelProto::decode(&bytes)?)
+    }
+
+    /// Checks that every op is supported and every node input is defined. Opsets before 13
+    /// define some of the ops differently, e.g. Softmax, and are rejected.
+    pub fn from_proto(model: &ModelProto) -> Result<Self, SerializationError> {
+        if model.opset_version < OPSET_VERSION {
+            return Err(SerializationError::InvalidFormat(format!("Unsupported opset version {}, at least {} is needed", model.opset_version, OPSET_VERSION)));
+        }
+        let graph = &model.graph;
+        let mut imported = OnnxModel {
+            nodes: vec![],
+            params: vec![],
+            float_constants: HashMap::new(),
+            int_constants: HashMap::new(),
+            inputs: vec![],
+            outputs: graph.outputs.iter().map(|output| output.name.clone()).collect(),
+        };
+        for initializer in &graph.initializers {
+            imported.add_constant_or_param(initializer, &initializer.name, true)?;
+        }
+        let mut defined: Vec<String> = graph.initializers.iter().map(|initializer| initializer.name.clone()).collect();
+        for input in &graph.inputs {
+            // Older exporters also list the initializers as inputs
+            if !defined.contains(&input.name) {
+                imported.inputs.push((input.name.clone(), input.shape.clone()));
+                defined.push(input.name.clone());
+            }
+        }
+
+        for (index, node) in graph.nodes.iter().enumerate() {
+            let label = node_label(index, node);
+            if !SUPPORTED_OPS.contains(&node.op_type.as_str()) {
+                return Err(SerializationError::UnsupportedOp { node: label, op_type: node.op_type.clone() });
+            }
+            for (position, input) in node.inputs.iter().enumerate() {
+                if input.is_empty() {
+                    continue;
+                }
+                if !defined.contains(input) {
+                    return Err(SerializationError::InvalidGraph(format!("Input {} of node {} is not defined before it", input, label)));
+                }
+                if int_input_positions(&node.op_type).contains(&position) && !imported.int_constants.contains_key(input) {
+                    return Err(SerializationError::InvalidGraph(format!("Input {} of node {} must be a constant INT64 tensor", input, label)));
+                }
+            }
+            for position in required_int_input_positions(&node.op_type) {
+                if node.inputs.get(*position).is_none_or(|input| input.is_empty()) {
+                    return Err(SerializationError::InvalidGraph(format!("Node {} is missing its input {}", label, position)));
+                }
+            }
+            let output = node.outputs.first()
+                .ok_or_else(|| SerializationError::InvalidGraph(format!("Node {} has no output", label)))?;
+            if node.op_type == "Constant" {
+                match node.attribute("value") {
+                    Some(AttributeValue::Tensor(tensor)) => imported.add_constant_or_param(tensor, output, false)?,
+                    _ => return Err(SerializationError::InvalidGraph(format!("Constant node {} has no tensor value", label))),
+                }
+            } else {
+                imported.nodes.push(node.clone());
+            }
+            defined.push(output.clone());
+        }
+        for output in &imported.outputs {
+            if !defined.contains(output) {
+                return Err(SerializationError::InvalidGraph(format!("Graph output {} is not computed", output)));
+            }
+        }
+        Ok(imported)
+    }
+
+    fn add_constant_or_param(&mut self, tensor: &TensorProto, name: &str, trainable: bool) -> Result<(), SerializationError> {
+        match &tensor.data {
+            TensorData::Int64(values) => {
+                self.int_constants.insert(name.to_string(), values.clone());
+            }
+            TensorData::Float(values) => {
+                let value = T::from_shape_vec(&to_usize_dims(&tensor.dims, name)?, values.clone());
+                if trainable && !tensor.dims.is_empty() {
+                    self.params.push((name.to_string(), value));
+                } else {
+                    self.float_constants.insert(name.to_string(), value);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Names and shapes of the graph inputs, -1 for dimensions without a fixed value
+    pub fn inputs(&self) -> &[(String, Vec<i64>)] {
+        &self.inputs
+    }
+
+    pub fn output_names(&self) -> &[String] {
+        &self.outputs
+    }
+
+    /// The imported weights, in the format of the parameter store
+    pub fn state_dict(&self) -> HashMap<String, T> {
+        self.params.iter().cloned().collect()
+    }
+
+    /// Computes the outputs without keeping the graph for gradients
+    pub fn run(&self, inputs: &[(&str, T)]) -> Result<Vec<T>, SerializationError> {
+        let record = ComputationRecord::new();
+        let module = self.track(&record, &HashMap::new());
+        let inputs: Vec<(&str, TrackedTensor<T>)> = inputs.iter()
+            .map(|(name, value)| (*name, record.tensor_from_value(value.clone())))
+            .collect();
+        let inputs: Vec<(&str, &TrackedTensor<T>)> = inputs.iter().map(|(name, tensor)| (*name, tensor)).collect();
+        Ok(module.run(&inputs)?.into_iter().map(|output| output.into_data()).collect())
+    }
+
+    /// Builds the model on `record` with its parameters taken from the store if there, from the
+    /// ONNX initializers otherwise, like the layers do
+    pub fn track<'m, 't>(&'m self, record: &'t ComputationRecord<T>, params_store: &HashMap<String, T>) -> OnnxModule<'m, 't, T> {
+        let params = self.params.iter()
+            .map(|(name, imported)| {
+                let value = match params_store.get(name) {
+                    None => imported.clone(),
+                    Some(value) => {
+                        assert_eq!(value.shape(), imported.shape(), "Parameter {} in the store has the wrong shape", name);
+                        value.clone()
+                    }
+                };
+                (name.clone(), record.tensor_from_value(value))
+            })
+            .collect();
+        OnnxModule {
+            model: self,
+            record,
+            params,
+            training: true,
+        }
+    }
+}
+
+/// An OnnxModel on a ComputationRecord, whose parameters are the float initializers
+pub struct OnnxModule<'m, 't, T: TensorBackend> {
+    model: &'m OnnxModel<T>,
+    record: &'t ComputationRecord<T>,
+    params: Vec<(String, TrackedTensor<'t, T>)>,
+    training: bool,
+}
+
+impl <'m, 't, T: TensorBackend> OnnxModule<'m, 't, T> {
+    /// Computes all graph outputs from the named inputs
+    pub fn run(&self, inputs: &[(&str, &TrackedTensor<'t, T>)]) -> Result<Vec<TrackedTensor<'t, T>>, SerializationError> {
+        for (name, _shape) in &self.model.inputs {
+            if !inputs.iter().any(|(input_name, _tensor)| input_name == name) {
+                return Err(SerializationError::InvalidGraph(format!("Missing value for input {}", name)));
+            }
+        }
+        let mut values: HashMap<String, TrackedTensor<'t, T>> = self.model.float_constants.iter()
+            .map(|(name, value)| (name.clone(), self.record.tensor_from_value(value.clone())))
+            .collect();
+        for (index, node) in self.model.nodes.iter().enumerate() {
+            let result = {
+                let float_input = |position: usize| -> Option<&TrackedTensor<'t, T>> {
+                    let name = node.inputs.get(position).filter(|name| !name.is_empty())?;
+                    inputs.iter().find(|(input_name, _tensor)| input_name == name).map(|(_name, tensor)| *tensor)
+                        .or_else(|| self.params.iter().find(|(param_name, _tensor)| param_name == name).map(|(_name, tensor)| tensor))
+                        .or_else(|| values.get(name))
+                };
+                let int_input = |position: usize| -> Option<&Vec<i64>> {
+                    let name = node.inputs.get(position).filter(|name| !name.is_empty())?;
+                    self.model.int_constants.get(name)
+                };
+                execute_node(&node_label(index, node), node, float_input, int_input)?
+            };
+            values.insert(node.outputs[0].clone(), result);
+        }
+        Ok(self.model.outputs.iter()
+            .map(|name| {
+                let value = inputs.iter().find(|(input_name, _tensor)| input_name == name).map(|(_name, tensor)| *tensor)
+                    .or_else(|| self.params.iter().find(|(param_name, _tensor)| param_name == name).map(|(_name, tensor)| tensor))
+                    .or_else(|| values.get(name))
+                    .expect("Outputs are checked when importing");
+                reshape(value, value.shape())
+            })
+            .collect())
+    }
+}
+
+impl <'m, 't, T: TensorBackend> Module<'t, T> for OnnxModule<'m, 't, T> {
+    /// For graphs with a single input and output, panics on shape errors
+    fn forward(&self, input: &TrackedTensor<'t, T>) -> TrackedTensor<'t, T> {
+        assert_eq!(self.model.inputs.len(), 1, "forward needs a graph with one input, use run instead");
+        assert_eq!(self.model.outputs.len(), 1, "forward needs a graph with one output, use run instead");
+        let name = self.model.inputs[0].0.as_str();
+        match self.run(&[(name, input)]) {
+            Ok(mut outputs) => outputs.remove(0),
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    fn local_parameters(&self) -> Vec<(String, &TrackedTensor<'t, T>)> {
+        self.params.iter().map(|(name, param)| (name.clone(), param)).collect()
+    }
+
+    fn is_training(&self) -> bool {
+        self.training
+    }
+
+    fn set_training(&mut self, training: bool) {
+        self.training = training;
+    }
+}
+
+/// Shape of the result of NumPy style broadcasting
+fn broadcast_shape(shape_a: &[usize], shape_b: &[usize]) -> Option<Vec<usize>> {
+    let rank = shape_a.len().max(shape_b.len());
+    let padded = |shape: &[usize], axis: usize| {
+        let padding = rank - shape.len();
+        if axis < padding { 1 } else { shape[axis - padding] }
+    };
+    (0..rank)
+        .map(|axis| match (padded(shape_a, axis), padded(shape_b, axis)) {
+            (a, b) if a == b || b == 1 => Some(a),
+            (1, b) => Some(b),
+            _ => None,
+        })
+        .collect()
+}
+
+fn broadcast_error(node: &str, shape_a: &[usize], shape_b: &[usize]) -> SerializationError {
+    SerializationError::InvalidGraph(format!("Node {} can not broadcast {:?} with {:?}", node, shape_a, shape_b))
+}
+
+fn expand<'t, T: TensorBackend>(input: &TrackedTensor<'t, T>, shape: &[usize]) -> TrackedTensor<'t, T> {
+    if input.shape() == shape {
+        reshape(input, shape)
+    } else {
+        broadcast_to(input, shape)
+    }
+}
+
+/// Matmul after checking that the shapes are compatible, since the Op panics otherwise
+fn checked_matmul<'t, T: TensorBackend>(node: &str, a: &TrackedTensor<'t, T>, b: &TrackedTensor<'t, T>) -> Result<TrackedTensor<'t, T>, SerializationError> {
+    let (shape_a, shape_b) = (a.shape(), b.shape());
+    let error = || SerializationError::InvalidGraph(format!("Node {} can not multiply {:?} by {:?}", node, shape_a, shape_b));
+    let (rank_a, rank_b) = (shape_a.len(), shape_b.len());
+    if rank_a == 0 || rank_b == 0 {
+        return Err(error());
+    }
+    let inner_b = if rank_b == 1 { shape_b[0] } else { shape_b[rank_b - 2] };
+    if shape_a[rank_a - 1] != inner_b {
+        return Err(error());
+    }
+    broadcast_shape(&shape_a[..rank_a.saturating_sub(2)], &shape_b[..rank_b.saturating_sub(2)]).ok_or_else(error)?;
+    Ok(matmul(a, b))
+}
+
+/// Applies an element wise Op after broadcasting both operands to the same shape
+fn broadcast_binary<'t, T: TensorBackend>(node: &str, a: &TrackedTensor<'t, T>, b: &TrackedTensor<'t, T>, op: fn(&TrackedTensor<'t, T>, &TrackedTensor<'t, T>) -> TrackedTensor<'t, T>) -> Result<TrackedTensor<'t, T>, SerializationError> {
+    let shape = broadcast_shape(a.shape(), b.shape()).ok_or_else(|| broadcast_error(node, a.shape(), b.shape()))?;
+    if a.shape() == shape.as_slice() && b.shape() == shape.as_slice() {
+        return Ok(op(a, b));
+    }
+    Ok(op(&expand(a, &shape), &expand(b, &shape)))
+}
+
+fn float_attribute(label: &str, node: &NodeProto, name: &str, default: f32) -> Result<f32, SerializationError> {
+    match node.attribute(name) {
+        None => Ok(default),
+        Some(AttributeValue::Float(value)) => Ok(*value),
+        Some(_) => Err(SerializationError::InvalidGraph(format!("Attribute {} of node {} is not a float", name, label))),
+    }
+}
+
+fn int_attribute(label: &str, node: &NodeProto, name: &str, default: i64) -> Result<i64, SerializationError> {
+    match node.attribute(name) {
+        None => Ok(default),
+        Some(AttributeValue::Int(value)) => Ok(*value),
+        Some(_) => Err(SerializationError::InvalidGraph(format!("Attribute {} of node {} is not an int", name, label))),
+    }
+}
+
+fn ints_attribute(label: &str, node: &NodeProto, name: &str) -> Result<Option<Vec<i64>>, SerializationError> {
+    match node.attribute(name) {
+        None => Ok(None),
+        Some(AttributeValue::Ints(values)) => Ok(Some(values.clone())),
+        Some(_) => Err(SerializationError::InvalidGraph(format!("Attribute {} of node {} is not a list of ints", name, label))),
+    }
+}
+
+/// Resolves a negative axis, counted from the end
+fn normalize_axis(node: &str, axis: i64, rank: usize) -> Result<usize, SerializationError> {
+    let normalized = if axis < 0 { axis + rank as i64 } else { axis };
+    if normalized < 0 || normalized >= rank as i64 {
+        return Err(SerializationError::InvalidGraph(format!("Axis {} of node {} is out of range for rank {}", axis, node, rank)));
+    }
+    Ok(normalized as usize)
+}
+
+/// ReduceSum or ReduceMean over `axes`, all of them if None
+fn reduce<'t, T: TensorBackend>(node: &str, input: &TrackedTensor<'t, T>, axes: Option<Vec<i64>>, keepdims: bool, is_sum: bool) -> Result<TrackedTensor<'t, T>, SerializationError> {
+    let rank = input.shape().len();
+    let axes = match axes {
+        Some(axes) if !axes.is_empty() => axes,
+        _ => {
+            let reduced = if is_sum { sum(input) } else { mean(input) };
+            return Ok(if keepdims { reshape(&reduced, &vec![1; rank]) } else { reduced });
+        }
+    };
+    let mut axes = axes.iter().map(|axis| normalize_axis(node, *axis, rank)).collect::<Result<Vec<usize>, _>>()?;
+    axes.sort_unstable();
+    axes.dedup();
+    // From the last axis so the indices of the remaining ones do not change
+    let mut result = reshape(input, input.shape());
+    for axis in axes.into_iter().rev() {
+        result = if is_sum { sum_axis(&result, axis, keepdims) } else { mean_axis(&result, axis, keepdims) };
+    }
+    Ok(result)
+}
+
+/// Target shape of a Reshape, where 0 copies the input dimension and -1 is inferred
+fn reshape_target(node: &str, input_shape: &[usize], target: &[i64]) -> Result<Vec<usize>, SerializationError> {
+    let error = || SerializationError::InvalidGraph(format!("Node {} can not reshape {:?} to {:?}", node, input_shape, target));
+    let mut shape = vec![];
+    let mut inferred = None;
+    for (axis, len) in target.iter().enumerate() {
+        match *len {
+            0 => shape.push(*input_shape.get(axis).ok_or_else(error)?),
+            -1 if inferred.is_none() => {
+                inferred = Some(axis);
+                shape.push(1);
+            }
+            len if len > 0 => shape.push(len as usize),
+            _ => return Err(error()),
+        }
+    }
+    let input_len: usize = input_shape.iter().product();
+    let known_len = shape.iter().try_fold(1usize, |len, dim| len.checked_mul(*dim)).ok_or_else(error)?;
+    if let Some(axis) = inferred {
+        if known_len == 0 || !input_len.is_multiple_of(known_len) {
+            return Err(error());
+        }
+        shape[axis] = input_len / known_len;
+    } else if known_len != input_len {
+        return Err(error());
+    }
+    if shape.is_empty() {
+        shape.push(1);
+    }
+    Ok(shape)
+}
+
+fn execute_node<'a, 't: 'a, T: TensorBackend>(
+    label: &str,
+    node: &NodeProto,
+    float_input: impl Fn(usize) -> Option<&'a TrackedTensor<'t, T>>,
+    int_input: impl Fn(usize) -> Option<&'a Vec<i64>>,
+) -> Result<TrackedTensor<'t, T>, SerializationError> {
+    let input = |position: usize| {
+        float_input(position)
+            .ok_or_else(|| SerializationError::InvalidGraph(format!("Node {} is missing its float input {}", label, position)))
+    };
+    let op_type = node.op_type.as_str();
+    let result = match op_type {
+        "Identity" => {
+            let x = input(0)?;
+            reshape(x, x.shape())
+        }
+        "Add" => broadcast_binary(label, input(0)?, input(1)?, add)?,
+        "Sub" => broadcast_binary(label, input(0)?, input(1)?, sub)?,
+        "Mul" => broadcast_binary(label, input(0)?, input(1)?, mul)?,
+        "Div" => broadcast_binary(label, input(0)?, input(1)?, div)?,
+        "MatMul" => checked_matmul(label, input(0)?, input(1)?)?,
+        "Gemm" => {
+            // alpha * A' B' + beta * C
+            for position in 0..2 {
+                if input(position)?.shape().len() != 2 {
+                    return Err(SerializationError::InvalidGraph(format!("Input {} of Gemm node {} must be a matrix, got shape {:?}", position, label, input(position)?.shape())));
+                }
+            }
+            let a = if int_attribute(label, node, "transA", 0)? != 0 { transpose(input(0)?, 0, 1) } else { reshape(input(0)?, input(0)?.shape()) };
+            let b = if int_attribute(label, node, "transB", 0)? != 0 { transpose(input(1)?, 0, 1) } else { reshape(input(1)?, input(1)?.shape()) };
+            let mut result = checked_matmul(label, &a, &b)?;
+            let alpha = float_attribute(label, node, "alpha", 1.)?;
+            if alpha != 1. {
+                result = mul_scalar(&result, alpha);
+            }
+            if let Some(c) = float_input(2) {
+                let beta = float_attribute(label, node, "beta", 1.)?;
+                let c = if beta != 1. { mul_scalar(c, beta) } else { reshape(c, c.shape()) };
+                let shape = broadcast_shape(result.shape(), c.shape())
+                    .filter(|shape| shape.as_slice() == result.shape())
+                    .ok_or_else(|| broadcast_error(label, result.shape(), c.shape()))?;
+                result = add(&result, &expand(&c, &shape));
+            }
+            result
+        }
+        "Relu" => relu(input(0)?),
+        "LeakyRelu" => leaky_relu(input(0)?, float_attribute(label, node, "alpha", 0.01)?),
+        "Exp" => exp(input(0)?),
+        "Log" => log(input(0)?),
+        "Sqrt" => sqrt(input(0)?),
+        "Abs" => abs(input(0)?),
+        "Neg" => neg(input(0)?),
+        "Sin" => sin(input(0)?),
+        "Cos" => cos(input(0)?),
+        "Tanh" => tanh(input(0)?),
+        "Sigmoid" => sigmoid(input(0)?),
+        "Softplus" => softplus(input(0)?),
+        "Reciprocal" => reciprocal(input(0)?),
+        "Softmax" | "LogSoftmax" => {
+            let x = input(0)?;
+            let axis = normalize_axis(label, int_attribute(label, node, "axis", -1)?, x.shape().len())?;
+            if op_type == "Softmax" { softmax(x, axis) } else { log_softmax(x, axis) }
+        }
+        "ReduceSum" | "ReduceMean" => {
+            // ReduceSum takes the axes as input since opset 13, ReduceMean since opset 18
+            let axes = match int_input(1) {
+                Some(axes) => Some(axes.clone()),
+                None => ints_attribute(label, node, "axes")?,
+            };
+            let keepdims = int_attribute(label, node, "keepdims", 1)? != 0;
+            reduce(label, input(0)?, axes, keepdims, op_type == "ReduceSum")?
+        }
+        "Reshape" => {
+            let x = input(0)?;
+            let target = int_input(1).expect("Checked when importing");
+            reshape(x, &reshape_target(label, x.shape(), target)?)
+        }
+        "Expand" => {
+            let x = input(0)?;
+            let target = to_usize_dims(int_input(1).expect("Checked when importing"), label)?;
+            let shape = broadcast_shape(x.shape(), &target).ok_or_else(|| broadcast_error(label, x.shape(), &target))?;
+            expand(x, &shape)
+        }
+        "Transpose" => {
+            let x = input(0)?;
+            let rank = x.shape().len();
+            let axes: Vec<usize> = match ints_attribute(label, node, "perm")? {
+                None => (0..rank).rev().collect(),
+                Some(perm) => perm.iter().map(|axis| normalize_axis(label, *axis, rank)).collect::<Result<_, _>>()?,
+            };
+            permute(x, &axes)
+        }
+        "Flatten" => {
+            let x = input(0)?;
+            let axis = int_attribute(label, node, "axis", 1)?;
+            let axis = if axis == x.shape().len() as i64 { x.shape().len() } else { normalize_axis(label, axis, x.shape().len())? };
+            let outer: usize = x.shape()[..axis].iter().product();
+            let inner: usize = x.shape()[axis..].iter().product();
+            reshape(x, &[outer, inner])
+        }
+        _ => return Err(SerializationError::UnsupportedOp { node: label.to_string(), op_type: op_type.to_string() }),
+    };
+    Ok(result)
+}
+
+
+#[cfg(test)]
+mod import_tests {
+    use super::*;
+    use crate::layers::{LinearConfig, LinearLayer};
+    use crate::optim::{Optimizer, Sgd, SgdConfig};
+    use crate::serialization::onnx::proto::{AttributeProto, GraphProto, ValueInfoProto};
+    use crate::serialization::onnx::{export, OPSET_VERSION};
+    use crate::tensor_backends::NdArray;
+
+    fn input_data() -> NdArray {
+        NdArray::from_shape_vec(&[2, 3], vec![1., 2., 3., -1., 0., 1.])
+    }
+
+    #[test]
+    fn export_import_round_trip() {
+        let rec: ComputationRecord<NdArray> = ComputationRecord::new();
+        let store = HashMap::new();
+        let hidden = LinearLayer::from_config(&rec, LinearConfig { name: Some("hidden".to_string()), ..LinearConfig::new(3, 4) }, &store);
+        let head = LinearLayer::from_config(&rec, LinearConfig { name: Some("head".to_string()), ..LinearConfig::new(4, 2) }, &store);
+        let input = rec.tensor_from_value(input_data());
+        let logits = head.forward(&leaky_relu(&hidden.forward(&input), 0.1));
+        let output = mean_axis(&div_scalar(&log_softmax(&reshape(&logits, &[1, 4]), 1), 2.), 0, false);
+        let mut params = hidden.named_parameters();
+        params.extend(head.named_parameters());
+        let bytes = export(&[("input", &input)], &params, &output).unwrap().encode();
+
+        let model: OnnxModel<NdArray> = OnnxModel::from_proto(&ModelProto::decode(&bytes).unwrap()).unwrap();
+        assert_eq!(model.inputs(), &[("input".to_string(), vec![2, 3])]);
+        let mut imported_names: Vec<String> = model.state_dict().into_keys().collect();
+        imported_names.sort();
+        assert_eq!(imported_names, vec!["head.bias", "head.weight", "hidden.bias", "hidden.weight"]);
+        let outputs = model.run(&[("input", input_data())]).unwrap();
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(outputs[0].shape(), output.shape());
+        for (imported, original) in outputs[0].to_vec().iter().zip(output.data().to_vec()) {
+            assert!((imported - original).abs() < 1e-6, "{} != {}", imported, original);
+        }
+    }
+
+    #[test]
+    fn fine_tune_imported_weights() {
+        let rec: ComputationRecord<NdArray> = ComputationRecord::new();
+        let linear = LinearLayer::from_config(&rec, LinearConfig { name: Some("fc".to_string()), ..LinearConfig::new(3, 2) }, &HashMap::new());
+        let input = rec.tensor_from_value(input_data());
+        let bytes = export(&[("input", &input)], &linear.named_parameters(), &linear.forward(&input)).unwrap().encode();
+        let model: OnnxModel<NdArray> = OnnxModel::from_proto(&ModelProto::decode(&bytes).unwrap()).unwrap();
+
+        let mut store = model.state_dict();
+        let mut optimizer = Sgd::new(SgdConfig::new(0.01));
+        let mut losses = vec![];
+        for _step in 0..3 {
+            let rec: ComputationRecord<NdArray> = ComputationRecord::new();
+            let module = model.track(&rec, &store);
+            let loss = sum(&square(&module.forward(&rec.tensor_from_value(input_data()))));
+            losses.push(loss.data().index(&[0]));
+            let grad = loss.grad();
+            optimizer.step(&module.named_parameters(), &grad, &mut store);
+        }
+        assert!(losses[2] < losses[1] && losses[1] < losses[0], "{:?}", losses);
+        // The imported model keeps the original weights
+        assert_eq!(&model.state_dict()["fc.weight"], linear.named_parameters()[0].1.data());
+    }
+
+    fn node(name: &str, op_type: &str, inputs: &[&str], output: &str, attributes: Vec<AttributeProto>) -> NodeProto {
+        NodeProto {
+            name: name.to_string(),
+            op_type: op_type.to_string(),
+            inputs: inputs.iter().map(|input| input.to_string()).collect(),
+            outputs: vec![output.to_string()],
+            attributes,
+        }
+    }
+
+    fn model(nodes: Vec<NodeProto>, initializers: Vec<TensorProto>, output: &str) -> ModelProto {
+        ModelProto {
+            ir_version: 8,
+            producer_name: "test".to_string(),
+            opset_version: OPSET_VERSION,
+            graph: GraphProto {
+                name: "test".to_string(),
+                nodes,
+                initializers,
+                inputs: vec![ValueInfoProto { name: "x".to_string(), shape: vec![-1, 2, 2] }],
+                outputs: vec![ValueInfoProto { name: output.to_string(), shape: vec![-1, 1] }],
+            },
+        }
+    }
+
+    #[test]
+    fn gemm_flatten_and_constant() {
+        let weight = TensorProto { name: "w".to_string(), dims: vec![1, 4], data: TensorData::Float(vec![1., 2., 3., 4.]) };
+        let bias = TensorProto { name: "b".to_string(), dims: vec![], data: TensorData::Float(vec![0.5]) };
+        let two = AttributeProto { name: "value".to_string(), value: AttributeValue::Tensor(TensorProto { name: String::new(), dims: vec![1], data: TensorData::Float(vec![2.]) }) };
+        let trans_b = AttributeProto { name: "transB".to_string(), value: AttributeValue::Int(1) };
+        let onnx = model(vec![
+            node("flatten", "Flatten", &["x"], "flat", vec![]),
+            node("two", "Constant", &[], "two", vec![two]),
+            node("gemm", "Gemm", &["flat", "w", "b"], "y", vec![trans_b]),
+            node("double", "Mul", &["y", "two"], "out", vec![]),
+        ], vec![weight, bias], "out");
+        let model: OnnxModel<NdArray> = OnnxModel::from_proto(&ModelProto::decode(&onnx.encode()).unwrap()).unwrap();
+        // The rank 0 bias is a constant, not a parameter
+        assert_eq!(model.state_dict().len(), 1);
+        let x = NdArray::from_shape_vec(&[2, 2, 2], vec![1., 0., 0., 0., 0., 0., 0., 1.]);
+        let outputs = model.run(&[("x", x)]).unwrap();
+        assert_eq!(outputs[0], NdArray::from_shape_vec(&[2, 1], vec![3., 9.]));
+    }
+
+    #[test]
+    fn unsupported_op_is_reported() {
+        let onnx = model(vec![
+            node("relu", "Relu", &["x"], "y", vec![]),
+            node("conv_1", "Conv", &["y"], "z", vec![]),
+        ], vec![], "z");
+        match OnnxModel::<NdArray>::from_proto(&onnx) {
+            Err(error) => {
+                assert_eq!(error.to_string(), "Unsupported op Conv in node conv_1");
+            }
+            Ok(_) => panic!("Conv is not supported"),
+        }
+        let onnx = model(vec![node("relu", "Relu", &["missing"], "y", vec![])], vec![], "y");
+        assert!(matches!(OnnxModel::<NdArray>::from_proto(&onnx), Err(SerializationError::InvalidGraph(_))));
+        for inputs in &[&["x"][..], &["x", ""][..]] {
+            let onnx = model(vec![node("reshape", "Reshape", inputs, "y", vec![])], vec![], "y");
+            assert!(matches!(OnnxModel::<NdArray>::from_proto(&onnx), Err(SerializationError::InvalidGraph(_))));
+        }
+        let mut onnx = model(vec![node("relu", "Relu", &["x"], "y", vec![])], vec![], "y");
+        onnx.opset_version = 11;
+        assert!(matches!(OnnxModel::<NdArray>::from_proto(&onnx), Err(SerializationError::InvalidFormat(_))));
+    }
+
+    #[test]
+    fn invalid_nodes_fail_when_run() {
+        let weight = TensorProto { name: "w".to_string(), dims: vec![3, 1], data: TensorData::Float(vec![1., 2., 3.]) };
+        let alpha = AttributeProto { name: "alpha".to_string(), value: AttributeValue::Int(2) };
+        let x = NdArray::from_shape_vec(&[2, 2, 2], vec![1.; 8]);
+        let graphs = vec![
+            (node("", "MatMul", &["x", "w"], "y", vec![]), "Node #0 can not multiply [2, 2, 2] by [3, 1]"),
+            (node("gemm", "Gemm", &["x", "w"], "y", vec![]), "Input 0 of Gemm node gemm must be a matrix, got shape [2, 2, 2]"),
+            (node("", "LeakyRelu", &["x"], "y", vec![alpha]), "Attribute alpha of node #0 is not a float"),
+        ];
+        for (node, message) in graphs {
+            let model: OnnxModel<NdArray> = OnnxModel::from_proto(&model(vec![node], vec![weight.clone()], "y")).unwrap();
+            match model.run(&[("x", x.clone())]) {
+                Err(SerializationError::InvalidGraph(error)) => assert_eq!(error, message),
+                other => panic!("Expected an invalid graph error, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_dims_are_rejected() {
+        for dims in &[vec![i64::MAX, 2], vec![-1, -2]] {
+            let weight = TensorProto { name: "w".to_string(), dims: dims.clone(), data: TensorData::Float(vec![1., 2.]) };
+            let onnx = model(vec![node("add", "Add", &["x", "w"], "y", vec![])], vec![weight], "y");
+            assert!(matches!(ModelProto::decode(&onnx.encode()), Err(SerializationError::InvalidFormat(_))));
+        }
+    }
+
+    #[test]
+    fn reshape_targets() {
+        assert_eq!(reshape_target("r", &[2, 3, 4], &[0, -1]).unwrap(), vec![2, 12]);
+        assert_eq!(reshape_target("r", &[2, 3, 4], &[-1, 4, 1]).unwrap(), vec![6, 4, 1]);
+        assert!(reshape_target("r", &[2, 3, 4], &[5, -1]).is_err());
+        assert!(reshape_target("r", &[2, 3], &[-1, -1]).is_err());
+        assert!(reshape_target("r", &[2, 3], &[i64::MAX, i64::MAX, -1]).is_err());
+        assert_eq!(broadcast_shape(&[3, 1], &[4]), Some(vec![3, 4]));
+        assert_eq!(broadcast_shape(&[3], &[4]), None);
+    }
+}
@@ -0,0 +1,426 @@
+//! The subset of the ONNX messages (onnx.proto3) used for export and import. Fields which are
+//! not listed are skipped when decoding.
+
+use std::convert::TryFrom;
+use crate::serialization::SerializationError;
+use crate::serialization::onnx::protobuf::{decode_fields, Encoder};
+
+/// TensorProto.DataType values
+const FLOAT: i64 = 1;
+const INT64: i64 = 7;
+
+/// AttributeProto.AttributeType values
+const ATTRIBUTE_FLOAT: i64 = 1;
+const ATTRIBUTE_INT: i64 = 2;
+const ATTRIBUTE_STRING: i64 = 3;
+const ATTRIBUTE_TENSOR: i64 = 4;
+const ATTRIBUTE_FLOATS: i64 = 6;
+const ATTRIBUTE_INTS: i64 = 7;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelProto {
+    pub ir_version: i64,
+    pub producer_name: String,
+    /// Version of the default ("ai.onnx") operator set
+    pub opset_version: i64,
+    pub graph: GraphProto,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct GraphProto {
+    pub name: String,
+    /// In topological order
+    pub nodes: Vec<NodeProto>,
+    pub initializers: Vec<TensorProto>,
+    pub inputs: Vec<ValueInfoProto>,
+    pub outputs: Vec<ValueInfoProto>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct NodeProto {
+    pub name: String,
+    pub op_type: String,
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+    pub attributes: Vec<AttributeProto>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttributeProto {
+    pub name: String,
+    pub value: AttributeValue,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttributeValue {
+    Float(f32),
+    Int(i64),
+    String(String),
+    Tensor(TensorProto),
+    Floats(Vec<f32>),
+    Ints(Vec<i64>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TensorProto {
+    pub name: String,
+    pub dims: Vec<i64>,
+    pub data: TensorData,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TensorData {
+    Float(Vec<f32>),
+    Int64(Vec<i64>),
+}
+
+/// A float tensor graph input or output. Dimensions without a fixed value (dim_param) are -1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValueInfoProto {
+    pub name: String,
+    pub shape: Vec<i64>,
+}
+
+fn invalid(message: &str) -> SerializationError {
+    SerializationError::InvalidFormat(message.to_string())
+}
+
+impl ModelProto {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut encoder = Encoder::new();
+        encoder.int(1, self.ir_version);
+        encoder.string(2, &self.producer_name);
+        encoder.message(7, self.graph.encode());
+        let mut opset = Encoder::new();
+        opset.string(1, "");
+        opset.int(2, self.opset_version);
+        encoder.message(8, opset);
+        encoder.into_bytes()
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, SerializationError> {
+        let mut model = ModelProto {
+            ir_version: 0,
+            producer_name: String::new(),
+            opset_version: 0,
+            graph: GraphProto::default(),
+        };
+        let mut has_graph = false;
+        for (field, value) in decode_fields(bytes)? {
+            match field {
+                1 => model.ir_version = value.as_int()?,
+                2 => model.producer_name = value.as_string()?,
+                7 => {
+                    model.graph = GraphProto::decode(value.as_bytes()?)?;
+                    has_graph = true;
+                }
+                8 => {
+                    let mut domain = String::new();
+                    let mut version = 0;
+                    for (field, value) in decode_fields(value.as_bytes()?)? {
+                        match field {
+                            1 => domain = value.as_string()?,
+                            2 => version = value.as_int()?,
+                            _ => {}
+                        }
+                    }
+                    if domain.is_empty() || domain == "ai.onnx" {
+                        model.opset_version = version;
+                    }
+                }
+                _ => {}
+            }
+        }
+        if !has_graph {
+            return Err(invalid("ONNX model has no graph"));
+        }
+        Ok(model)
+    }
+}
+
+impl GraphProto {
+    fn encode(&self) -> Encoder {
+        let mut encoder = Encoder::new();
+        for node in &self.nodes {
+            encoder.message(1, node.encode());
+        }
+        encoder.string(2, &self.name);
+        for initializer in &self.initializers {
+            encoder.message(5, initializer.encode());
+        }
+        for input in &self.inputs {
+            encoder.message(11, input.encode());
+        }
+        for output in &self.outputs {
+            encoder.message(12, output.encode());
+        }
+        encoder
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, SerializationError> {
+        let mut graph = GraphProto::default();
+        for (field, value) in decode_fields(bytes)? {
+            match field {
+                1 => graph.nodes.push(NodeProto::decode(value.as_bytes()?)?),
+                2 => graph.name = value.as_string()?,
+                5 => graph.initializers.push(TensorProto::decode(value.as_bytes()?)?),
+                11 => graph.inputs.push(ValueInfoProto::decode(value.as_bytes()?)?),
+                12 => graph.outputs.push(ValueInfoProto::decode(value.as_bytes()?)?),
+                _ => {}
+            }
+        }
+        Ok(graph)
+    }
+}
+
+impl NodeProto {
+    pub fn attribute(&self, name: &str) -> Option<&AttributeValue> {
+        self.attributes.iter()
+            .find(|attribute| attribute.name == name)
+            .map(|attribute| &attribute.value)
+    }
+
+    fn encode(&self) -> Encoder {
+        let mut encoder = Encoder::new();
+        for input in &self.inputs {
+            encoder.string(1, input);
+        }
+        for output in &self.outputs {
+            encoder.string(2, output);
+        }
+        encoder.string(3, &self.name);
+        encoder.string(4, &self.op_type);
+        for attribute in &self.attributes {
+            encoder.message(5, attribute.encode());
+        }
+        encoder
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, SerializationError> {
+        let mut node = NodeProto::default();
+        for (field, value) in decode_fields(bytes)? {
+            match field {
+                1 => node.inputs.push(value.as_string()?),
+                2 => node.outputs.push(value.as_string()?),
+                3 => node.name = value.as_string()?,
+                4 => node.op_type = value.as_string()?,
+                5 => {
+                    // Attribute types this crate does not use, like graphs, are skipped
+                    if let Some(attribute) = AttributeProto::decode(value.as_bytes()?)? {
+                        node.attributes.push(attribute);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(node)
+    }
+}
+
+impl AttributeProto {
+    fn encode(&self) -> Encoder {
+        let mut encoder = Encoder::new();
+        encoder.string(1, &self.name);
+        let attribute_type = match &self.value {
+            AttributeValue::Float(value) => {
+                encoder.float(2, *value);
+                ATTRIBUTE_FLOAT
+            }
+            AttributeValue::Int(value) => {
+                encoder.int(3, *value);
+                ATTRIBUTE_INT
+            }
+            AttributeValue::String(value) => {
+                encoder.string(4, value);
+                ATTRIBUTE_STRING
+            }
+            AttributeValue::Tensor(tensor) => {
+                encoder.message(5, tensor.encode());
+                ATTRIBUTE_TENSOR
+            }
+            AttributeValue::Floats(values) => {
+                encoder.packed_floats(7, values);
+                ATTRIBUTE_FLOATS
+            }
+            AttributeValue::Ints(values) => {
+                encoder.packed_ints(8, values);
+                ATTRIBUTE_INTS
+            }
+        };
+        encoder.int(20, attribute_type);
+        encoder
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Option<Self>, SerializationError> {
+        let mut name = String::new();
+        let mut attribute_type = 0;
+        let mut float = 0.;
+        let mut int = 0;
+        let mut string = String::new();
+        let mut tensor = None;
+        let mut floats = vec![];
+        let mut ints = vec![];
+        for (field, value) in decode_fields(bytes)? {
+            match field {
+                1 => name = value.as_string()?,
+                2 => float = value.as_float()?,
+                3 => int = value.as_int()?,
+                4 => string = value.as_string()?,
+                5 => tensor = Some(TensorProto::decode(value.as_bytes()?)?),
+                7 => value.push_floats(&mut floats)?,
+                8 => value.push_ints(&mut ints)?,
+                20 => attribute_type = value.as_int()?,
+                _ => {}
+            }
+        }
+        let value = match attribute_type {
+            ATTRIBUTE_FLOAT => AttributeValue::Float(float),
+            ATTRIBUTE_INT => AttributeValue::Int(int),
+            ATTRIBUTE_STRING => AttributeValue::String(string),
+            ATTRIBUTE_TENSOR => match tensor {
+                Some(tensor) => AttributeValue::Tensor(tensor),
+                None => return Err(invalid("Tensor attribute without a tensor")),
+            },
+            ATTRIBUTE_FLOATS => AttributeValue::Floats(floats),
+            ATTRIBUTE_INTS => AttributeValue::Ints(ints),
+            _ => return Ok(None),
+        };
+        Ok(Some(AttributeProto { name, value }))
+    }
+}
+
+impl TensorProto {
+    fn encode(&self) -> Encoder {
+        let mut encoder = Encoder::new();
+        encoder.packed_ints(1, &self.dims);
+        let (data_type, raw): (i64, Vec<u8>) = match &self.data {
+            TensorData::Float(values) => (FLOAT, values.iter().flat_map(|value| value.to_le_bytes().to_vec()).collect()),
+            TensorData::Int64(values) => (INT64, values.iter().flat_map(|value| value.to_le_bytes().to_vec()).collect()),
+        };
+        encoder.int(2, data_type);
+        encoder.string(8, &self.name);
+        encoder.bytes(9, &raw);
+        encoder
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, SerializationError> {
+        let mut name = String::new();
+        let mut dims = vec![];
+        let mut data_type = 0;
+        let mut float_data = vec![];
+        let mut int64_data = vec![];
+        let mut raw_data: &[u8] = &[];
+        let mut has_raw_data = false;
+        for (field, value) in decode_fields(bytes)? {
+            match field {
+                1 => value.push_ints(&mut dims)?,
+                2 => data_type = value.as_int()?,
+                4 => value.push_floats(&mut float_data)?,
+                7 => value.push_ints(&mut int64_data)?,
+                8 => name = value.as_string()?,
+                9 => {
+                    raw_data = value.as_bytes()?;
+                    has_raw_data = true;
+                }
+                _ => {}
+            }
+        }
+        let data = match data_type {
+            FLOAT if has_raw_data => {
+                if !raw_data.len().is_multiple_of(4) {
+                    return Err(invalid("Raw float data length is not a multiple of 4"));
+                }
+                TensorData::Float(raw_data.chunks_exact(4).map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]])).collect())
+            }
+            FLOAT => TensorData::Float(float_data),
+            INT64 if has_raw_data => {
+                if !raw_data.len().is_multiple_of(8) {
+                    return Err(invalid("Raw int64 data length is not a multiple of 8"));
+                }
+                TensorData::Int64(raw_data.chunks_exact(8).map(|chunk| {
+                    let mut le_bytes = [0; 8];
+                    le_bytes.copy_from_slice(chunk);
+                    i64::from_le_bytes(le_bytes)
+                }).collect())
+            }
+            INT64 => TensorData::Int64(int64_data),
+            _ => return Err(SerializationError::InvalidFormat(format!("Tensor {} has unsupported data type {}, only FLOAT and INT64 are supported", name, data_type))),
+        };
+        let tensor = TensorProto { name, dims, data };
+        let expected_len = tensor.dims.iter()
+            .try_fold(1usize, |len, dim| usize::try_from(*dim).ok().and_then(|dim| len.checked_mul(dim)))
+            .ok_or_else(|| SerializationError::InvalidFormat(format!("Tensor {} has invalid dims {:?}", tensor.name, tensor.dims)))?;
+        if tensor.len() != expected_len {
+            return Err(SerializationError::InvalidFormat(format!("Tensor {} has {} values but its dims {:?} need {}", tensor.name, tensor.len(), tensor.dims, expected_len)));
+        }
+        Ok(tensor)
+    }
+
+    fn len(&self) -> usize {
+        match &self.data {
+            TensorData::Float(values) => values.len(),
+            TensorData::Int64(values) => values.len(),
+        }
+    }
+}
+
+impl ValueInfoProto {
+    fn encode(&self) -> Encoder {
+        let mut shape = Encoder::new();
+        for dim in &self.shape {
+            let mut dimension = Encoder::new();
+            dimension.int(1, *dim);
+            shape.message(1, dimension);
+        }
+        let mut tensor_type = Encoder::new();
+        tensor_type.int(1, FLOAT);
+        tensor_type.message(2, shape);
+        let mut type_proto = Encoder::new();
+        type_proto.message(1, tensor_type);
+
+        let mut encoder = Encoder::new();
+        encoder.string(1, &self.name);
+        encoder.message(2, type_proto);
+        encoder
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, SerializationError> {
+        let mut info = ValueInfoProto { name: String::new(), shape: vec![] };
+        for (field, value) in decode_fields(bytes)? {
+            match field {
+                1 => info.name = value.as_string()?,
+                2 => info.shape = decode_tensor_shape(value.as_bytes()?)?,
+                _ => {}
+            }
+        }
+        Ok(info)
+    }
+}
+
+/// TypeProto -> tensor_type -> shape -> dims
+fn decode_tensor_shape(type_proto: &[u8]) -> Result<Vec<i64>, SerializationError> {
+    let mut shape = vec![];
+    for (field, value) in decode_fields(type_proto)? {
+        if field != 1 {
+            continue;
+        }
+        for (field, value) in decode_fields(value.as_bytes()?)? {
+            if field != 2 {
+                continue;
+            }
+            for (field, value) in decode_fields(value.as_bytes()?)? {
+                if field != 1 {
+                    continue;
+                }
+                let mut dim = -1;
+                for (field, value) in decode_fields(value.as_bytes()?)? {
+                    if field == 1 {
+                        dim = value.as_int()?;
+                    }
+                }
+                shape.push(dim);
+            }
+        }
+    }
+    Ok(shape)
+}
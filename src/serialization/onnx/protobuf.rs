@@ -0,0 +1,226 @@
+//! Protocol buffers wire format, enough to encode and decode the ONNX messages
+
+use std::convert::TryInto;
+use crate::serialization::SerializationError;
+
+const VARINT: u32 = 0;
+const FIXED64: u32 = 1;
+const LENGTH_DELIMITED: u32 = 2;
+const FIXED32: u32 = 5;
+
+#[derive(Debug, Default)]
+pub(crate) struct Encoder {
+    bytes: Vec<u8>,
+}
+
+impl Encoder {
+    pub(crate) fn new() -> Self {
+        Encoder::default()
+    }
+
+    pub(crate) fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    fn raw_varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.bytes.push((value as u8 & 0x7F) | 0x80);
+            value >>= 7;
+        }
+        self.bytes.push(value as u8);
+    }
+
+    fn key(&mut self, field: u32, wire_type: u32) {
+        self.raw_varint(((field << 3) | wire_type) as u64);
+    }
+
+    /// int32 and int64 fields, negative values take 10 bytes
+    pub(crate) fn int(&mut self, field: u32, value: i64) {
+        self.key(field, VARINT);
+        self.raw_varint(value as u64);
+    }
+
+    pub(crate) fn float(&mut self, field: u32, value: f32) {
+        self.key(field, FIXED32);
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn bytes(&mut self, field: u32, value: &[u8]) {
+        self.key(field, LENGTH_DELIMITED);
+        self.raw_varint(value.len() as u64);
+        self.bytes.extend_from_slice(value);
+    }
+
+    pub(crate) fn string(&mut self, field: u32, value: &str) {
+        self.bytes(field, value.as_bytes());
+    }
+
+    pub(crate) fn message(&mut self, field: u32, message: Encoder) {
+        self.bytes(field, &message.bytes);
+    }
+
+    pub(crate) fn packed_ints(&mut self, field: u32, values: &[i64]) {
+        let mut packed = Encoder::new();
+        for value in values {
+            packed.raw_varint(*value as u64);
+        }
+        self.bytes(field, &packed.bytes);
+    }
+
+    pub(crate) fn packed_floats(&mut self, field: u32, values: &[f32]) {
+        let packed: Vec<u8> = values.iter().flat_map(|value| value.to_le_bytes().to_vec()).collect();
+        self.bytes(field, &packed);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum FieldValue<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Bytes(&'a [u8]),
+    Fixed32(u32),
+}
+
+impl <'a> FieldValue<'a> {
+    pub(crate) fn as_int(self) -> Result<i64, SerializationError> {
+        match self {
+            FieldValue::Varint(value) => Ok(value as i64),
+            _ => Err(wrong_type("varint")),
+        }
+    }
+
+    pub(crate) fn as_float(self) -> Result<f32, SerializationError> {
+        match self {
+            FieldValue::Fixed32(bits) => Ok(f32::from_bits(bits)),
+            _ => Err(wrong_type("fixed32")),
+        }
+    }
+
+    pub(crate) fn as_bytes(self) -> Result<&'a [u8], SerializationError> {
+        match self {
+            FieldValue::Bytes(bytes) => Ok(bytes),
+            _ => Err(wrong_type("length delimited")),
+        }
+    }
+
+    pub(crate) fn as_string(self) -> Result<String, SerializationError> {
+        String::from_utf8(self.as_bytes()?.to_vec())
+            .map_err(|_| SerializationError::InvalidFormat("Protobuf string is not UTF-8".to_string()))
+    }
+
+    /// Appends the values of a repeated integer field, which may be packed or not
+    pub(crate) fn push_ints(self, values: &mut Vec<i64>) -> Result<(), SerializationError> {
+        match self {
+            FieldValue::Bytes(mut bytes) => {
+                while !bytes.is_empty() {
+                    values.push(read_varint(&mut bytes)? as i64);
+                }
+                Ok(())
+            }
+            _ => {
+                values.push(self.as_int()?);
+                Ok(())
+            }
+        }
+    }
+
+    /// Appends the values of a repeated float field, which may be packed or not
+    pub(crate) fn push_floats(self, values: &mut Vec<f32>) -> Result<(), SerializationError> {
+        match self {
+            FieldValue::Bytes(bytes) => {
+                if !bytes.len().is_multiple_of(4) {
+                    return Err(SerializationError::InvalidFormat("Packed floats length is not a multiple of 4".to_string()));
+                }
+                values.extend(bytes.chunks_exact(4).map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap())));
+                Ok(())
+            }
+            _ => {
+                values.push(self.as_float()?);
+                Ok(())
+            }
+        }
+    }
+}
+
+fn wrong_type(expected: &str) -> SerializationError {
+    SerializationError::InvalidFormat(format!("Protobuf field has the wrong wire type, expected {}", expected))
+}
+
+fn truncated() -> SerializationError {
+    SerializationError::InvalidFormat("Truncated protobuf message".to_string())
+}
+
+fn read_varint(bytes: &mut &[u8]) -> Result<u64, SerializationError> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (byte, rest) = bytes.split_first().ok_or_else(truncated)?;
+        *bytes = rest;
+        value |= ((byte & 0x7F) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(SerializationError::InvalidFormat("Protobuf varint is too long".to_string()))
+}
+
+fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Result<&'a [u8], SerializationError> {
+    if bytes.len() < len {
+        return Err(truncated());
+    }
+    let (taken, rest) = bytes.split_at(len);
+    *bytes = rest;
+    Ok(taken)
+}
+
+/// Splits a message into its (field number, value) pairs, in the order they appear
+pub(crate) fn decode_fields(mut bytes: &[u8]) -> Result<Vec<(u32, FieldValue<'_>)>, SerializationError> {
+    let mut fields = vec![];
+    while !bytes.is_empty() {
+        let key = read_varint(&mut bytes)?;
+        let field = (key >> 3) as u32;
+        let value = match (key & 7) as u32 {
+            VARINT => FieldValue::Varint(read_varint(&mut bytes)?),
+            FIXED64 => FieldValue::Fixed64(u64::from_le_bytes(take(&mut bytes, 8)?.try_into().unwrap())),
+            LENGTH_DELIMITED => {
+                let len = read_varint(&mut bytes)? as usize;
+                FieldValue::Bytes(take(&mut bytes, len)?)
+            }
+            FIXED32 => FieldValue::Fixed32(u32::from_le_bytes(take(&mut bytes, 4)?.try_into().unwrap())),
+            wire_type => return Err(SerializationError::InvalidFormat(format!("Unsupported protobuf wire type {}", wire_type))),
+        };
+        fields.push((field, value));
+    }
+    Ok(fields)
+}
+
+
+#[cfg(test)]
+mod protobuf_tests {
+    use super::*;
+
+    #[test]
+    fn encode_and_decode() {
+        let mut inner = Encoder::new();
+        inner.string(1, "name");
+        let mut encoder = Encoder::new();
+        encoder.int(1, 150);
+        encoder.int(2, -1);
+        encoder.float(3, 1.5);
+        encoder.message(4, inner);
+        encoder.packed_ints(5, &[3, 270]);
+        let bytes = encoder.into_bytes();
+        // Example from the protobuf encoding guide
+        assert_eq!(&bytes[..3], &[0x08, 0x96, 0x01]);
+
+        let fields = decode_fields(&bytes).unwrap();
+        assert_eq!(fields[0], (1, FieldValue::Varint(150)));
+        assert_eq!(fields[1].1.as_int().unwrap(), -1);
+        assert_eq!(fields[2].1.as_float().unwrap(), 1.5);
+        let inner_fields = decode_fields(fields[3].1.as_bytes().unwrap()).unwrap();
+        assert_eq!(inner_fields[0].1.as_string().unwrap(), "name");
+        let mut values = vec![7];
+        fields[4].1.push_ints(&mut values).unwrap();
+        assert_eq!(values, vec![7, 3, 270]);
+        assert!(decode_fields(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
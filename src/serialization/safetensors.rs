@@ -0,0 +1,311 @@
+//! safetensors files: an 8 byte little endian header length, a JSON header mapping every tensor
+//! name to its dtype, shape and [begin, end) byte offsets in the data buffer, and the buffer of
+//! little endian values in row major order. An optional "__metadata__" entry maps strings to
+//! strings.
+//!
+//! F32, F64, F16 and BF16 tensors can be read and are converted to f32, tensors are written as
+//! F32. `SafeTensors` reads the file once and decodes tensors on request, so only the tensors
+//! which are used are converted. The values are copied since NdArray owns its data.
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::io::Write;
+use std::path::Path;
+use crate::tensor_backends::TensorBackend;
+use crate::serialization::SerializationError;
+use crate::serialization::json::Json;
+
+const METADATA_KEY: &str = "__metadata__";
+/// Upper bound on the header size, like the reference implementation, to reject garbage early
+const MAX_HEADER_LEN: u64 = 100_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SafeTensorsDtype {
+    F32,
+    F64,
+    F16,
+    BF16,
+}
+
+impl SafeTensorsDtype {
+    fn from_name(name: &str) -> Result<Self, SerializationError> {
+        match name {
+            "F32" => Ok(SafeTensorsDtype::F32),
+            "F64" => Ok(SafeTensorsDtype::F64),
+            "F16" => Ok(SafeTensorsDtype::F16),
+            "BF16" => Ok(SafeTensorsDtype::BF16),
+            _ => Err(SerializationError::InvalidFormat(format!("Unsupported dtype {}, only float tensors are supported", name))),
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            SafeTensorsDtype::F32 => 4,
+            SafeTensorsDtype::F64 => 8,
+            SafeTensorsDtype::F16 | SafeTensorsDtype::BF16 => 2,
+        }
+    }
+
+    fn decode(self, bytes: &[u8]) -> f32 {
+        match self {
+            SafeTensorsDtype::F32 => f32::from_le_bytes(bytes.try_into().unwrap()),
+            SafeTensorsDtype::F64 => f64::from_le_bytes(bytes.try_into().unwrap()) as f32,
+            SafeTensorsDtype::F16 => f16_to_f32(u16::from_le_bytes(bytes.try_into().unwrap())),
+            SafeTensorsDtype::BF16 => f32::from_bits((u16::from_le_bytes(bytes.try_into().unwrap()) as u32) << 16),
+        }
+    }
+}
+
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = ((bits >> 15) as u32) << 31;
+    let exponent = ((bits >> 10) & 0x1F) as u32;
+    let mantissa = (bits & 0x3FF) as u32;
+    let magnitude = match exponent {
+        // Subnormal, or zero: mantissa * 2^-24
+        0 => {
+            let value = mantissa as f32 * 2f32.powi(-24);
+            return if sign == 0 { value } else { -value };
+        }
+        0x1F => 0xFF << 23 | mantissa << 13,
+        _ => (exponent + 127 - 15) << 23 | mantissa << 13,
+    };
+    f32::from_bits(sign | magnitude)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TensorInfo {
+    pub dtype: SafeTensorsDtype,
+    pub shape: Vec<usize>,
+    /// Byte range in the data buffer
+    pub data_offsets: (usize, usize),
+}
+
+/// A parsed safetensors file
+#[derive(Debug)]
+pub struct SafeTensors {
+    bytes: Vec<u8>,
+    data_start: usize,
+    tensors: HashMap<String, TensorInfo>,
+    metadata: HashMap<String, String>,
+}
+
+impl SafeTensors {
+    /// Reads the whole file into memory, it is not memory mapped. Since every decoded tensor is a
+    /// copy, loading all of them needs about twice the file size at the peak.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, SerializationError> {
+        Self::from_bytes(std::fs::read(path)?)
+    }
+
+    /// Validates the header, the tensor data is only decoded by `tensor`
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, SerializationError> {
+        let invalid = |message: String| SerializationError::InvalidFormat(message);
+        let header_len = bytes.get(..8)
+            .map(|len| u64::from_le_bytes(len.try_into().unwrap()))
+            .ok_or_else(|| invalid("File too short".to_string()))?;
+        if header_len > MAX_HEADER_LEN || 8 + header_len > bytes.len() as u64 {
+            return Err(invalid(format!("Invalid header length {}", header_len)));
+        }
+        let data_start = 8 + header_len as usize;
+        let header = std::str::from_utf8(&bytes[8..data_start])
+            .map_err(|_| invalid("Header is not UTF-8".to_string()))?;
+        let entries = match Json::parse(header)? {
+            Json::Object(entries) => entries,
+            _ => return Err(invalid("Header is not a JSON object".to_string())),
+        };
+
+        let mut tensors = HashMap::new();
+        let mut metadata = HashMap::new();
+        for (name, entry) in entries {
+            if name == METADATA_KEY {
+                if let Json::Object(values) = entry {
+                    for (key, value) in values {
+                        let value = value.as_str().ok_or_else(|| invalid(format!("Metadata {} is not a string", key)))?;
+                        metadata.insert(key, value.to_string());
+                    }
+                    continue;
+                }
+                return Err(invalid("Metadata is not a JSON object".to_string()));
+            }
+            let info = Self::tensor_info(&name, &entry)?;
+            tensors.insert(name, info);
+        }
+
+        // The tensors must cover the whole buffer without gaps or overlaps
+        let mut ranges: Vec<(usize, usize, &String)> = tensors.iter()
+            .map(|(name, info)| (info.data_offsets.0, info.data_offsets.1, name))
+            .collect();
+        ranges.sort();
+        let mut expected_start = 0;
+        for (start, end, name) in ranges {
+            if start != expected_start {
+                return Err(invalid(format!("Data of {} starts at {} instead of {}", name, start, expected_start)));
+            }
+            expected_start = end;
+        }
+        if expected_start != bytes.len() - data_start {
+            return Err(invalid(format!("The tensors use {} bytes but the buffer has {}", expected_start, bytes.len() - data_start)));
+        }
+
+        Ok(SafeTensors { bytes, data_start, tensors, metadata })
+    }
+
+    fn tensor_info(name: &str, entry: &Json) -> Result<TensorInfo, SerializationError> {
+        let invalid = |field: &str| SerializationError::InvalidFormat(format!("Invalid {} of {}", field, name));
+        let dtype = SafeTensorsDtype::from_name(entry.get("dtype").and_then(Json::as_str).ok_or_else(|| invalid("dtype"))?)?;
+        let shape = entry.get("shape").and_then(Json::as_array).ok_or_else(|| invalid("shape"))?
+            .iter()
+            .map(|dim| dim.as_usize().ok_or_else(|| invalid("shape")))
+            .collect::<Result<Vec<usize>, SerializationError>>()?;
+        let offsets = entry.get("data_offsets").and_then(Json::as_array).ok_or_else(|| invalid("data_offsets"))?;
+        let data_offsets = match offsets {
+            [start, end] => (start.as_usize().ok_or_else(|| invalid("data_offsets"))?, end.as_usize().ok_or_else(|| invalid("data_offsets"))?),
+            _ => return Err(invalid("data_offsets")),
+        };
+        let byte_len = shape.iter().try_fold(dtype.size(), |len, dim| len.checked_mul(*dim));
+        if data_offsets.1 < data_offsets.0 || Some(data_offsets.1 - data_offsets.0) != byte_len {
+            return Err(SerializationError::InvalidFormat(format!("data_offsets of {} do not match its shape {:?}", name, shape)));
+        }
+        Ok(TensorInfo { dtype, shape, data_offsets })
+    }
+
+    pub fn names(&self) -> Vec<&String> {
+        let mut names: Vec<&String> = self.tensors.keys().collect();
+        names.sort();
+        names
+    }
+
+    pub fn info(&self, name: &str) -> Option<&TensorInfo> {
+        self.tensors.get(name)
+    }
+
+    pub fn metadata(&self) -> &HashMap<String, String> {
+        &self.metadata
+    }
+
+    pub fn tensor<T: TensorBackend>(&self, name: &str) -> Option<T> {
+        let info = self.tensors.get(name)?;
+        let data = &self.bytes[self.data_start + info.data_offsets.0..self.data_start + info.data_offsets.1];
+        let values: Vec<f32> = data.chunks_exact(info.dtype.size()).map(|value| info.dtype.decode(value)).collect();
+        if info.shape.is_empty() {
+            // Scalars are represented as shape [1] in the crate
+            return Some(T::from_slice(&values));
+        }
+        Some(T::from_shape_vec(&info.shape, values))
+    }
+
+    /// Every tensor, e.g. as a parameter store
+    pub fn tensors<T: TensorBackend>(&self) -> HashMap<String, T> {
+        self.tensors.keys()
+            .map(|name| (name.clone(), self.tensor(name).unwrap()))
+            .collect()
+    }
+}
+
+/// Every tensor of the file. The file is read into memory and each tensor copied out of it, so
+/// the peak memory use is about twice the file size.
+pub fn load<T: TensorBackend, P: AsRef<Path>>(path: P) -> Result<HashMap<String, T>, SerializationError> {
+    Ok(SafeTensors::open(path)?.tensors())
+}
+
+pub fn save<T: TensorBackend, P: AsRef<Path>>(path: P, tensors: &HashMap<String, T>, metadata: &HashMap<String, String>) -> Result<(), SerializationError> {
+    std::fs::write(path, serialize(tensors, metadata))?;
+    Ok(())
+}
+
+pub fn write<T: TensorBackend, W: Write>(mut writer: W, tensors: &HashMap<String, T>, metadata: &HashMap<String, String>) -> Result<(), SerializationError> {
+    writer.write_all(&serialize(tensors, metadata))?;
+    Ok(())
+}
+
+/// Tensors are written in name order as F32
+pub fn serialize<T: TensorBackend>(tensors: &HashMap<String, T>, metadata: &HashMap<String, String>) -> Vec<u8> {
+    let mut names: Vec<&String> = tensors.keys().collect();
+    names.sort();
+    let mut header = vec![];
+    if !metadata.is_empty() {
+        let mut values: Vec<(String, Json)> = metadata.iter()
+            .map(|(key, value)| (key.clone(), Json::String(value.clone())))
+            .collect();
+        values.sort_by(|a, b| a.0.cmp(&b.0));
+        header.push((METADATA_KEY.to_string(), Json::Object(values)));
+    }
+    let mut data = vec![];
+    for name in names {
+        let tensor = &tensors[name];
+        let start = data.len();
+        for value in tensor.to_vec() {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        let shape = tensor.shape().iter().map(|dim| Json::Number(*dim as f64)).collect();
+        header.push((name.clone(), Json::Object(vec![
+            ("dtype".to_string(), Json::String("F32".to_string())),
+            ("shape".to_string(), Json::Array(shape)),
+            ("data_offsets".to_string(), Json::Array(vec![Json::Number(start as f64), Json::Number(data.len() as f64)])),
+        ])));
+    }
+    let mut header = Json::Object(header).to_json_string();
+    // Padded with spaces so the data is 8 byte aligned
+    while !header.len().is_multiple_of(8) {
+        header.push(' ');
+    }
+
+    let mut bytes = vec![];
+    bytes.extend_from_slice(&(header.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(header.as_bytes());
+    bytes.extend_from_slice(&data);
+    bytes
+}
+
+
+#[cfg(test)]
+mod safetensors_tests {
+    use super::*;
+    use crate::tensor_backends::NdArray;
+
+    #[test]
+    fn round_trip() {
+        let mut store = HashMap::new();
+        store.insert("fc.weight".to_string(), NdArray::from_shape_vec(&[2, 3], vec![1., 2., 3., 4., 5., 6.]));
+        store.insert("fc.bias".to_string(), NdArray::from_slice(&[0.5, -0.5, 1.]));
+        let mut metadata = HashMap::new();
+        metadata.insert("format".to_string(), "pt".to_string());
+
+        let bytes = serialize(&store, &metadata);
+        assert_eq!(u64::from_le_bytes(bytes[..8].try_into().unwrap()) % 8, 0);
+        let file = SafeTensors::from_bytes(bytes).unwrap();
+        assert_eq!(file.names(), vec!["fc.bias", "fc.weight"]);
+        assert_eq!(file.info("fc.weight").unwrap().data_offsets, (12, 36));
+        assert_eq!(file.metadata(), &metadata);
+        assert_eq!(file.tensors::<NdArray>(), store);
+    }
+
+    #[test]
+    fn reads_half_precision() {
+        // As written by the reference implementation: no padding needed for this header
+        let header = r#"{"a":{"dtype":"F16","shape":[3],"data_offsets":[0,6]},"b":{"dtype":"BF16","shape":[],"data_offsets":[6,8]}}"#;
+        let mut bytes = (header.len() as u64).to_le_bytes().to_vec();
+        bytes.extend_from_slice(header.as_bytes());
+        // f16 1.0, -2.5 and 2^-24 (the smallest subnormal), then bf16 3.0
+        for half in &[0x3C00u16, 0xC100, 0x0001, 0x4040] {
+            bytes.extend_from_slice(&half.to_le_bytes());
+        }
+        let file = SafeTensors::from_bytes(bytes).unwrap();
+        assert_eq!(file.tensor::<NdArray>("a").unwrap(), NdArray::from_slice(&[1., -2.5, 2f32.powi(-24)]));
+        assert_eq!(file.tensor::<NdArray>("b").unwrap(), NdArray::from_slice(&[3.]));
+    }
+
+    #[test]
+    fn rejects_inconsistent_offsets() {
+        let header = r#"{"a":{"dtype":"F32","shape":[2],"data_offsets":[0,8]},"b":{"dtype":"F32","shape":[1],"data_offsets":[4,8]}}"#;
+        let mut bytes = (header.len() as u64).to_le_bytes().to_vec();
+        bytes.extend_from_slice(header.as_bytes());
+        bytes.extend_from_slice(&[0; 8]);
+        assert!(SafeTensors::from_bytes(bytes).is_err());
+
+        let header = r#"{"a":{"dtype":"F32","shape":[4294967296,4294967296,4294967296],"data_offsets":[0,4]}}"#;
+        let mut bytes = (header.len() as u64).to_le_bytes().to_vec();
+        bytes.extend_from_slice(header.as_bytes());
+        bytes.extend_from_slice(&[0; 4]);
+        assert!(SafeTensors::from_bytes(bytes).is_err());
+    }
+}
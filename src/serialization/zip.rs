@@ -0,0 +1,438 @@
+//! Just enough of the zip format for .npz archives: writing uncompressed entries and reading
+//! stored or deflated entries, including the zip64 records numpy writes.
+
+use std::convert::{TryFrom, TryInto};
+use crate::serialization::{crc32, SerializationError};
+
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const CENTRAL_HEADER: u32 = 0x0201_4b50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x0605_4b50;
+const ZIP64_END_OF_CENTRAL_DIRECTORY: u32 = 0x0606_4b50;
+const ZIP64_EXTRA_FIELD: u16 = 0x0001;
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+/// 1980-01-01, the earliest date zip can represent
+const DOS_DATE: u16 = 0x21;
+
+fn invalid(message: &str) -> SerializationError {
+    SerializationError::InvalidFormat(message.to_string())
+}
+
+/// Offset `len` bytes after `offset`, failing instead of overflowing on corrupted sizes
+fn after(offset: usize, len: usize) -> Result<usize, SerializationError> {
+    offset.checked_add(len).ok_or_else(|| invalid("Unexpected end of zip archive"))
+}
+
+fn bytes_at(bytes: &[u8], offset: usize, len: usize) -> Result<&[u8], SerializationError> {
+    bytes.get(offset..after(offset, len)?)
+        .ok_or_else(|| invalid("Unexpected end of zip archive"))
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> Result<u16, SerializationError> {
+    Ok(u16::from_le_bytes(bytes_at(bytes, offset, 2)?.try_into().unwrap()))
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> Result<u32, SerializationError> {
+    Ok(u32::from_le_bytes(bytes_at(bytes, offset, 4)?.try_into().unwrap()))
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> Result<u64, SerializationError> {
+    Ok(u64::from_le_bytes(bytes_at(bytes, offset, 8)?.try_into().unwrap()))
+}
+
+/// `value` as a 16 bit field, 0xFFFF is excluded as it marks the value as stored in zip64 records
+fn u16_field(value: usize, what: &str) -> Result<u16, SerializationError> {
+    match u16::try_from(value) {
+        Ok(field) if field != u16::MAX => Ok(field),
+        _ => Err(invalid(&format!("{} {} does not fit in a zip archive without zip64", what, value))),
+    }
+}
+
+/// `value` as a 32 bit field, 0xFFFFFFFF is excluded as it marks the value as stored in zip64
+/// records
+fn u32_field(value: usize, what: &str) -> Result<u32, SerializationError> {
+    match u32::try_from(value) {
+        Ok(field) if field != u32::MAX => Ok(field),
+        _ => Err(invalid(&format!("{} {} does not fit in a zip archive without zip64", what, value))),
+    }
+}
+
+/// Archive with the files stored without compression. zip64 records are not written, so
+/// archives of 4GiB or more, or with 65535 files or more, fail.
+pub(crate) fn write_archive(files: &[(String, Vec<u8>)]) -> Result<Vec<u8>, SerializationError> {
+    let entries = u16_field(files.len(), "File count")?;
+    let mut bytes = vec![];
+    let mut central_directory = vec![];
+    for (name, content) in files {
+        let offset = u32_field(bytes.len(), "Offset")?;
+        let size = u32_field(content.len(), "File size")?;
+        let checksum = crc32(content);
+        // Fields common to the local and central headers: version needed, flags, compression,
+        // time, date, crc, sizes and name length
+        let mut common = vec![];
+        common.extend_from_slice(&20u16.to_le_bytes());
+        common.extend_from_slice(&0u16.to_le_bytes());
+        common.extend_from_slice(&STORED.to_le_bytes());
+        common.extend_from_slice(&0u16.to_le_bytes());
+        common.extend_from_slice(&DOS_DATE.to_le_bytes());
+        common.extend_from_slice(&checksum.to_le_bytes());
+        common.extend_from_slice(&size.to_le_bytes());
+        common.extend_from_slice(&size.to_le_bytes());
+        common.extend_from_slice(&u16_field(name.len(), "File name length")?.to_le_bytes());
+
+        bytes.extend_from_slice(&LOCAL_HEADER.to_le_bytes());
+        bytes.extend_from_slice(&common);
+        bytes.extend_from_slice(&0u16.to_le_bytes());
+        bytes.extend_from_slice(name.as_bytes());
+        bytes.extend_from_slice(content);
+
+        central_directory.extend_from_slice(&CENTRAL_HEADER.to_le_bytes());
+        central_directory.extend_from_slice(&20u16.to_le_bytes());
+        central_directory.extend_from_slice(&common);
+        // Extra field and comment lengths, disk number, internal and external attributes
+        central_directory.extend_from_slice(&[0; 12]);
+        central_directory.extend_from_slice(&offset.to_le_bytes());
+        central_directory.extend_from_slice(name.as_bytes());
+    }
+    let central_directory_offset = u32_field(bytes.len(), "Offset")?;
+    let central_directory_len = u32_field(central_directory.len(), "Central directory size")?;
+    bytes.extend_from_slice(&central_directory);
+    bytes.extend_from_slice(&END_OF_CENTRAL_DIRECTORY.to_le_bytes());
+    bytes.extend_from_slice(&[0; 4]);
+    bytes.extend_from_slice(&entries.to_le_bytes());
+    bytes.extend_from_slice(&entries.to_le_bytes());
+    bytes.extend_from_slice(&central_directory_len.to_le_bytes());
+    bytes.extend_from_slice(&central_directory_offset.to_le_bytes());
+    bytes.extend_from_slice(&0u16.to_le_bytes());
+    Ok(bytes)
+}
+
+/// Names and decompressed contents of the files in the archive
+pub(crate) fn read_archive(bytes: &[u8]) -> Result<Vec<(String, Vec<u8>)>, SerializationError> {
+    // The end of central directory record is at the end, followed by a comment of up to 64KiB
+    let end = (0..bytes.len().saturating_sub(21)).rev()
+        .take(u16::MAX as usize + 1)
+        .find(|offset| u32_at(bytes, *offset).ok() == Some(END_OF_CENTRAL_DIRECTORY))
+        .ok_or_else(|| invalid("Not a zip archive"))?;
+    let mut entries = u16_at(bytes, end + 10)? as u64;
+    let mut offset = u32_at(bytes, end + 16)? as u64;
+    if entries == u16::MAX as u64 || offset == u32::MAX as u64 {
+        // The zip64 record is pointed to by a locator right before the end record
+        let locator = end.checked_sub(12).ok_or_else(|| invalid("Missing zip64 end of central directory locator"))?;
+        let zip64_end = bytes_at(bytes, u64_at(bytes, locator)? as usize, 56)?;
+        if u32_at(zip64_end, 0)? != ZIP64_END_OF_CENTRAL_DIRECTORY {
+            return Err(invalid("Invalid zip64 end of central directory"));
+        }
+        entries = u64_at(zip64_end, 32)?;
+        offset = u64_at(zip64_end, 48)?;
+    }
+
+    let mut files = vec![];
+    let mut offset = offset as usize;
+    for _entry in 0..entries {
+        let header = bytes_at(bytes, offset, 46)?;
+        if u32_at(header, 0)? != CENTRAL_HEADER {
+            return Err(invalid("Invalid zip central directory"));
+        }
+        let compression = u16_at(header, 10)?;
+        let checksum = u32_at(header, 16)?;
+        let mut compressed_size = u32_at(header, 20)? as u64;
+        let mut size = u32_at(header, 24)? as u64;
+        let name_len = u16_at(header, 28)? as usize;
+        let extra_len = u16_at(header, 30)? as usize;
+        let comment_len = u16_at(header, 32)? as usize;
+        let mut local_offset = u32_at(header, 42)? as u64;
+        let name_start = offset + 46;
+        let name = String::from_utf8(bytes_at(bytes, name_start, name_len)?.to_vec())
+            .map_err(|_| invalid("Invalid zip file name"))?;
+
+        // The zip64 extra field has the 64 bit values of the fields set to 0xFFFFFFFF, in order
+        let mut extra = after(name_start, name_len)?;
+        let extra_end = after(extra, extra_len)?;
+        while after(extra, 4)? <= extra_end {
+            let id = u16_at(bytes, extra)?;
+            let len = u16_at(bytes, extra + 2)? as usize;
+            if id == ZIP64_EXTRA_FIELD {
+                let mut field = after(extra, 4)?;
+                for value in [&mut size, &mut compressed_size, &mut local_offset].iter_mut() {
+                    if **value == u32::MAX as u64 {
+                        **value = u64_at(bytes, field)?;
+                        field = after(field, 8)?;
+                    }
+                }
+            }
+            extra = after(extra, 4 + len)?;
+        }
+
+        let local_offset = local_offset as usize;
+        let local_header = bytes_at(bytes, local_offset, 30)?;
+        if u32_at(local_header, 0)? != LOCAL_HEADER {
+            return Err(invalid("Invalid zip local header"));
+        }
+        let local_name_len = u16_at(local_header, 26)? as usize;
+        let local_extra_len = u16_at(local_header, 28)? as usize;
+        let data_start = local_offset + 30 + local_name_len + local_extra_len;
+        let data = bytes_at(bytes, data_start, compressed_size as usize)?;
+        let content = match compression {
+            STORED => data.to_vec(),
+            DEFLATED => inflate(data)?,
+            other => return Err(SerializationError::InvalidFormat(format!("Unsupported zip compression method {} for {}", other, name))),
+        };
+        if content.len() as u64 != size || crc32(&content) != checksum {
+            return Err(SerializationError::InvalidFormat(format!("Corrupted zip entry {}", name)));
+        }
+        files.push((name, content));
+        offset = after(extra_end, comment_len)?;
+    }
+    Ok(files)
+}
+
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    buffer: u32,
+    buffered: u32,
+}
+
+impl <'a> BitReader<'a> {
+    /// Next n bits, least significant first
+    fn bits(&mut self, n: u32) -> Result<u32, SerializationError> {
+        while self.buffered < n {
+            let byte = *self.bytes.get(self.position).ok_or_else(|| invalid("Unexpected end of deflate data"))?;
+            self.position += 1;
+            self.buffer |= (byte as u32) << self.buffered;
+            self.buffered += 8;
+        }
+        let value = self.buffer & ((1u64 << n) - 1) as u32;
+        self.buffer >>= n;
+        self.buffered -= n;
+        Ok(value)
+    }
+
+    fn align_to_byte(&mut self) {
+        self.buffer = 0;
+        self.buffered = 0;
+    }
+}
+
+/// Canonical Huffman code as the number of codes of each length and the symbols ordered by code
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0u16; 16];
+        for length in lengths {
+            counts[*length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut symbols = vec![];
+        for length in 1..16 {
+            for (symbol, symbol_length) in lengths.iter().enumerate() {
+                if *symbol_length as usize == length {
+                    symbols.push(symbol as u16);
+                }
+            }
+        }
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, SerializationError> {
+        // Codes of each length are consecutive numbers starting at `first`
+        let mut code = 0i32;
+        let mut first = 0i32;
+        let mut index = 0i32;
+        for length in 1..16 {
+            code |= reader.bits(1)? as i32;
+            let count = self.counts[length] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid("Invalid Huffman code in deflate data"))
+    }
+}
+
+const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASE: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+const DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+/// Order in which the code length code lengths are stored
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+/// Decompresses raw deflate data (RFC 1951)
+pub(crate) fn inflate(bytes: &[u8]) -> Result<Vec<u8>, SerializationError> {
+    let mut reader = BitReader { bytes, position: 0, buffer: 0, buffered: 0 };
+    let mut output = vec![];
+    loop {
+        let last_block = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => {
+                reader.align_to_byte();
+                let len = u16_at(bytes, reader.position)?;
+                let complement = u16_at(bytes, reader.position + 2)?;
+                if len != !complement {
+                    return Err(invalid("Invalid stored block length in deflate data"));
+                }
+                let start = reader.position + 4;
+                let block = bytes.get(start..start + len as usize)
+                    .ok_or_else(|| invalid("Unexpected end of deflate data"))?;
+                output.extend_from_slice(block);
+                reader.position = start + len as usize;
+            }
+            1 => {
+                let mut lengths = [8u8; 288];
+                lengths[144..256].iter_mut().for_each(|length| *length = 9);
+                lengths[256..280].iter_mut().for_each(|length| *length = 7);
+                inflate_block(&mut reader, &mut output, &Huffman::new(&lengths), &Huffman::new(&[5; 30]))?;
+            }
+            2 => {
+                let (literals, distances) = read_dynamic_codes(&mut reader)?;
+                inflate_block(&mut reader, &mut output, &literals, &distances)?;
+            }
+            _ => return Err(invalid("Invalid block type in deflate data")),
+        }
+        if last_block {
+            return Ok(output);
+        }
+    }
+}
+
+fn read_dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman), SerializationError> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+    let mut code_lengths = [0u8; 19];
+    for symbol in CODE_LENGTH_ORDER.iter().take(code_length_count) {
+        code_lengths[*symbol] = reader.bits(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_lengths);
+
+    let mut lengths = vec![];
+    while lengths.len() < literal_count + distance_count {
+        let symbol = code_lengths.decode(reader)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths.last().ok_or_else(|| invalid("Repeat without a previous length in deflate data"))?;
+                (previous, 3 + reader.bits(2)?)
+            }
+            17 => (0, 3 + reader.bits(3)?),
+            _ => (0, 11 + reader.bits(7)?),
+        };
+        lengths.extend(std::iter::repeat_n(value, repeat as usize));
+    }
+    if lengths.len() > literal_count + distance_count {
+        return Err(invalid("Too many code lengths in deflate data"));
+    }
+    let (literal_lengths, distance_lengths) = lengths.split_at(literal_count);
+    Ok((Huffman::new(literal_lengths), Huffman::new(distance_lengths)))
+}
+
+fn inflate_block(reader: &mut BitReader, output: &mut Vec<u8>, literals: &Huffman, distances: &Huffman) -> Result<(), SerializationError> {
+    loop {
+        let symbol = literals.decode(reader)? as usize;
+        match symbol {
+            0..=255 => output.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let index = symbol - 257;
+                if index >= LENGTH_BASE.len() {
+                    return Err(invalid("Invalid length symbol in deflate data"));
+                }
+                let len = LENGTH_BASE[index] as usize + reader.bits(LENGTH_EXTRA[index] as u32)? as usize;
+                let index = distances.decode(reader)? as usize;
+                if index >= DISTANCE_BASE.len() {
+                    return Err(invalid("Invalid distance symbol in deflate data"));
+                }
+                let distance = DISTANCE_BASE[index] as usize + reader.bits(DISTANCE_EXTRA[index] as u32)? as usize;
+                if distance > output.len() {
+                    return Err(invalid("Distance too far back in deflate data"));
+                }
+                // Copied one byte at a time since the source may overlap what is being written
+                let start = output.len() - distance;
+                for offset in 0..len {
+                    output.push(output[start + offset]);
+                }
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod zip_tests {
+    use super::*;
+
+    #[test]
+    fn crc32_reference() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn stored_round_trip() {
+        let files = vec![("a.npy".to_string(), b"first".to_vec()), ("b.npy".to_string(), vec![])];
+        assert_eq!(read_archive(&write_archive(&files).unwrap()).unwrap(), files);
+    }
+
+    #[test]
+    fn too_large_for_zip32_is_rejected() {
+        let long_name = vec![("a".repeat(u16::MAX as usize), vec![])];
+        let too_many_files: Vec<(String, Vec<u8>)> = (0..u16::MAX).map(|i| (i.to_string(), vec![])).collect();
+        for files in &[long_name, too_many_files] {
+            match write_archive(files) {
+                Err(SerializationError::InvalidFormat(_)) => {}
+                other => panic!("Expected an invalid format error, got {:?}", other.map(|bytes| bytes.len())),
+            }
+        }
+    }
+
+    #[test]
+    fn corrupted_offsets_are_rejected() {
+        let end_record = |entries: u16| {
+            let mut record = END_OF_CENTRAL_DIRECTORY.to_le_bytes().to_vec();
+            record.extend_from_slice(&[0; 4]);
+            record.extend_from_slice(&entries.to_le_bytes());
+            record.extend_from_slice(&entries.to_le_bytes());
+            record.extend_from_slice(&[0; 4]);
+            record.extend_from_slice(&u32::MAX.to_le_bytes());
+            record.extend_from_slice(&0u16.to_le_bytes());
+            record
+        };
+        // zip64 markers with no room for the locator, a locator pointing past the end and a
+        // local header offset past the end
+        let mut locator_pointing_past_the_end = u64::MAX.to_le_bytes().to_vec();
+        locator_pointing_past_the_end.extend_from_slice(&[0; 4]);
+        locator_pointing_past_the_end.extend_from_slice(&end_record(u16::MAX));
+        let mut huge_local_offset = write_archive(&[("a.npy".to_string(), b"first".to_vec())]).unwrap();
+        let central_directory = huge_local_offset.len() - 22 - 51;
+        huge_local_offset[central_directory + 42..central_directory + 46].copy_from_slice(&u32::MAX.to_le_bytes());
+        for archive in &[end_record(u16::MAX), locator_pointing_past_the_end, huge_local_offset] {
+            match read_archive(archive) {
+                Err(SerializationError::InvalidFormat(_)) => {}
+                other => panic!("Expected an invalid format error, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn inflate_dynamic_huffman() {
+        // Raw deflate of " ".join(str(i * 7 % 1000) for i in range(60)) by zlib at level 9
+        let compressed = [
+        0x0d, 0xce, 0x37, 0x01, 0x00, 0x30, 0x0c, 0xc4, 0x40, 0x2a, 0x82, 0xe0, 0x16, 0x17, 0xfe, 0xc4,
+        0xf2, 0x83, 0xb6, 0x73, 0x31, 0x06, 0x2f, 0xc2, 0x89, 0x25, 0x1f, 0x15, 0xd4, 0xf1, 0x9a, 0x4e,
+        0xc6, 0x98, 0x61, 0x8b, 0x73, 0x6e, 0x71, 0x7b, 0xb8, 0x87, 0x3a, 0x3c, 0x1a, 0xcf, 0xd4, 0xa8,
+        0x29, 0xad, 0x78, 0x85, 0xb7, 0x2b, 0xb9, 0x91, 0x5b, 0xb9, 0x95, 0xbb, 0x26, 0x2c, 0xb5, 0xdf,
+        0xd4, 0x10, 0xa1, 0x5b, 0xa9, 0x63, 0xb9, 0x44, 0x3d, 0xe2, 0x85, 0x3a, 0xa2, 0xe5, 0x46, 0x6e,
+        0xe5, 0x56, 0xee, 0x8a, 0x34, 0x57, 0xfa, 0xca, 0x1f, 0x19, 0xa1, 0x8e, 0xcc, 0x26, 0x2b, 0xf5,
+        0xa9, 0xa9, 0x21, 0x5b, 0x6e, 0xe4, 0x46, 0x6e, 0xe5, 0x4e, 0xee, 0x8e, 0xb2, 0xa6, 0x3c, 0x3f,
+        ];
+        let expected: Vec<String> = (0..60).map(|i| (i * 7 % 1000).to_string()).collect();
+        assert_eq!(inflate(&compressed).unwrap(), expected.join(" ").into_bytes());
+    }
+}
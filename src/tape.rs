@@ -1,4 +1,5 @@
-use std::cell::RefCell;
+use std::cell::{Ref, RefCell};
+use std::collections::HashMap;
 use std::fmt::{Error, Formatter};
 use crate::tensor_backends::TensorBackend;
 
@@ -12,6 +13,8 @@ pub struct ComputationRecord<T: TensorBackend> {
     /// Then we calculate its parents gradients using the data stored in its node, then for each
     /// of those parents we calculate their gradient and so on.
     ops_data: RefCell<Vec<OpData<T>>>,
+    /// How many names were generated for each prefix, see `unique_name`
+    name_counters: RefCell<HashMap<String, usize>>,
 }
 
 #[derive(Debug)]
@@ -20,6 +23,16 @@ pub struct OpData<T: TensorBackend> {
     /// the Op which created this Tensor. If the Var was user create, this is empty.
     pub operands_grad_blueprint: Vec<OperandGradBlueprint<T>>,
     pub op_name: String,
+    /// Parameters of the Op which are not Tensors, like the axis of a reduction. The GradFns
+    /// capture what they need, these are for tools which walk the record like the ONNX exporter.
+    pub attributes: Vec<(String, OpAttribute)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OpAttribute {
+    Float(f32),
+    Int(i64),
+    Ints(Vec<i64>),
 }
 
 impl <T: TensorBackend> OpData<T> {
@@ -27,6 +40,7 @@ impl <T: TensorBackend> OpData<T> {
         Self {
             operands_grad_blueprint: vec![],
             op_name: "NoOp".to_string(),
+            attributes: vec![],
         }
     }
 
@@ -34,11 +48,24 @@ impl <T: TensorBackend> OpData<T> {
         Self {
             operands_grad_blueprint: blueprints,
             op_name,
+            attributes: vec![],
         }
     }
+
+    pub fn with_attribute(mut self, name: &str, value: OpAttribute) -> Self {
+        self.attributes.push((name.to_string(), value));
+        self
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&OpAttribute> {
+        self.attributes.iter()
+            .find(|(attribute_name, _value)| attribute_name == name)
+            .map(|(_name, value)| value)
+    }
 }
 
 /// First argument is the child_grad, second is the current "parent" grad
+#[allow(clippy::type_complexity)]
 pub struct GradFn<T: TensorBackend>(pub Box<dyn Fn(T, &mut T)>);
 
 impl <T: TensorBackend> std::fmt::Debug for GradFn<T> {
@@ -61,6 +88,13 @@ pub struct OperandGradBlueprint<T: TensorBackend> {
     grad_fn: GradFn<T>,
 }
 
+impl <T: TensorBackend> OperandGradBlueprint<T> {
+    /// Index in the tape of the operand this blueprint calculates the gradient of
+    pub fn operand_tape_index(&self) -> usize {
+        self.operand_tape_index
+    }
+}
+
 #[derive(Debug)]
 pub struct TrackedTensor<'t, T: TensorBackend> {
     /// Reference to the Tape which stores the information needed to calculate the gradients
@@ -72,13 +106,31 @@ pub struct TrackedTensor<'t, T: TensorBackend> {
     data: T,
 }
 
+impl <T: TensorBackend> Default for ComputationRecord<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl <T: TensorBackend> ComputationRecord<T> {
     pub fn new() -> Self {
         ComputationRecord {
             ops_data: RefCell::new(Vec::new()),
+            name_counters: RefCell::new(HashMap::new()),
         }
     }
 
+    /// Generates a name like "prefix_0", "prefix_1", ... counting per prefix. Layers created in
+    /// the same order on every new record get the same names, so they find their parameters
+    /// in the store again.
+    pub fn unique_name(&self, prefix: &str) -> String {
+        let mut counters = self.name_counters.borrow_mut();
+        let counter = counters.entry(prefix.to_string()).or_insert(0);
+        let name = format!("{}_{}", prefix, counter);
+        *counter += 1;
+        name
+    }
+
     //noinspection RsNeedlessLifetimes
     pub fn tensor_from_slice<'t>(&'t self, value: &[f32]) -> TrackedTensor<'t, T> {
         TrackedTensor {
@@ -126,7 +178,7 @@ impl <T: TensorBackend> ComputationRecord<T> {
     }
 
 
-    pub fn tensor_from_op_result_and_data(&self, op_result: T, op_data: OpData<T>) -> TrackedTensor<T>{
+    pub fn tensor_from_op_result_and_data(&self, op_result: T, op_data: OpData<T>) -> TrackedTensor<'_, T>{
         TrackedTensor {
             tape: self,
             data: op_result,
@@ -138,6 +190,15 @@ impl <T: TensorBackend> ComputationRecord<T> {
         self.ops_data.borrow().len()
     }
 
+    /// The recorded Ops, indexed by the `parent_op_index` of the Tensors they created
+    pub fn ops_data(&self) -> Ref<'_, Vec<OpData<T>>> {
+        self.ops_data.borrow()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops_data.borrow().is_empty()
+    }
+
     pub fn push_op(&self, op_data: OpData<T>) -> usize {
         let mut ops_data = self.ops_data.borrow_mut();
         let len = ops_data.len();
@@ -159,6 +220,8 @@ impl <T: TensorBackend> ComputationRecord<T> {
 #[derive(Debug)]
 pub struct Grad<T: TensorBackend> {
     all_grads: Vec<T>,
+    /// Whether the output depends on the Var at each tape index, only those have a gradient
+    reached: Vec<bool>,
 }
 
 impl <T: TensorBackend> Grad<T> {
@@ -171,6 +234,61 @@ impl <T: TensorBackend> Grad<T> {
             Some(grad) => var.tape.tensor_from_value(grad.clone()),
         }
     }
+
+    /// Gradient value of var without recording it in the tape.
+    /// None if var does not influence the output the gradient was calculated for.
+    pub fn wrt_data(&self, var: &TrackedTensor<'_, T>) -> Option<&T> {
+        match self.all_grads.get(var.parent_op_index) {
+            None => {
+                panic!("This var is not part of the computational graph. Maybe it was created using another Tape");
+            }
+            Some(_grad) if !self.reached[var.parent_op_index] => None,
+            Some(grad) => Some(grad),
+        }
+    }
+
+    /// Tape indices of the params which have a gradient, each only once
+    fn reached_indices(&self, params: &[&TrackedTensor<'_, T>]) -> Vec<usize> {
+        let mut indices: Vec<usize> = params.iter()
+            .filter(|param| self.wrt_data(param).is_some())
+            .map(|param| param.parent_op_index)
+            .collect();
+        indices.sort_unstable();
+        indices.dedup();
+        indices
+    }
+
+    /// L2 norm of the gradients of all params taken together as a single vector
+    pub fn global_norm(&self, params: &[&TrackedTensor<'_, T>]) -> f32 {
+        self.reached_indices(params).into_iter()
+            .map(|index| self.all_grads[index].square().sum())
+            .sum::<f32>()
+            .sqrt()
+    }
+
+    /// Scales the gradients of params so their global norm is at most max_norm.
+    /// Returns the global norm before clipping.
+    pub fn clip_grad_norm(&mut self, params: &[&TrackedTensor<'_, T>], max_norm: f32) -> f32 {
+        let norm = self.global_norm(params);
+        // Small epsilon to avoid dividing by zero, as PyTorch does
+        let scale = max_norm / (norm + 1e-6);
+        if scale < 1. {
+            for index in self.reached_indices(params) {
+                self.all_grads[index] = self.all_grads[index].mul_scalar(scale);
+            }
+        }
+        norm
+    }
+
+    /// Clamps every gradient value of params to [-clip_value, clip_value].
+    /// Returns the global norm before clipping.
+    pub fn clip_grad_value(&mut self, params: &[&TrackedTensor<'_, T>], clip_value: f32) -> f32 {
+        let norm = self.global_norm(params);
+        for index in self.reached_indices(params) {
+            self.all_grads[index] = self.all_grads[index].clamp(-clip_value, clip_value);
+        }
+        norm
+    }
 }
 
 impl<'t, T: TensorBackend> TrackedTensor<'t, T> {
@@ -199,23 +317,29 @@ impl<'t, T: TensorBackend> TrackedTensor<'t, T> {
         let one = T::from_slice(&[1.]);
         all_grads[self.parent_op_index] = one;
 
-        let mut tape_indices_to_visit = vec![self.parent_op_index];
+        // Tracks which Vars are reachable backwards from self and so have a gradient
+        let mut reached = vec![false; tape_len];
+        reached[self.parent_op_index] = true;
 
-        while let Some(current_tape_index) = tape_indices_to_visit.pop() {
+        // Operands are always recorded before the Op using them, so walking the tape backwards
+        // from self visits each Var only after every Var using it has contributed to its gradient
+        for current_tape_index in (0..=self.parent_op_index).rev() {
+            if !reached[current_tape_index] {
+                continue;
+            }
             // Get the data to calculate current Var parents gradients
             let current_op_data = &ops_data[current_tape_index];
             // Get current Var gradient
             let current_tensor_grad = all_grads[current_tape_index].clone();
             // For each parent of this Var
             for operand in &current_op_data.operands_grad_blueprint {
-                // Make sure to visit parents later
-                tape_indices_to_visit.push(operand.operand_tape_index);
                 // Get the function to calculate the gradient
                 let grad_fn = &operand.grad_fn;
                 // Get the current gradient
                 let curr_grad = &mut all_grads[operand.operand_tape_index];
-                // If empty, initialize it
-                if curr_grad.is_empty() {
+                // If not reached yet, initialize it
+                if !reached[operand.operand_tape_index] {
+                    reached[operand.operand_tape_index] = true;
                     let new_grad_shape = operand.grad_shape.clone();
                     *curr_grad = T::zeros(new_grad_shape.as_slice());
                 }
@@ -225,7 +349,7 @@ impl<'t, T: TensorBackend> TrackedTensor<'t, T> {
             }
         }
 
-        Grad { all_grads }
+        Grad { all_grads, reached }
     }
 
     /// Returns a blueprint to calculate this Var's gradient using the provided grad_fn
@@ -240,3 +364,74 @@ impl<'t, T: TensorBackend> TrackedTensor<'t, T> {
 
 }
 
+
+#[cfg(test)]
+mod tape_tests {
+    use crate::ops::{add, mul, relu, sum};
+    use crate::tape::ComputationRecord;
+    use crate::tensor_backends::{NdArray, TensorBackend};
+
+    #[test]
+    fn diamond_graph_grad() {
+        // x is an intermediate result used by both a and b, so its gradient must be
+        // propagated to w only once, after both uses have contributed to it
+        let t: ComputationRecord<NdArray> = ComputationRecord::new();
+        let w = t.tensor_from_slice(&[1., 2.]);
+        let x = relu(&w);
+        let a = add(&x, &t.tensor_from_slice(&[1., 1.]));
+        let b = mul(&x, &t.tensor_from_slice(&[3., 3.]));
+        let out = sum(&add(&a, &b));
+        let grad = out.grad().wrt(&w);
+        assert_eq!(grad.data(), &NdArray::from_slice(&[4., 4.]));
+    }
+}
+
+#[cfg(test)]
+mod grad_clipping_tests {
+    use crate::tape::ComputationRecord;
+    use crate::tensor_backends::{NdArray, TensorBackend};
+    use crate::ops::*;
+
+    #[test]
+    fn clip_grad_norm_test() {
+        let rec: ComputationRecord<NdArray> = ComputationRecord::new();
+        let a = rec.tensor_from_slice(&[3., 0.]);
+        let b = rec.tensor_from_slice(&[4.]);
+        let unused = rec.tensor_from_slice(&[100.]);
+        // Gradients are 2a = [6, 0] and 2b = [8], global norm 10
+        let loss = add(&sum(&mul(&a, &a)), &sum(&mul(&b, &b)));
+        let mut grad = loss.grad();
+        let params = [&a, &b, &unused, &a];
+        assert!((grad.global_norm(&params) - 10.).abs() < 1e-5);
+
+        // Below the maximum nothing changes
+        assert!((grad.clip_grad_norm(&params, 20.) - 10.).abs() < 1e-5);
+        assert_eq!(grad.wrt_data(&b).unwrap(), &NdArray::from_slice(&[8.]));
+
+        assert!((grad.clip_grad_norm(&params, 5.) - 10.).abs() < 1e-5);
+        assert!((grad.global_norm(&params) - 5.).abs() < 1e-5);
+        assert!((grad.wrt_data(&a).unwrap().index(&[0]) - 3.).abs() < 1e-5);
+        assert!(grad.wrt_data(&unused).is_none());
+    }
+
+    #[test]
+    fn clip_grad_value_test() {
+        let rec: ComputationRecord<NdArray> = ComputationRecord::new();
+        let a = rec.tensor_from_slice(&[3., -0.5]);
+        let loss = sum(&mul_scalar(&a, 2.));
+        let mut grad = loss.grad();
+        let norm = grad.clip_grad_value(&[&a], 1.5);
+        assert!((norm - 8f32.sqrt()).abs() < 1e-5);
+        assert_eq!(grad.wrt_data(&a).unwrap(), &NdArray::from_slice(&[1.5, 1.5]));
+    }
+
+    #[test]
+    fn rank_0_params_are_clipped() {
+        let rec: ComputationRecord<NdArray> = ComputationRecord::new();
+        let a = rec.tensor_from_value(NdArray::zeros(&[]));
+        let loss = sum(&mul_scalar(&a, 3.));
+        let mut grad = loss.grad();
+        assert!(grad.wrt_data(&a).is_some());
+        assert!((grad.clip_grad_norm(&[&a], 1.) - 3.).abs() < 1e-5);
+    }
+}
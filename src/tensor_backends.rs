@@ -5,24 +5,53 @@ mod ndarray_backend;
 use ndarray::prelude::IxDyn;
 
 pub mod indexing;
+pub mod slicing;
+use slicing::SliceElem;
+use crate::random::{self, Generator};
 
 pub trait TensorBackend: Sized + Clone + Debug + 'static{
     /* Constructors, there are proxies to these in the Tape */
     fn from_slice(slice: &[f32]) -> Self;
+    /// Values in row major (C) order, panics if their number does not match the shape
+    fn from_shape_vec(shape: &[usize], values: Vec<f32>) -> Self;
     fn zeros(shape: &[usize]) -> Self;
-    fn rand(shape: &[usize]) -> Self;
+    /// Uniform in [0, 1) from the global generator, see `random::manual_seed`
+    fn rand(shape: &[usize]) -> Self {
+        random::with_global_generator(|rng| Self::rand_uniform(shape, 0., 1., rng))
+    }
     fn zeros_like(other: &Self) -> Self;
     fn empty() -> Self;
 
+    /* Random constructors, deterministic for a given Generator state */
+    /// Uniform in [low, high)
+    fn rand_uniform(shape: &[usize], low: f32, high: f32, rng: &mut Generator) -> Self;
+    fn rand_normal(shape: &[usize], mean: f32, std: f32, rng: &mut Generator) -> Self;
+    /// Normal values conditioned on falling in [low, high], which may be far in the tails
+    fn rand_truncated_normal(shape: &[usize], mean: f32, std: f32, low: f32, high: f32, rng: &mut Generator) -> Self;
+    /// 1 with probability `p`, 0 otherwise
+    fn rand_bernoulli(shape: &[usize], p: f32, rng: &mut Generator) -> Self;
+    /// The numbers 0..n in random order, shape [n]. n is at most 2^24, above which f32 can not
+    /// represent every index.
+    fn rand_permutation(n: usize, rng: &mut Generator) -> Self;
+
     /* Helper functions */
     fn is_empty(&self) -> bool;
     fn fill_with(&mut self, value: f32);
+    /// Values in row major (C) order
+    fn to_vec(&self) -> Vec<f32>;
 
     /* Shape Changing functions */
     /// Transposes dim 0 and 1, panics if they don't exist
     fn t(&mut self);
     fn reshape(&mut self, shape: &[usize]);
     fn shape(&self) -> &[usize];
+    /// Reorders the axes: axis i of the result is axis axes[i] of self
+    fn permute(&self, axes: &[usize]) -> Self;
+    /// Swaps two axes
+    fn transpose(&self, axis_a: usize, axis_b: usize) -> Self;
+    /// Broadcasts to the given shape following NumPy rules, panics if the shapes are not
+    /// compatible
+    fn broadcast_to(&self, shape: &[usize]) -> Self;
 
 
 
@@ -30,22 +59,77 @@ pub trait TensorBackend: Sized + Clone + Debug + 'static{
     fn add(&self, rhs: &Self) -> Self;
     fn sub(&self, rhs: &Self) -> Self;
     fn mul(&self, rhs: &Self) -> Self;
+    fn div(&self, rhs: &Self) -> Self;
 
     /* Basic Ops Scalar */
     fn add_scalar(&self, rhs: f32) -> Self;
     fn sub_scalar(&self, rhs: f32) -> Self;
     fn mul_scalar(&self, rhs: f32) -> Self;
+    fn div_scalar(&self, rhs: f32) -> Self;
 
     /// sums all elements
     fn sum(&self) -> f32;
-    fn matmul2d(&self, rhs: &Self) -> Self;
+    /// Matrix product with NumPy's matmul semantics: rank 1 operands are promoted to matrices
+    /// and leading batch axes are broadcast
+    fn matmul(&self, rhs: &Self) -> Self;
+
+    /* Reductions along an axis */
+    // With keepdim the reduced axis is kept with length 1, otherwise it is removed. Reducing the
+    // only axis of a rank 1 Tensor always gives shape [1], the same as `sum`.
+    fn sum_axis(&self, axis: usize, keepdim: bool) -> Self;
+    fn mean_axis(&self, axis: usize, keepdim: bool) -> Self;
+    fn max_axis(&self, axis: usize, keepdim: bool) -> Self;
+    fn min_axis(&self, axis: usize, keepdim: bool) -> Self;
+    fn prod_axis(&self, axis: usize, keepdim: bool) -> Self;
+    /// Index of the first maximum along the axis, stored as f32
+    fn argmax_axis(&self, axis: usize, keepdim: bool) -> Self;
+
+    /* Element wise math */
+    fn exp(&self) -> Self;
+    /// Natural logarithm
+    fn log(&self) -> Self;
+    fn sqrt(&self) -> Self;
+    fn pow_scalar(&self, exponent: f32) -> Self;
+    fn abs(&self) -> Self;
+    fn neg(&self) -> Self;
+    fn sin(&self) -> Self;
+    fn cos(&self) -> Self;
+    fn tanh(&self) -> Self;
+    /// 1 / (1 + exp(-x)), computed without overflowing for large |x|
+    fn sigmoid(&self) -> Self;
+    /// ln(1 + exp(x)), computed without overflowing for large x
+    fn softplus(&self) -> Self;
+    fn clamp(&self, min: f32, max: f32) -> Self;
+    fn reciprocal(&self) -> Self;
+    fn square(&self) -> Self;
 
     // Operating on all elements
     fn map_inplace<F>(&mut self, f: F) where F: FnMut(&mut f32);
+    /// Combines the elements of two Tensors of the same shape pairwise
+    fn zip_map<F>(&self, rhs: &Self, f: F) -> Self where F: FnMut(f32, f32) -> f32;
+
+    /* Joining and splitting */
+    /// Joins Tensors along an existing axis, all other dimensions must match
+    fn cat(tensors: &[&Self], axis: usize) -> Self;
+    /// Joins Tensors of the same shape along a new axis inserted at `axis`
+    fn stack(tensors: &[&Self], axis: usize) -> Self;
+    /// Splits along `axis` into pieces of the given sizes, which must add up to the axis length
+    fn split(&self, sizes: &[usize], axis: usize) -> Vec<Self>;
+    /// Splits along `axis` into at most `chunks` pieces of equal size, the last one may be smaller
+    fn chunk(&self, chunks: usize, axis: usize) -> Vec<Self> {
+        self.split(&slicing::chunk_sizes(self.shape()[axis], chunks), axis)
+    }
+
+    /* Slicing */
+    /// Copies the sub Tensor described by `info`, see `SliceElem`
+    fn slice(&self, info: &[SliceElem]) -> Self;
+    /// Adds `rhs` to the sub Tensor described by `info`, `rhs` must have the shape `slice` would
+    /// return for the same `info`
+    fn slice_add_assign(&mut self, info: &[SliceElem], rhs: &Self);
 
     fn index(&self, index: &[usize]) -> f32;
     fn _index_mut(&mut self, index: &[usize]) -> &mut f32;
 }
 
 #[derive(Debug, Clone, PartialEq)]
-pub struct NdArray(Array<f32, IxDyn>);
\ No newline at end of file
+pub struct NdArray(Array<f32, IxDyn>);
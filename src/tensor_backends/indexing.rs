@@ -1,76 +1,81 @@
+/// Iterates over every index of a shape in row major order: for rank 3 this looks like
+/// [0, 0, 0], [0, 0, 1], [0, 1, 0] ... and so on.
+/// A shape with a zero sized dimension has no indices, while the scalar shape [] has a single
+/// one: the empty index.
 #[derive(Debug)]
 pub struct Indexer{
     original_shape: Vec<usize>,
-    current_index: Vec<usize>,
-    max_index: Vec<usize>,
-    dims: usize,
+    /// Next index to be returned, None once all indices were visited
+    next_index: Option<Vec<usize>>,
 }
 
 impl From<&[usize]> for Indexer{
     fn from(shape: &[usize]) -> Self {
-        assert!(shape.len() > 0, "Indexer source needs at least one dimension");
-        let source_vec = shape.to_vec();
-        let dims = source_vec.len();
-        let mut zeros = vec![];
-        for _dim in 0..dims{
-            zeros.push(0);
-        }
-        let mut max_index = vec![];
-        for dim in 0..dims{
-            max_index.push(shape[dim] - 1);
-        }
-
+        let next_index = if shape.contains(&0) {
+            None
+        } else {
+            Some(vec![0; shape.len()])
+        };
         Indexer{
-            original_shape: source_vec,
-            current_index: vec![],
-            dims,
-            max_index
+            original_shape: shape.to_vec(),
+            next_index,
         }
     }
 }
 
-// We may need GAT to implement iterator here due to lifetime in the result
-impl Indexer{
-    pub fn next(&mut self) -> Option<&[usize]>{
-        if self.current_index == self.max_index{
-            return None
-        }else if self.current_index.is_empty(){
-            for dim in 0..self.dims{
-                self.current_index.push(0);
-            }
-            return Some(self.current_index.as_slice());
-        }else{
-            for dim in (0..self.dims).rev(){
-                if self.current_index[dim] < self.original_shape[dim] - 1{
-                    self.current_index[dim] += 1;
-                    break;
-                }else{
-                    self.current_index[dim] = 0;
-                }
+impl Iterator for Indexer{
+    type Item = Vec<usize>;
+
+    fn next(&mut self) -> Option<Vec<usize>>{
+        let current_index = self.next_index.take()?;
+        let mut next_index = current_index.clone();
+        // Increment the last dimension carrying over to the previous ones, when every
+        // dimension wraps around we are done
+        for dim in (0..self.original_shape.len()).rev(){
+            if next_index[dim] < self.original_shape[dim] - 1{
+                next_index[dim] += 1;
+                self.next_index = Some(next_index);
+                break;
+            }else{
+                next_index[dim] = 0;
             }
         }
-        Some(self.current_index.as_slice())
+        Some(current_index)
     }
 }
 
 #[cfg(test)]
 mod indexing_tests {
     use super::*;
-    use crate::tape::*;
-    use crate::tensor_backends::NdArray;
 
     #[test]
     fn index_test() {
         let k: &[usize] = &[2, 3];
         let mut indexer: Indexer = Indexer::from(k);
-        assert_eq!(indexer.next(), Some([0usize,0].as_ref()));
-        assert_eq!(indexer.next(), Some([0usize,1].as_ref()));
-        assert_eq!(indexer.next(), Some([0usize,2].as_ref()));
-        assert_eq!(indexer.next(), Some([1usize,0].as_ref()));
-        assert_eq!(indexer.next(), Some([1usize,1].as_ref()));
-        assert_eq!(indexer.next(), Some([1usize,2].as_ref()));
+        assert_eq!(indexer.next(), Some(vec![0, 0]));
+        assert_eq!(indexer.next(), Some(vec![0, 1]));
+        assert_eq!(indexer.next(), Some(vec![0, 2]));
+        assert_eq!(indexer.next(), Some(vec![1, 0]));
+        assert_eq!(indexer.next(), Some(vec![1, 1]));
+        assert_eq!(indexer.next(), Some(vec![1, 2]));
         assert_eq!(indexer.next(), None);
         assert_eq!(indexer.next(), None);
     }
-}
 
+    #[test]
+    fn index_high_rank_test() {
+        let shape: &[usize] = &[2, 1, 3, 2, 2];
+        let indices: Vec<Vec<usize>> = Indexer::from(shape).collect();
+        assert_eq!(indices.len(), 24);
+        assert_eq!(indices[1], vec![0, 0, 0, 0, 1]);
+        assert_eq!(indices[23], vec![1, 0, 2, 1, 1]);
+    }
+
+    #[test]
+    fn index_degenerate_shapes_test() {
+        let scalar: &[usize] = &[];
+        assert_eq!(Indexer::from(scalar).collect::<Vec<_>>(), vec![Vec::<usize>::new()]);
+        let zero_sized: &[usize] = &[3, 0, 2];
+        assert_eq!(Indexer::from(zero_sized).count(), 0);
+    }
+}
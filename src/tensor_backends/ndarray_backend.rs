@@ -1,22 +1,74 @@
-use ndarray::{arr1, ArrayBase, arr0};
+use ndarray::{arr1, ArrayBase, ArrayView1, Axis, IxDyn, SliceInfo, SliceOrIndex, Zip};
 use crate::tensor_backends::{TensorBackend, NdArray};
+use crate::tensor_backends::slicing::{self, SliceElem};
+use crate::random::Generator;
+use ndarray_rand::RandomExt;
+use ndarray_rand::rand::Rng;
+use ndarray_rand::rand::seq::SliceRandom;
+use ndarray_rand::rand_distr::{Distribution, Exp1, Normal, StandardNormal, Uniform};
 
-mod matmul2d;
+mod matmul;
+
+/// Standard normal value in [low, high], by rejection from the proposal of "Simulation of
+/// truncated normal variables" (Robert, 1995) best suited to the interval, so that every
+/// proposal is accepted with a probability of at least about 1/3, even far in the tails
+fn standard_truncated_normal(low: f64, high: f64, rng: &mut Generator) -> f64 {
+    if high < 0. {
+        return -standard_truncated_normal(-high, -low, rng);
+    }
+    let uniform = |rng: &mut Generator| low + (high - low) * rng.gen::<f64>();
+    if low <= 0. {
+        if high - low < (2. * std::f64::consts::PI).sqrt() {
+            // The density is at most 1 in the interval, at 0
+            loop {
+                let value = uniform(rng);
+                if rng.gen::<f64>() < (-value * value / 2.).exp() {
+                    return value;
+                }
+            }
+        }
+        loop {
+            let value: f64 = StandardNormal.sample(rng);
+            if (low..=high).contains(&value) {
+                return value;
+            }
+        }
+    }
+    if (high - low) * (high + low) / 2. <= 1. {
+        // The density is highest at low
+        loop {
+            let value = uniform(rng);
+            if rng.gen::<f64>() < ((low * low - value * value) / 2.).exp() {
+                return value;
+            }
+        }
+    }
+    // Exponential proposal shifted to low, with the rate maximizing the acceptance
+    let rate = (low + (low * low + 4.).sqrt()) / 2.;
+    loop {
+        let exponential: f64 = Exp1.sample(rng);
+        let value = low + exponential / rate;
+        if value <= high && rng.gen::<f64>() < (-(value - rate) * (value - rate) / 2.).exp() {
+            return value;
+        }
+    }
+}
 
 impl TensorBackend for NdArray {
     fn from_slice(slice: &[f32]) -> Self {
         Self(arr1(slice).into_dyn())
     }
 
-    fn zeros(shape: &[usize]) -> Self {
-        Self(ArrayBase::zeros(shape).into_dyn())
+    fn from_shape_vec(shape: &[usize], values: Vec<f32>) -> Self {
+        let len = values.len();
+        match ndarray::Array::from_shape_vec(IxDyn(shape), values) {
+            Ok(array) => Self(array),
+            Err(_) => panic!("{} values do not fit shape {:?}", len, shape),
+        }
     }
 
-    fn rand(shape: &[usize]) -> Self {
-        use ndarray_rand::rand_distr::Uniform;
-        use ndarray_rand::RandomExt;
-        let dist = Uniform::new(0., 10.);
-        Self(ndarray::Array::random(shape, dist).into_dyn())
+    fn zeros(shape: &[usize]) -> Self {
+        Self(ArrayBase::zeros(shape).into_dyn())
     }
 
     fn zeros_like(other: &Self) -> Self {
@@ -28,8 +80,48 @@ impl TensorBackend for NdArray {
         Self::from_slice(&[])
     }
 
+    fn rand_uniform(shape: &[usize], low: f32, high: f32, rng: &mut Generator) -> Self {
+        assert!(low < high, "Uniform needs low < high, got [{}, {})", low, high);
+        Self(ndarray::Array::random_using(shape, Uniform::new(low, high), rng).into_dyn())
+    }
+
+    fn rand_normal(shape: &[usize], mean: f32, std: f32, rng: &mut Generator) -> Self {
+        let dist = Normal::new(mean, std).unwrap_or_else(|_| panic!("Invalid standard deviation {}", std));
+        Self(ndarray::Array::random_using(shape, dist, rng).into_dyn())
+    }
+
+    fn rand_truncated_normal(shape: &[usize], mean: f32, std: f32, low: f32, high: f32, rng: &mut Generator) -> Self {
+        assert!(low < high, "Truncated normal needs low < high, got [{}, {}]", low, high);
+        assert!(std > 0., "Truncated normal needs a positive standard deviation, got {}", std);
+        let standard_low = (low as f64 - mean as f64) / std as f64;
+        let standard_high = (high as f64 - mean as f64) / std as f64;
+        let len = shape.iter().product();
+        let values = (0..len)
+            .map(|_i| {
+                let value = mean as f64 + std as f64 * standard_truncated_normal(standard_low, standard_high, rng);
+                // Rounding to f32 may step just outside of the interval
+                (value as f32).max(low).min(high)
+            })
+            .collect();
+        Self::from_shape_vec(shape, values)
+    }
+
+    fn rand_bernoulli(shape: &[usize], p: f32, rng: &mut Generator) -> Self {
+        assert!((0. ..=1.).contains(&p), "Bernoulli probability {} is not in [0, 1]", p);
+        let len = shape.iter().product();
+        let values = (0..len).map(|_i| if rng.gen::<f32>() < p { 1. } else { 0. }).collect();
+        Self::from_shape_vec(shape, values)
+    }
+
+    fn rand_permutation(n: usize, rng: &mut Generator) -> Self {
+        assert!(n <= 1 << 24, "A permutation of {} values can not be stored exactly in f32", n);
+        let mut values: Vec<f32> = (0..n).map(|i| i as f32).collect();
+        values.shuffle(rng);
+        Self::from_shape_vec(&[n], values)
+    }
+
     fn is_empty(&self) -> bool {
-        self.shape() == &[] || self.shape() == &[0]
+        self.shape().is_empty() || self.shape() == [0]
     }
 
 
@@ -37,18 +129,41 @@ impl TensorBackend for NdArray {
         self.0.fill(value);
     }
 
+    fn to_vec(&self) -> Vec<f32> {
+        self.0.iter().cloned().collect()
+    }
+
     fn t(&mut self) {
         self.0.swap_axes(0, 1);
     }
 
     fn reshape(&mut self, shape: &[usize]) {
-        self.0 = self.0.clone().into_shape(shape).expect("Invalid shape");
+        // into_shape only works on contiguous data, which may not be the case after a transpose
+        self.0 = self.0.as_standard_layout().into_owned().into_shape(shape).expect("Invalid shape");
     }
 
     fn shape(&self) -> &[usize] {
         self.0.shape()
     }
 
+    fn permute(&self, axes: &[usize]) -> Self {
+        assert_eq!(axes.len(), self.shape().len(), "Permutation {:?} does not match shape {:?}", axes, self.shape());
+        Self(self.0.clone().permuted_axes(axes))
+    }
+
+    fn transpose(&self, axis_a: usize, axis_b: usize) -> Self {
+        let mut transposed = self.0.clone();
+        transposed.swap_axes(axis_a, axis_b);
+        Self(transposed)
+    }
+
+    fn broadcast_to(&self, shape: &[usize]) -> Self {
+        let broadcast = self.0.broadcast(shape).unwrap_or_else(|| {
+            panic!("Can not broadcast shape {:?} to {:?}", self.shape(), shape)
+        });
+        Self(broadcast.to_owned())
+    }
+
     fn add(&self, rhs: &Self) -> Self {
         assert_eq!(self.shape(), rhs.shape(), "Can only add elements of same shape");
         Self(&self.0 + &rhs.0)
@@ -63,6 +178,11 @@ impl TensorBackend for NdArray {
         Self(&self.0*&rhs.0)
     }
 
+    fn div(&self, rhs: &Self) -> Self {
+        assert_eq!(self.shape(), rhs.shape(), "Can only div elements of same shape");
+        Self(&self.0 / &rhs.0)
+    }
+
     fn add_scalar(&self, rhs: f32) -> Self {
         Self(&self.0 + rhs)
     }
@@ -76,46 +196,222 @@ impl TensorBackend for NdArray {
         Self(&self.0 * rhs)
     }
 
+    fn div_scalar(&self, rhs: f32) -> Self {
+        Self(&self.0 / rhs)
+    }
+
     fn sum(&self) -> f32 {
         self.0.sum()
     }
 
-    fn matmul2d(&self, rhs: &Self) -> Self {
+    fn matmul(&self, rhs: &Self) -> Self {
         let self_view = self.0.view();
         let other_view = rhs.0.view();
-        Self(matmul2d::mm_ndarray(self_view, other_view))
+        Self(matmul::matmul_ndarray(self_view, other_view))
+    }
+
+    fn sum_axis(&self, axis: usize, keepdim: bool) -> Self {
+        self.reduce_axis(axis, keepdim, |lane| lane.sum())
+    }
+
+    fn mean_axis(&self, axis: usize, keepdim: bool) -> Self {
+        self.reduce_axis(axis, keepdim, |lane| lane.sum() / lane.len() as f32)
+    }
+
+    fn max_axis(&self, axis: usize, keepdim: bool) -> Self {
+        self.reduce_axis(axis, keepdim, |lane| lane.fold(f32::NEG_INFINITY, |acc, x| acc.max(*x)))
+    }
+
+    fn min_axis(&self, axis: usize, keepdim: bool) -> Self {
+        self.reduce_axis(axis, keepdim, |lane| lane.fold(f32::INFINITY, |acc, x| acc.min(*x)))
+    }
+
+    fn prod_axis(&self, axis: usize, keepdim: bool) -> Self {
+        self.reduce_axis(axis, keepdim, |lane| lane.fold(1., |acc, x| acc * x))
+    }
+
+    fn argmax_axis(&self, axis: usize, keepdim: bool) -> Self {
+        self.reduce_axis(axis, keepdim, |lane| {
+            let mut max_index = 0;
+            for (i, x) in lane.iter().enumerate() {
+                if *x > lane[max_index] {
+                    max_index = i;
+                }
+            }
+            max_index as f32
+        })
+    }
+
+    fn exp(&self) -> Self {
+        Self(self.0.mapv(f32::exp))
+    }
+
+    fn log(&self) -> Self {
+        Self(self.0.mapv(f32::ln))
+    }
+
+    fn sqrt(&self) -> Self {
+        Self(self.0.mapv(f32::sqrt))
+    }
+
+    fn pow_scalar(&self, exponent: f32) -> Self {
+        Self(self.0.mapv(|x| x.powf(exponent)))
+    }
+
+    fn abs(&self) -> Self {
+        Self(self.0.mapv(f32::abs))
+    }
+
+    fn neg(&self) -> Self {
+        Self(self.0.mapv(|x| -x))
+    }
+
+    fn sin(&self) -> Self {
+        Self(self.0.mapv(f32::sin))
+    }
+
+    fn cos(&self) -> Self {
+        Self(self.0.mapv(f32::cos))
+    }
+
+    fn tanh(&self) -> Self {
+        Self(self.0.mapv(f32::tanh))
+    }
+
+    fn sigmoid(&self) -> Self {
+        Self(self.0.mapv(|x| {
+            if x >= 0. {
+                1. / (1. + (-x).exp())
+            } else {
+                let exp = x.exp();
+                exp / (1. + exp)
+            }
+        }))
+    }
+
+    fn softplus(&self) -> Self {
+        Self(self.0.mapv(|x| x.max(0.) + (-x.abs()).exp().ln_1p()))
+    }
+
+    fn clamp(&self, min: f32, max: f32) -> Self {
+        Self(self.0.mapv(|x| x.max(min).min(max)))
+    }
+
+    fn reciprocal(&self) -> Self {
+        Self(self.0.mapv(f32::recip))
+    }
+
+    fn square(&self) -> Self {
+        Self(self.0.mapv(|x| x * x))
     }
 
     fn map_inplace<F>(&mut self, f: F) where F: FnMut(&mut f32) {
         self.0.map_inplace(f);
     }
 
+    fn zip_map<F>(&self, rhs: &Self, mut f: F) -> Self where F: FnMut(f32, f32) -> f32 {
+        assert_eq!(self.shape(), rhs.shape(), "Can only zip elements of same shape");
+        let mut result = self.0.clone();
+        Zip::from(&mut result).and(&rhs.0).apply(|left, right| {
+            *left = f(*left, *right);
+        });
+        Self(result)
+    }
+
+
+    fn cat(tensors: &[&Self], axis: usize) -> Self {
+        let views: Vec<_> = tensors.iter().map(|tensor| tensor.0.view()).collect();
+        let joined = ndarray::stack(Axis(axis), &views)
+            .unwrap_or_else(|err| panic!("Can not concatenate along axis {}: {}", axis, err));
+        Self(joined)
+    }
+
+    fn stack(tensors: &[&Self], axis: usize) -> Self {
+        let views: Vec<_> = tensors.iter().map(|tensor| tensor.0.view().insert_axis(Axis(axis))).collect();
+        let joined = ndarray::stack(Axis(axis), &views)
+            .unwrap_or_else(|err| panic!("Can not stack along axis {}: {}", axis, err));
+        Self(joined)
+    }
+
+    fn split(&self, sizes: &[usize], axis: usize) -> Vec<Self> {
+        assert_eq!(sizes.iter().sum::<usize>(), self.shape()[axis], "Split sizes {:?} do not add up to the length of axis {} of {:?}", sizes, axis, self.shape());
+        let mut start = 0;
+        sizes.iter().map(|size| {
+            let range = SliceElem::range(start as isize, (start + size) as isize);
+            start += size;
+            self.slice(&slicing::along_axis(axis, range))
+        }).collect()
+    }
+
+    fn slice(&self, info: &[SliceElem]) -> Self {
+        let (ndarray_info, new_axes) = self.ndarray_slice_info(info);
+        let mut sliced = self.0.slice(ndarray_info.as_ref()).to_owned();
+        for axis in new_axes {
+            sliced = sliced.insert_axis(Axis(axis));
+        }
+        Self(sliced)
+    }
+
+    fn slice_add_assign(&mut self, info: &[SliceElem], rhs: &Self) {
+        let (ndarray_info, _new_axes) = self.ndarray_slice_info(info);
+        let mut view = self.0.slice_mut(ndarray_info.as_ref());
+        // New axes have length 1, so dropping them is just a reshape
+        let rhs = rhs.0.as_standard_layout().into_owned()
+            .into_shape(view.shape())
+            .unwrap_or_else(|_| panic!("Shape {:?} does not match the slice shape {:?}", rhs.shape(), view.shape()));
+        view += &rhs;
+    }
 
     fn index(&self, index: &[usize]) -> f32 {
         assert_eq!(index.len(), self.shape().len(), "Needs to index with the same number of dimensions and the Tensor itself.");
-        let indexes = index.to_vec();
-        let inner = &self.0;
-        let indexed_val = match indexes.len(){
-            0 => {panic!("Invalid index: &[]")},
-            1 => inner[[indexes[0]]],
-            2 => inner[[indexes[0], indexes[1]]],
-            3 => inner[[indexes[0], indexes[1], indexes[2]]],
-            _ => panic!("Indexing not implemented for more than 3 dims")
-        };
-        indexed_val
+        self.0[IxDyn(index)]
     }
 
     fn _index_mut(&mut self, index: &[usize]) -> &mut f32 {
-        let indexes = index.to_vec();
-        let inner = &mut self.0;
-        let indexed_val = match indexes.len(){
-            0 => {panic!("Invalid index: &[]")},
-            1 => &mut inner[[indexes[0]]],
-            2 => &mut inner[[indexes[0], indexes[1]]],
-            3 => &mut inner[[indexes[0], indexes[1], indexes[2]]],
-            _ => panic!("Indexing not implemented for more than 3 dims")
-        };
-        indexed_val
+        assert_eq!(index.len(), self.shape().len(), "Needs to index with the same number of dimensions and the Tensor itself.");
+        &mut self.0[IxDyn(index)]
+    }
+}
+
+impl NdArray {
+    /// Converts the slice description into ndarray's, which has no new axes. Those are returned
+    /// separately as the positions they need to be inserted at (in increasing order) in the
+    /// sliced result.
+    fn ndarray_slice_info(&self, info: &[SliceElem]) -> (SliceInfo<Vec<SliceOrIndex>, IxDyn>, Vec<usize>) {
+        let mut indices = vec![];
+        let mut new_axes = vec![];
+        let mut output_axis = 0;
+        for elem in info {
+            match *elem {
+                SliceElem::Range { start, end, step } => {
+                    indices.push(SliceOrIndex::Slice { start, end, step });
+                    output_axis += 1;
+                }
+                SliceElem::Index(index) => indices.push(SliceOrIndex::Index(index)),
+                SliceElem::NewAxis => {
+                    new_axes.push(output_axis);
+                    output_axis += 1;
+                }
+            }
+        }
+        assert!(indices.len() <= self.shape().len(), "Too many indices {:?} for shape {:?}", info, self.shape());
+        while indices.len() < self.shape().len() {
+            indices.push(SliceOrIndex::Slice { start: 0, end: None, step: 1 });
+        }
+        let slice_info = SliceInfo::new(indices).expect("Invalid slice");
+        (slice_info, new_axes)
+    }
+
+    /// Applies `f` to every lane along `axis`, producing one value per lane
+    fn reduce_axis<F>(&self, axis: usize, keepdim: bool, f: F) -> Self where F: FnMut(ArrayView1<f32>) -> f32 {
+        assert!(axis < self.shape().len(), "Axis {} out of bounds for shape {:?}", axis, self.shape());
+        let mut reduced = self.0.map_axis(Axis(axis), f);
+        if keepdim {
+            reduced = reduced.insert_axis(Axis(axis));
+        } else if reduced.ndim() == 0 {
+            reduced = reduced.insert_axis(Axis(0));
+        }
+        Self(reduced)
     }
 }
 
@@ -123,7 +419,9 @@ impl TensorBackend for NdArray {
 #[cfg(test)]
 mod ndarray_backend_tests {
     use crate::tensor_backends::{NdArray, TensorBackend};
-    use super::*;
+    use crate::tensor_backends::slicing::SliceElem;
+    use crate::random::Generator;
+
     #[test]
     fn scalar_add() {
         let left = NdArray::from_slice(&[1., 2., 3.]);
@@ -131,4 +429,191 @@ mod ndarray_backend_tests {
         assert_eq!(NdArray::from_slice(&[3., 4., 5.]), left.add_scalar(right));
         assert_eq!(left, NdArray::from_slice(&[1., 2., 3.]));
     }
+
+    #[test]
+    fn axis_reductions() {
+        let mut data = NdArray::from_slice(&[1., 5., 3., 4., 2., 6.]);
+        data.reshape(&[2, 3]);
+
+        let mut expected = NdArray::from_slice(&[5., 7., 9.]);
+        assert_eq!(data.sum_axis(0, false), expected);
+        expected.reshape(&[1, 3]);
+        assert_eq!(data.sum_axis(0, true), expected);
+
+        assert_eq!(data.mean_axis(1, false), NdArray::from_slice(&[3., 4.]));
+        assert_eq!(data.max_axis(1, false), NdArray::from_slice(&[5., 6.]));
+        assert_eq!(data.min_axis(0, false), NdArray::from_slice(&[1., 2., 3.]));
+        assert_eq!(data.prod_axis(1, false), NdArray::from_slice(&[15., 48.]));
+        assert_eq!(data.argmax_axis(1, false), NdArray::from_slice(&[1., 2.]));
+
+        let mut expected = NdArray::from_slice(&[1., 2.]);
+        expected.reshape(&[2, 1]);
+        assert_eq!(data.argmax_axis(1, true), expected);
+
+        // Reducing a rank 1 Tensor gives shape [1], the same as sum
+        let ties = NdArray::from_slice(&[2., 7., 7.]);
+        assert_eq!(ties.max_axis(0, false), NdArray::from_slice(&[7.]));
+        assert_eq!(ties.argmax_axis(0, false), NdArray::from_slice(&[1.]));
+    }
+
+    #[test]
+    fn slicing() {
+        let mut data = NdArray::from_slice(&(0..12).map(|x| x as f32).collect::<Vec<f32>>());
+        data.reshape(&[3, 4]);
+
+        // x[:, 1:3]
+        let mut expected = NdArray::from_slice(&[1., 2., 5., 6., 9., 10.]);
+        expected.reshape(&[3, 2]);
+        assert_eq!(data.slice(&[SliceElem::full(), (1..3).into()]), expected);
+
+        // x[-1]
+        assert_eq!(data.slice(&[SliceElem::Index(-1)]), NdArray::from_slice(&[8., 9., 10., 11.]));
+
+        // x[::2, None, -1]
+        let mut expected = NdArray::from_slice(&[3., 11.]);
+        expected.reshape(&[2, 1]);
+        let info = [SliceElem::full().step_by(2), SliceElem::NewAxis, SliceElem::Index(-1)];
+        assert_eq!(data.slice(&info), expected);
+
+        let mut grad = NdArray::zeros(&[3, 4]);
+        grad.slice_add_assign(&info, &NdArray::from_slice(&[1., 2.]).slice(&[SliceElem::full(), SliceElem::NewAxis]));
+        grad.slice_add_assign(&info, &expected);
+        let mut expected = NdArray::zeros(&[3, 4]);
+        *expected._index_mut(&[0, 3]) = 4.;
+        *expected._index_mut(&[2, 3]) = 13.;
+        assert_eq!(grad, expected);
+    }
+
+    #[test]
+    fn join_and_split() {
+        let mut left = NdArray::from_slice(&[1., 2., 3., 4.]);
+        left.reshape(&[2, 2]);
+        let mut right = NdArray::from_slice(&[5., 6.]);
+        right.reshape(&[2, 1]);
+
+        let joined = NdArray::cat(&[&left, &right], 1);
+        let mut expected = NdArray::from_slice(&[1., 2., 5., 3., 4., 6.]);
+        expected.reshape(&[2, 3]);
+        assert_eq!(joined, expected);
+        assert_eq!(joined.split(&[2, 1], 1), vec![left.clone(), right]);
+
+        let stacked = NdArray::stack(&[&left, &left], 0);
+        assert_eq!(stacked.shape(), &[2, 2, 2]);
+        assert_eq!(stacked.slice(&[SliceElem::Index(1)]), left);
+
+        let chunks = NdArray::from_slice(&[1., 2., 3., 4., 5.]).chunk(3, 0);
+        assert_eq!(chunks, vec![NdArray::from_slice(&[1., 2.]), NdArray::from_slice(&[3., 4.]), NdArray::from_slice(&[5.])]);
+    }
+
+    #[test]
+    fn permute_then_reshape() {
+        let mut data = NdArray::from_slice(&(0..6).map(|x| x as f32).collect::<Vec<f32>>());
+        data.reshape(&[1, 2, 3]);
+        let mut permuted = data.permute(&[2, 0, 1]);
+        assert_eq!(permuted.shape(), &[3, 1, 2]);
+        assert_eq!(permuted.index(&[2, 0, 1]), 5.);
+        assert_eq!(permuted, data.transpose(0, 2).transpose(1, 2));
+
+        permuted.reshape(&[6]);
+        assert_eq!(permuted, NdArray::from_slice(&[0., 3., 1., 4., 2., 5.]));
+    }
+
+    #[test]
+    fn matmul_numpy_semantics() {
+        let vector = NdArray::from_slice(&[1., 2.]);
+        let mut matrix = NdArray::from_slice(&[1., 2., 3., 4.]);
+        matrix.reshape(&[2, 2]);
+
+        assert_eq!(vector.matmul(&vector), NdArray::from_slice(&[5.]));
+        assert_eq!(vector.matmul(&matrix), NdArray::from_slice(&[7., 10.]));
+        assert_eq!(matrix.matmul(&vector), NdArray::from_slice(&[5., 11.]));
+
+        // [3, 1, 2] x [2, 2] broadcasts the matrix over the batch
+        let mut batch = NdArray::from_slice(&[1., 0., 0., 1., 1., 1.]);
+        batch.reshape(&[3, 1, 2]);
+        let mut expected = NdArray::from_slice(&[1., 2., 3., 4., 4., 6.]);
+        expected.reshape(&[3, 1, 2]);
+        assert_eq!(batch.matmul(&matrix), expected);
+
+        // [3, 1, 2] x [2] drops the promoted axis
+        let mut expected = NdArray::from_slice(&[1., 2., 3.]);
+        expected.reshape(&[3, 1]);
+        assert_eq!(batch.matmul(&vector), expected);
+
+        // [2] x [3, 2, 1] gives [3, 1]
+        let mut column_batch = batch.clone();
+        column_batch.reshape(&[3, 2, 1]);
+        assert_eq!(vector.matmul(&column_batch), expected);
+    }
+
+    #[test]
+    fn stable_sigmoid_and_softplus() {
+        let data = NdArray::from_slice(&[-100., 0., 100.]);
+        let sigmoid = data.sigmoid();
+        assert!(sigmoid.index(&[0]) < 1e-30);
+        assert_eq!(sigmoid.index(&[1]), 0.5);
+        assert_eq!(sigmoid.index(&[2]), 1.);
+        let softplus = data.softplus();
+        assert!(softplus.index(&[0]) < 1e-30);
+        assert!((softplus.index(&[1]) - 2f32.ln()).abs() < 1e-6);
+        assert_eq!(softplus.index(&[2]), 100.);
+    }
+
+    #[test]
+    fn index_any_rank() {
+        let mut data = NdArray::from_slice(&(0..32).map(|x| x as f32).collect::<Vec<f32>>());
+        data.reshape(&[2, 2, 2, 2, 2]);
+        assert_eq!(data.index(&[1, 0, 1, 1, 0]), 22.);
+        *data._index_mut(&[0, 1, 0, 0, 1]) = -1.;
+        assert_eq!(data.index(&[0, 1, 0, 0, 1]), -1.);
+
+        let mut scalar = NdArray::from_slice(&[4.]);
+        scalar.reshape(&[]);
+        assert_eq!(scalar.index(&[]), 4.);
+    }
+
+    #[test]
+    fn truncated_normal_far_in_the_tail() {
+        let mut rng = Generator::new(0);
+        let in_range = |values: NdArray, low: f32, high: f32| values.to_vec().iter().all(|x| (low..=high).contains(x));
+        assert!(in_range(NdArray::rand_truncated_normal(&[1000], 0., 1., 50., 51., &mut rng), 50., 51.));
+        assert!(in_range(NdArray::rand_truncated_normal(&[1000], 1., 2., -101., -99., &mut rng), -101., -99.));
+        assert!(in_range(NdArray::rand_truncated_normal(&[1000], 0., 1., 8., 8.001, &mut rng), 8., 8.001));
+        // The mean of a standard normal above 2 is pdf(2) / (1 - cdf(2))
+        let tail = NdArray::rand_truncated_normal(&[10000], 0., 1., 2., f32::INFINITY, &mut rng).to_vec();
+        let mean = tail.iter().sum::<f32>() / 10000.;
+        assert!(tail.iter().all(|x| *x >= 2.) && (mean - 2.373_2).abs() < 0.02, "{}", mean);
+    }
+
+    #[test]
+    fn seeded_random_constructors() {
+        let sample = |seed| {
+            let mut rng = Generator::new(seed);
+            vec![
+                NdArray::rand_uniform(&[2, 3], -1., 1., &mut rng),
+                NdArray::rand_normal(&[4], 0., 1., &mut rng),
+                NdArray::rand_truncated_normal(&[4], 0., 1., -0.5, 0.5, &mut rng),
+                NdArray::rand_bernoulli(&[5], 0.5, &mut rng),
+                NdArray::rand_permutation(6, &mut rng),
+            ]
+        };
+        assert_eq!(sample(3), sample(3));
+        assert_ne!(sample(3), sample(4));
+
+        let mut rng = Generator::new(0);
+        let uniform = NdArray::rand_uniform(&[1000], 2., 3., &mut rng).to_vec();
+        assert!(uniform.iter().all(|x| (2. ..3.).contains(x)));
+        let normal = NdArray::rand_normal(&[10000], 1., 2., &mut rng).to_vec();
+        let mean = normal.iter().sum::<f32>() / 10000.;
+        let var = normal.iter().map(|x| (x - mean).powi(2)).sum::<f32>() / 10000.;
+        assert!((mean - 1.).abs() < 0.1 && (var.sqrt() - 2.).abs() < 0.1, "{} {}", mean, var);
+        let truncated = NdArray::rand_truncated_normal(&[1000], 0., 1., -0.5, 0.5, &mut rng).to_vec();
+        assert!(truncated.iter().all(|x| (-0.5..=0.5).contains(x)));
+        let bernoulli = NdArray::rand_bernoulli(&[1000], 0.25, &mut rng).to_vec();
+        let ones = bernoulli.iter().filter(|x| **x == 1.).count();
+        assert!(bernoulli.iter().all(|x| *x == 0. || *x == 1.) && (200..300).contains(&ones), "{}", ones);
+        let mut permutation = NdArray::rand_permutation(10, &mut rng).to_vec();
+        permutation.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(permutation, (0..10).map(|i| i as f32).collect::<Vec<f32>>());
+    }
 }
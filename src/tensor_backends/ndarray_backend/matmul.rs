@@ -0,0 +1,87 @@
+use ndarray::prelude::*;
+use crate::tensor_backends::indexing::Indexer;
+
+/// Matrix product following NumPy's matmul semantics:
+/// - Rank 2 operands are multiplied as matrices
+/// - A rank 1 left operand is treated as a [1, n] matrix and a rank 1 right operand as a [n, 1]
+///   matrix, the added axis is removed from the result. Two rank 1 operands give shape [1].
+/// - Higher rank operands are stacks of matrices in the last two axes, the leading (batch) axes
+///   are broadcast against each other
+pub fn matmul_ndarray(
+    m1: ndarray::ArrayView<f32, IxDyn>,
+    m2: ndarray::ArrayView<f32, IxDyn>,
+) -> ndarray::Array<f32, IxDyn> {
+    let shape_1 = m1.shape().to_vec();
+    let shape_2 = m2.shape().to_vec();
+    assert!(
+        !shape_1.is_empty() && !shape_2.is_empty(),
+        "Can not multiply tensors of rank 0, but got {:?} and {:?}",
+        shape_1,
+        shape_2
+    );
+    let m1 = if shape_1.len() == 1 { m1.insert_axis(Axis(0)) } else { m1 };
+    let m2 = if shape_2.len() == 1 { m2.insert_axis(Axis(1)) } else { m2 };
+
+    let (batch_1, matrix_1) = m1.shape().split_at(m1.ndim() - 2);
+    let (batch_2, matrix_2) = m2.shape().split_at(m2.ndim() - 2);
+    assert_eq!(
+        matrix_1[1],
+        matrix_2[0],
+        "Tensor shapes dont match for multiplication: {:?} and {:?}",
+        shape_1,
+        shape_2
+    );
+    let batch = broadcast_shapes(batch_1, batch_2).unwrap_or_else(|| {
+        panic!("Can not broadcast batch dimensions of {:?} and {:?}", shape_1, shape_2)
+    });
+    let (rows, inner, cols) = (matrix_1[0], matrix_1[1], matrix_2[1]);
+
+    let m1 = m1.broadcast([batch.as_slice(), &[rows, inner]].concat()).unwrap();
+    let m2 = m2.broadcast([batch.as_slice(), &[inner, cols]].concat()).unwrap();
+    let mut result = Array::zeros([batch.as_slice(), &[rows, cols]].concat());
+    for batch_index in Indexer::from(batch.as_slice()) {
+        let matrix_1 = index_matrix(&m1, &batch_index);
+        let matrix_2 = index_matrix(&m2, &batch_index);
+        let mut output = result.view_mut();
+        for index in &batch_index {
+            output = output.index_axis_move(Axis(0), *index);
+        }
+        output.assign(&matrix_1.dot(&matrix_2));
+    }
+
+    if shape_2.len() == 1 {
+        let cols_axis = result.ndim() - 1;
+        result = result.index_axis_move(Axis(cols_axis), 0);
+    }
+    if shape_1.len() == 1 {
+        // The rows axis is the last one if the columns one was already removed
+        let rows_axis = result.ndim() - if shape_2.len() == 1 { 1 } else { 2 };
+        result = result.index_axis_move(Axis(rows_axis), 0);
+    }
+    if result.ndim() == 0 {
+        result = result.insert_axis(Axis(0));
+    }
+    result
+}
+
+/// Takes the matrix stored in the last two axes at the given batch index
+fn index_matrix<'a>(batched: &ArrayView<'a, f32, IxDyn>, batch_index: &[usize]) -> ArrayView2<'a, f32> {
+    let mut matrix = batched.clone();
+    for index in batch_index {
+        matrix = matrix.index_axis_move(Axis(0), *index);
+    }
+    matrix.into_dimensionality().unwrap()
+}
+
+/// Shape both shapes broadcast to following NumPy rules, None if they are incompatible
+pub fn broadcast_shapes(shape_1: &[usize], shape_2: &[usize]) -> Option<Vec<usize>> {
+    let ndim = shape_1.len().max(shape_2.len());
+    let padded = |shape: &[usize]| [vec![1; ndim - shape.len()], shape.to_vec()].concat();
+    padded(shape_1).into_iter().zip(padded(shape_2)).map(|(dim_1, dim_2)| {
+        match (dim_1, dim_2) {
+            (1, dim) | (dim, 1) => Some(dim),
+            (dim_1, dim_2) if dim_1 == dim_2 => Some(dim_1),
+            _ => None,
+        }
+    }).collect()
+}
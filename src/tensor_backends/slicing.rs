@@ -0,0 +1,84 @@
+use std::ops::{Range, RangeFrom, RangeFull, RangeTo};
+
+/// Describes how a single axis is sliced. A slice is a list of these, one per axis of the
+/// source Tensor (except for `NewAxis` which does not consume one). Axes not covered by the list
+/// are taken whole.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SliceElem {
+    /// Elements from `start` (inclusive) to `end` (exclusive) every `step` elements.
+    /// Negative `start` or `end` count from the back of the axis and `end: None` goes until the
+    /// end of the axis. A negative step first selects the range and then walks it backwards.
+    Range { start: isize, end: Option<isize>, step: isize },
+    /// Picks a single element, removing the axis. Negative values count from the back.
+    Index(isize),
+    /// Inserts a new axis of length 1
+    NewAxis,
+}
+
+impl SliceElem {
+    /// The whole axis, the same as `:` in NumPy
+    pub fn full() -> Self {
+        SliceElem::Range { start: 0, end: None, step: 1 }
+    }
+
+    pub fn range(start: isize, end: isize) -> Self {
+        SliceElem::Range { start, end: Some(end), step: 1 }
+    }
+
+    /// Changes the step of a range, panics if called on an index or new axis
+    pub fn step_by(self, step: isize) -> Self {
+        match self {
+            SliceElem::Range { start, end, .. } => SliceElem::Range { start, end, step },
+            other => panic!("Only ranges have a step, got {:?}", other),
+        }
+    }
+}
+
+/// Slice which applies `elem` to `axis` and takes all the previous axes whole
+pub fn along_axis(axis: usize, elem: SliceElem) -> Vec<SliceElem> {
+    let mut info = vec![SliceElem::full(); axis];
+    info.push(elem);
+    info
+}
+
+/// Sizes of the pieces `chunk` splits an axis of length `len` into: ceil(len / chunks) each,
+/// except possibly for the last one
+pub fn chunk_sizes(len: usize, chunks: usize) -> Vec<usize> {
+    assert!(chunks > 0, "Can not split into 0 chunks");
+    let chunk_size = len.div_ceil(chunks).max(1);
+    let mut sizes = vec![chunk_size; len / chunk_size];
+    if !len.is_multiple_of(chunk_size) {
+        sizes.push(len % chunk_size);
+    }
+    sizes
+}
+
+impl From<Range<isize>> for SliceElem {
+    fn from(range: Range<isize>) -> Self {
+        SliceElem::range(range.start, range.end)
+    }
+}
+
+impl From<RangeFrom<isize>> for SliceElem {
+    fn from(range: RangeFrom<isize>) -> Self {
+        SliceElem::Range { start: range.start, end: None, step: 1 }
+    }
+}
+
+impl From<RangeTo<isize>> for SliceElem {
+    fn from(range: RangeTo<isize>) -> Self {
+        SliceElem::Range { start: 0, end: Some(range.end), step: 1 }
+    }
+}
+
+impl From<RangeFull> for SliceElem {
+    fn from(_: RangeFull) -> Self {
+        SliceElem::full()
+    }
+}
+
+impl From<isize> for SliceElem {
+    fn from(index: isize) -> Self {
+        SliceElem::Index(index)
+    }
+}